@@ -0,0 +1,27 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rusty_chat::common::protocol::{
+    message::{Message, MessageRef},
+    packet::{server::Chat, Packet},
+    serializable::Serializable,
+};
+use std::hint::black_box;
+
+fn parse_chat(c: &mut Criterion) {
+    let message = Chat::new(String::from("Kitt3120"), "⚡".repeat(16 * 1024)).to_message();
+    let bytes = message.as_bytes();
+
+    let mut group = c.benchmark_group("parse_server_chat");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_function("Message::from_bytes", |b| {
+        b.iter(|| Message::from_bytes(black_box(&bytes)))
+    });
+    group.bench_function("MessageRef::from_bytes", |b| {
+        b.iter(|| MessageRef::from_bytes(black_box(&bytes)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse_chat);
+criterion_main!(benches);
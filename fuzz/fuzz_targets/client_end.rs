@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_chat::common::protocol::{packet::client::End, serializable::Serializable};
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(value) = End::from_bytes(bytes) {
+        let parsed_value = End::from_bytes(&value.as_bytes())
+            .unwrap_or_else(|err| panic!("Failed to reparse {:?}: {}", value, err));
+
+        assert_eq!(value, parsed_value);
+    }
+});
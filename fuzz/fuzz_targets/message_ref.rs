@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_chat::common::protocol::{
+    message::{Message, MessageRef},
+    serializable::Serializable,
+};
+
+fuzz_target!(|bytes: &[u8]| {
+    let owned = Message::from_bytes(bytes);
+    let borrowed = MessageRef::from_bytes(bytes).map(MessageRef::into_owned);
+
+    assert_eq!(owned, borrowed);
+});
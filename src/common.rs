@@ -1,3 +1,29 @@
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod authorized_keys;
+pub mod chat_client;
+pub mod command;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+#[cfg(feature = "e2e")]
+pub mod e2e;
+pub mod federation;
+pub mod file_transfer;
+pub mod http;
+pub mod irc;
 pub mod message_stream;
+pub mod moderation;
+pub mod offline_queue;
+pub mod peer_credentials;
+pub mod permissions;
+pub mod plugin;
 pub mod protocol;
+pub mod rate_limit;
+pub mod server_state;
 pub mod threading;
+#[cfg(unix)]
+pub mod unix_socket;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
+#[cfg(feature = "websocket")]
+pub mod websocket;
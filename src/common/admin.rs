@@ -0,0 +1,627 @@
+use std::{
+    io::BufReader,
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::common::{
+    http::{error::HttpError, HttpRequest, HttpResponse},
+    protocol::{
+        error::UsernameError,
+        packet::{
+            client::{Ban, Kick},
+            server::Chat,
+            BanTarget, Permission, PermissionSet,
+        },
+    },
+    server_state::{ServerState, MAIN_ROOM},
+};
+
+#[derive(Deserialize)]
+struct PostMessage {
+    username: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct PostKick {
+    username: String,
+    reason: Option<String>,
+}
+
+// Exactly one of username and address
+#[derive(Deserialize)]
+struct PostBan {
+    username: Option<String>,
+    address: Option<IpAddr>,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+}
+
+// A connection that stays silent longer than this gives its thread back
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Requests handled at once, the ones beyond get a 503 instead of a thread
+pub const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+// What the holder of a token may do: /kick and /bans need Kick and Ban,
+// and /users only shows addresses with SeeAddresses
+#[derive(Clone, Debug)]
+pub struct AdminToken {
+    pub token: String,
+    pub permissions: PermissionSet,
+}
+
+impl AdminToken {
+    pub fn new(token: String, permissions: PermissionSet) -> AdminToken {
+        AdminToken { token, permissions }
+    }
+}
+
+// Works on the same ServerState the listeners admit sessions into.
+// Everything but /health needs "Authorization: Bearer <token>" with one of the configured tokens.
+#[derive(Clone, Debug)]
+pub struct AdminApi {
+    state: Arc<Mutex<ServerState>>,
+    tokens: Vec<AdminToken>,
+    timeout: Duration,
+    max_concurrent_requests: usize,
+    active_requests: Arc<AtomicUsize>,
+}
+
+// Hands the request slot back however the handling thread ends
+struct RequestSlot(Arc<AtomicUsize>);
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl AdminApi {
+    pub fn new(state: Arc<Mutex<ServerState>>, tokens: Vec<AdminToken>) -> AdminApi {
+        AdminApi {
+            state,
+            tokens,
+            timeout: REQUEST_TIMEOUT,
+            max_concurrent_requests: MAX_CONCURRENT_REQUESTS,
+            active_requests: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_max_concurrent_requests(&mut self, max_concurrent_requests: usize) {
+        self.max_concurrent_requests = max_concurrent_requests;
+    }
+
+    pub fn serve(&self, listener: TcpListener) -> Result<(), HttpError> {
+        for tcp_stream in listener.incoming() {
+            let mut tcp_stream = tcp_stream.map_err(HttpError::IoError)?;
+
+            let active_requests = self.active_requests.fetch_add(1, Ordering::SeqCst);
+            let slot = RequestSlot(self.active_requests.clone());
+            if active_requests >= self.max_concurrent_requests {
+                drop(slot);
+                // Answered right here, the write timeout keeps a stuck client from holding up accept
+                let _ = tcp_stream
+                    .set_write_timeout(Some(self.timeout))
+                    .map_err(HttpError::IoError)
+                    .and_then(|_| {
+                        error_response(503, "Too many concurrent requests")
+                            .write_to(&mut tcp_stream)
+                    });
+                continue;
+            }
+
+            let admin_api = self.clone();
+            thread::spawn(move || {
+                let _slot = slot;
+                admin_api.handle_connection(tcp_stream)
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_connection(&self, tcp_stream: TcpStream) -> Result<(), HttpError> {
+        tcp_stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| tcp_stream.set_write_timeout(Some(self.timeout)))
+            .map_err(HttpError::IoError)?;
+
+        let mut writer = tcp_stream.try_clone().map_err(HttpError::IoError)?;
+        let mut reader = BufReader::new(tcp_stream);
+
+        let response = match HttpRequest::read_from(&mut reader) {
+            Ok(request) => self.handle(&request),
+            Err(HttpError::BodyTooLarge(limit)) => {
+                error_response(413, &format!("Body exceeds {} bytes", limit))
+            }
+            Err(HttpError::IoError(err)) => return Err(HttpError::IoError(err)),
+            Err(err) => error_response(400, &err.to_string()),
+        };
+
+        response.write_to(&mut writer)
+    }
+
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let path = request.path.split('?').next().unwrap_or_default();
+
+        if path == "/health" {
+            return match request.method.as_str() {
+                "GET" => json_response(200, json!({ "status": "ok" })),
+                _ => error_response(405, "Method not allowed"),
+            };
+        }
+
+        let permissions = match self.authorize(request) {
+            Some(permissions) => permissions,
+            None => return error_response(401, "Missing or invalid API token"),
+        };
+
+        match (request.method.as_str(), path) {
+            ("POST", "/kick") if !permissions.contains(Permission::Kick) => {
+                forbidden(Permission::Kick)
+            }
+            ("POST", "/bans") if !permissions.contains(Permission::Ban) => {
+                forbidden(Permission::Ban)
+            }
+            ("GET", "/users") => self.users(permissions),
+            ("GET", "/rooms") => self.rooms(),
+            ("GET", "/stats") => self.stats(),
+            ("POST", "/messages") => self.post_message(&request.body),
+            ("POST", "/kick") => self.kick(&request.body),
+            ("POST", "/bans") => self.ban(&request.body),
+            (_, "/users" | "/rooms" | "/stats" | "/messages" | "/kick" | "/bans") => {
+                error_response(405, "Method not allowed")
+            }
+            _ => error_response(404, "Not found"),
+        }
+    }
+
+    // The permissions of the token the request carries, None when it has no valid one
+    fn authorize(&self, request: &HttpRequest) -> Option<PermissionSet> {
+        let token = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))?
+            .trim();
+
+        self.tokens
+            .iter()
+            .find(|expected| constant_time_eq(expected.token.as_bytes(), token.as_bytes()))
+            .map(|expected| expected.permissions)
+    }
+
+    fn users(&self, permissions: PermissionSet) -> HttpResponse {
+        let see_addresses = permissions.contains(Permission::SeeAddresses);
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // One row per user, with a session for each device they are connected from
+        let users: Vec<Value> = state
+            .usernames()
+            .into_iter()
+            .map(|username| {
+                let sessions: Vec<Value> = state
+                    .user_sessions(&username)
+                    .into_iter()
+                    .map(|(_, session)| {
+                        let mut row = json!({
+                            "device": session.device,
+                            "connected_seconds": session.connected_at.elapsed().as_secs(),
+                        });
+                        if see_addresses {
+                            row["address"] =
+                                json!(session.address.map(|address| address.to_string()));
+                        }
+
+                        row
+                    })
+                    .collect();
+
+                json!({ "username": username, "sessions": sessions })
+            })
+            .collect();
+
+        json_response(200, json!({ "users": users }))
+    }
+
+    // There is one conversation, listed as a single room so integrations don't have to special-case it
+    fn rooms(&self) -> HttpResponse {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let members = state.usernames();
+
+        json_response(
+            200,
+            json!({ "rooms": [{ "name": MAIN_ROOM, "members": members }] }),
+        )
+    }
+
+    fn stats(&self) -> HttpResponse {
+        let stats = self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stats();
+
+        json_response(
+            200,
+            json!({
+                "connected_users": stats.connected_users,
+                "messages_relayed": stats.messages_relayed,
+                "active_bans": stats.active_bans,
+                "uptime_seconds": stats.uptime_seconds,
+            }),
+        )
+    }
+
+    fn post_message(&self, body: &[u8]) -> HttpResponse {
+        let post: PostMessage = match serde_json::from_slice(body) {
+            Ok(post) => post,
+            Err(err) => return error_response(400, &err.to_string()),
+        };
+
+        if post.message.is_empty() {
+            return error_response(400, "Message must not be empty");
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Bots don't hold a session, but their name still has to be one a user could pick.
+        // Names of connected or registered users are theirs alone, so bots can't impersonate them.
+        let usernames = state.usernames();
+        let username = match state.username_policy().validate(&post.username, &usernames) {
+            Ok(username) if state.is_registered(&username) => {
+                return error_response(409, &UsernameError::Taken(username).to_string())
+            }
+            Ok(username) => username,
+            Err(err @ UsernameError::Taken(_)) => return error_response(409, &err.to_string()),
+            Err(err) => return error_response(400, &err.to_string()),
+        };
+
+        state.relay(Chat::new(username, post.message));
+        json_response(202, json!({ "relayed": true }))
+    }
+
+    fn kick(&self, body: &[u8]) -> HttpResponse {
+        let post: PostKick = match serde_json::from_slice(body) {
+            Ok(post) => post,
+            Err(err) => return error_response(400, &err.to_string()),
+        };
+
+        let kicked = self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .kick(&Kick::new(post.username, post.reason));
+
+        json_response(200, json!({ "kicked": kicked }))
+    }
+
+    fn ban(&self, body: &[u8]) -> HttpResponse {
+        let post: PostBan = match serde_json::from_slice(body) {
+            Ok(post) => post,
+            Err(err) => return error_response(400, &err.to_string()),
+        };
+
+        let target = match (post.username, post.address) {
+            (Some(username), None) => BanTarget::Username(username),
+            (None, Some(address)) => BanTarget::Address(address),
+            _ => return error_response(400, "Ban either a username or an address"),
+        };
+
+        let ban = Ban::new(target, post.expires_at, post.reason);
+        let result = self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ban(ban);
+
+        match result {
+            Ok(closed_sessions) => {
+                json_response(200, json!({ "closed_sessions": closed_sessions }))
+            }
+            Err(err) => error_response(500, &err.to_string()),
+        }
+    }
+}
+
+fn json_response(status: u16, body: Value) -> HttpResponse {
+    HttpResponse::new(
+        status,
+        vec![(
+            String::from("Content-Type"),
+            String::from("application/json"),
+        )],
+        body.to_string().into_bytes(),
+    )
+}
+
+fn error_response(status: u16, error: &str) -> HttpResponse {
+    json_response(status, json!({ "error": error }))
+}
+
+fn forbidden(permission: Permission) -> HttpResponse {
+    error_response(
+        403,
+        &format!("The API token lacks the {} permission", permission),
+    )
+}
+
+// Doesn't stop at the first differing byte, so response times don't leak how much of a token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter()
+        .zip(b)
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::mpsc, time::Instant};
+
+    use crate::common::{
+        authorized_keys::{AuthorizedKey, AuthorizedKeys},
+        moderation::Moderation,
+        peer_credentials::PeerCredentials,
+        permissions::Permissions,
+        protocol::{
+            packet::{server::End, EndReason, Packet},
+            username_policy::UsernamePolicy,
+        },
+    };
+
+    const TOKEN: &str = "secret-token";
+    const READ_ONLY_TOKEN: &str = "read-only-token";
+
+    fn tokens() -> Vec<AdminToken> {
+        vec![
+            AdminToken::new(String::from(TOKEN), PermissionSet::new(&Permission::ALL)),
+            AdminToken::new(String::from(READ_ONLY_TOKEN), PermissionSet::default()),
+        ]
+    }
+
+    fn request(
+        address: &str,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: Value,
+    ) -> HttpResponse {
+        let mut headers = Vec::new();
+        if let Some(token) = token {
+            headers.push((String::from("Authorization"), format!("Bearer {}", token)));
+        }
+        let request = HttpRequest::new(method, path, headers, body.to_string().into_bytes());
+
+        let mut tcp_stream =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        request
+            .write_to(&mut tcp_stream, address)
+            .unwrap_or_else(|err| panic!("Failed to send request: {}", err));
+
+        HttpResponse::read_from(&mut BufReader::new(tcp_stream))
+            .unwrap_or_else(|err| panic!("Failed to read response: {}", err))
+    }
+
+    fn body(response: &HttpResponse) -> Value {
+        serde_json::from_slice(&response.body)
+            .unwrap_or_else(|err| panic!("Failed to parse response body: {}", err))
+    }
+
+    #[test]
+    fn admin_api_drops_silent_connections() {
+        let state = Arc::new(Mutex::new(ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )));
+        let mut admin_api = AdminApi::new(state, tokens());
+        admin_api.set_timeout(Duration::from_millis(50));
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+        let _silent =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let (tcp_stream, _) = listener
+            .accept()
+            .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+
+        assert!(matches!(
+            admin_api.handle_connection(tcp_stream),
+            Err(HttpError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn admin_api_limits_concurrent_requests() {
+        let state = Arc::new(Mutex::new(ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )));
+        let mut admin_api = AdminApi::new(state, tokens());
+        admin_api.set_max_concurrent_requests(1);
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err))
+            .to_string();
+        thread::spawn(move || admin_api.serve(listener));
+
+        // Holds the only slot until it hangs up
+        let silent =
+            TcpStream::connect(&address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        // Answered without reading a request, so one isn't sent into a closing socket
+        let rejected =
+            TcpStream::connect(&address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let rejected = HttpResponse::read_from(&mut BufReader::new(rejected))
+            .unwrap_or_else(|err| panic!("Failed to read response: {}", err));
+        assert_eq!(rejected.status, 503);
+
+        drop(silent);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while request(&address, "GET", "/health", None, Value::Null).status != 200 {
+            assert!(Instant::now() < deadline, "Request slot was never freed");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn admin_api_serves_shared_server_state() {
+        let state = Arc::new(Mutex::new(ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )));
+        let (sender, receiver) = mpsc::channel();
+        let (phone_sender, _phone) = mpsc::channel();
+        {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            for (username, seed) in [("alice", 7), ("bob", 8)] {
+                state
+                    .authorized_keys_mut()
+                    .add(AuthorizedKey::new(
+                        String::from(username),
+                        ed25519_dalek::SigningKey::from_bytes(&[seed; 32])
+                            .verifying_key()
+                            .to_bytes(),
+                        None,
+                    ))
+                    .unwrap_or_else(|err| panic!("Failed to add key: {}", err));
+            }
+            for (device, sender) in [("laptop", sender), ("phone", phone_sender)] {
+                state
+                    .join(
+                        String::from("Alice"),
+                        Some(String::from(device)),
+                        Some(IpAddr::from([192, 0, 2, 7])),
+                        sender,
+                    )
+                    .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err))
+            .to_string();
+        let admin_api = AdminApi::new(state, tokens());
+        thread::spawn(move || admin_api.serve(listener));
+
+        let health = request(&address, "GET", "/health", None, Value::Null);
+        assert_eq!(health.status, 200);
+
+        let unauthorized = request(&address, "GET", "/users", Some("wrong"), Value::Null);
+        assert_eq!(unauthorized.status, 401);
+
+        let users = request(&address, "GET", "/users", Some(TOKEN), Value::Null);
+        let users = body(&users)["users"].clone();
+        assert_eq!(users.as_array().map(Vec::len), Some(1));
+        assert_eq!(users[0]["username"], "Alice");
+        assert_eq!(users[0]["sessions"][0]["device"], "laptop");
+        assert_eq!(users[0]["sessions"][1]["device"], "phone");
+        assert_eq!(users[0]["sessions"][0]["address"], "192.0.2.7");
+
+        let users = request(
+            &address,
+            "GET",
+            "/users",
+            Some(READ_ONLY_TOKEN),
+            Value::Null,
+        );
+        assert_eq!(body(&users)["users"][0]["sessions"][0]["device"], "laptop");
+        assert!(body(&users)["users"][0]["sessions"][0]
+            .get("address")
+            .is_none());
+
+        let posted = request(
+            &address,
+            "POST",
+            "/messages",
+            Some(TOKEN),
+            json!({ "username": "ci-bot", "message": "Build passed" }),
+        );
+        assert_eq!(posted.status, 202);
+
+        let impersonating = request(
+            &address,
+            "POST",
+            "/messages",
+            Some(TOKEN),
+            json!({ "username": "ALICE", "message": "Trust me" }),
+        );
+        assert_eq!(impersonating.status, 409);
+
+        let impersonating_offline = request(
+            &address,
+            "POST",
+            "/messages",
+            Some(TOKEN),
+            json!({ "username": "Bob", "message": "Trust me" }),
+        );
+        assert_eq!(impersonating_offline.status, 409);
+        assert_eq!(
+            receiver.try_recv().ok(),
+            Some(Chat::new(String::from("ci-bot"), String::from("Build passed")).to_message())
+        );
+
+        let forbidden = request(
+            &address,
+            "POST",
+            "/kick",
+            Some(READ_ONLY_TOKEN),
+            json!({ "username": "alice" }),
+        );
+        assert_eq!(forbidden.status, 403);
+
+        let kicked = request(
+            &address,
+            "POST",
+            "/kick",
+            Some(TOKEN),
+            json!({ "username": "alice", "reason": "Bye" }),
+        );
+        assert_eq!(body(&kicked)["kicked"], true);
+        assert_eq!(
+            receiver.try_recv().ok(),
+            Some(End::new(EndReason::Kicked, Some(String::from("Bye"))).to_message())
+        );
+
+        let stats = request(&address, "GET", "/stats", Some(TOKEN), Value::Null);
+        assert_eq!(body(&stats)["connected_users"], 0);
+        assert_eq!(body(&stats)["messages_relayed"], 1);
+
+        let invalid_ban = request(&address, "POST", "/bans", Some(TOKEN), json!({}));
+        assert_eq!(invalid_ban.status, 400);
+    }
+}
@@ -0,0 +1,247 @@
+pub mod error;
+
+use std::{fs, path::PathBuf};
+
+use ed25519_dalek::VerifyingKey;
+
+use self::error::AuthorizedKeysError;
+
+use crate::common::protocol::username_policy::UsernamePolicy;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizedKey {
+    pub username: String,
+    pub public_key: [u8; 32],
+    pub comment: Option<String>,
+}
+
+impl AuthorizedKey {
+    pub fn new(username: String, public_key: [u8; 32], comment: Option<String>) -> AuthorizedKey {
+        AuthorizedKey {
+            username,
+            public_key,
+            comment,
+        }
+    }
+}
+
+// The Ed25519 keys users may log in with, kept like SSH's authorized_keys:
+// one "<username> <hex public key> [comment]" per line, # starts a comment line.
+// A username with at least one key can't log in without one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizedKeys {
+    keys: Vec<AuthorizedKey>,
+    path: Option<PathBuf>,
+}
+
+impl AuthorizedKeys {
+    pub fn new() -> AuthorizedKeys {
+        AuthorizedKeys {
+            keys: Vec::new(),
+            path: None,
+        }
+    }
+
+    // Loads the keys from disk if the file exists and writes them back there on every change
+    pub fn load(path: PathBuf) -> Result<AuthorizedKeys, AuthorizedKeysError> {
+        let keys = match fs::read_to_string(&path) {
+            Ok(text) => parse_authorized_keys(&text)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(AuthorizedKeysError::IoError(err)),
+        };
+
+        Ok(AuthorizedKeys {
+            keys,
+            path: Some(path),
+        })
+    }
+
+    pub fn keys(&self) -> &[AuthorizedKey] {
+        &self.keys
+    }
+
+    pub fn keys_of<'a>(
+        &'a self,
+        username_policy: &'a UsernamePolicy,
+        username: &str,
+    ) -> impl Iterator<Item = &'a AuthorizedKey> {
+        let canonical_username = username_policy.canonicalize(username);
+
+        self.keys
+            .iter()
+            .filter(move |key| username_policy.canonicalize(&key.username) == canonical_username)
+    }
+
+    pub fn requires_key(&self, username_policy: &UsernamePolicy, username: &str) -> bool {
+        self.keys_of(username_policy, username).next().is_some()
+    }
+
+    pub fn is_authorized(
+        &self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        public_key: &[u8; 32],
+    ) -> bool {
+        self.keys_of(username_policy, username)
+            .any(|key| key.public_key == *public_key)
+    }
+
+    pub fn add(&mut self, key: AuthorizedKey) -> Result<(), AuthorizedKeysError> {
+        if VerifyingKey::from_bytes(&key.public_key).is_err() {
+            return Err(AuthorizedKeysError::InvalidKey);
+        }
+
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    // Returns whether the key was registered
+    pub fn remove(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        public_key: &[u8; 32],
+    ) -> Result<bool, AuthorizedKeysError> {
+        let canonical_username = username_policy.canonicalize(username);
+        let key_count = self.keys.len();
+
+        self.keys.retain(|key| {
+            username_policy.canonicalize(&key.username) != canonical_username
+                || key.public_key != *public_key
+        });
+
+        if self.keys.len() == key_count {
+            return Ok(false);
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), AuthorizedKeysError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut text = String::new();
+        for key in &self.keys {
+            text.push_str(&key.username);
+            text.push(' ');
+            key.public_key
+                .iter()
+                .for_each(|byte| text.push_str(&format!("{:02x}", byte)));
+            if let Some(comment) = &key.comment {
+                text.push(' ');
+                text.push_str(comment);
+            }
+            text.push('\n');
+        }
+
+        // Write next to the key file first, so a crash never leaves a truncated file behind
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, text).map_err(AuthorizedKeysError::IoError)?;
+        fs::rename(&temporary_path, path).map_err(AuthorizedKeysError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl Default for AuthorizedKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_authorized_keys(text: &str) -> Result<Vec<AuthorizedKey>, AuthorizedKeysError> {
+    let mut keys = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parse_error =
+            |message: &str| AuthorizedKeysError::ParseError(index + 1, message.to_owned());
+
+        let mut parts = line.splitn(3, char::is_whitespace);
+        let (username, public_key) = match (parts.next(), parts.next()) {
+            (Some(username), Some(public_key)) => (username, public_key),
+            _ => return Err(parse_error("Expected a username and a public key")),
+        };
+        let comment = parts
+            .next()
+            .map(str::trim)
+            .filter(|comment| !comment.is_empty())
+            .map(str::to_owned);
+
+        let public_key =
+            parse_public_key(public_key).ok_or_else(|| parse_error("Invalid public key"))?;
+        if VerifyingKey::from_bytes(&public_key).is_err() {
+            return Err(parse_error("Not an Ed25519 public key"));
+        }
+
+        keys.push(AuthorizedKey::new(username.to_owned(), public_key, comment));
+    }
+
+    Ok(keys)
+}
+
+fn parse_public_key(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut public_key = [0; 32];
+    for (byte, digits) in public_key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    Some(public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn authorized_keys_persist_and_match_canonical_usernames() {
+        let path =
+            std::env::temp_dir().join(format!("rusty_chat_authorized_keys_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let username_policy = UsernamePolicy::new();
+        let public_key = SigningKey::from_bytes(&[7; 32]).verifying_key().to_bytes();
+
+        let mut authorized_keys = AuthorizedKeys::load(path.clone())
+            .unwrap_or_else(|err| panic!("Failed to load authorized keys: {}", err));
+        authorized_keys
+            .add(AuthorizedKey::new(
+                String::from("Ops"),
+                public_key,
+                Some(String::from("laptop")),
+            ))
+            .unwrap_or_else(|err| panic!("Failed to add key: {}", err));
+
+        let reloaded = AuthorizedKeys::load(path.clone())
+            .unwrap_or_else(|err| panic!("Failed to reload authorized keys: {}", err));
+        assert_eq!(reloaded.keys(), authorized_keys.keys());
+        assert!(reloaded.is_authorized(&username_policy, "ops", &public_key));
+        assert!(!reloaded.is_authorized(&username_policy, "ops", &[0; 32]));
+        assert!(!reloaded.requires_key(&username_policy, "Kitt3120"));
+
+        assert!(matches!(
+            parse_authorized_keys("# ops\nops nothex\n"),
+            Err(AuthorizedKeysError::ParseError(2, _))
+        ));
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,22 @@
+use std::{fmt::Display, io::Error};
+
+#[derive(Debug)]
+pub enum AuthorizedKeysError {
+    IoError(Error),
+    ParseError(usize, String),
+    InvalidKey,
+}
+
+impl Display for AuthorizedKeysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuthorizedKeysError::IoError(e) => {
+                write!(f, "IoError while persisting authorized keys: {}", e)
+            }
+            AuthorizedKeysError::ParseError(line, e) => {
+                write!(f, "Error in authorized keys on line {}: {}", line, e)
+            }
+            AuthorizedKeysError::InvalidKey => write!(f, "Not an Ed25519 public key"),
+        }
+    }
+}
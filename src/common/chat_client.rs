@@ -0,0 +1,787 @@
+pub mod error;
+
+use std::{mem, thread, time::Duration};
+
+use ed25519_dalek::SigningKey;
+
+use self::error::ChatClientError;
+
+use crate::common::{
+    message_stream::{connection::Connection, error::MessageStreamError, MessageStream},
+    protocol::{
+        codec::{BinaryCodec, Codec},
+        error::HandshakeError,
+        handshake::client::{Handshake, HandshakeArguments},
+        message::{server, Message},
+        packet::{
+            client,
+            server::{
+                Ack, Attachment, Chat, CommandResult, DeliveryStatus, EncryptedMessage, End,
+                FileChunk, PrivateMessage, PublicKey, ReadReceipt, UploadReady, UserJoined,
+                UserLeft, Warning,
+            },
+            Compression, EndReason, Packet,
+        },
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub delay: Duration,
+}
+
+impl ReconnectPolicy {
+    pub fn new(max_attempts: u32, delay: Duration) -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_attempts,
+            delay,
+        }
+    }
+
+    pub fn never() -> ReconnectPolicy {
+        ReconnectPolicy::new(0, Duration::ZERO)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy::new(5, Duration::from_secs(1))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatClientConfig {
+    // host:port, or unix:<path> for a local socket
+    pub address: String,
+    pub username: String,
+    pub signing_key: Option<SigningKey>,
+    // Shown next to this connection when the account lists its sessions
+    pub device: Option<String>,
+    pub compression: Compression,
+    pub reconnect: ReconnectPolicy,
+    // Whether mark_read answers senders that asked for a read receipt
+    pub read_receipts: bool,
+}
+
+impl ChatClientConfig {
+    pub fn new(address: String, username: String) -> ChatClientConfig {
+        ChatClientConfig {
+            address,
+            username,
+            signing_key: None,
+            device: None,
+            compression: Compression::Deflate,
+            reconnect: ReconnectPolicy::default(),
+            read_receipts: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatEvent {
+    // Only emitted after a reconnect, the first handshake is done by ChatClient::connect
+    Connected(Handshake),
+    Chat(Chat),
+    UserJoined(UserJoined),
+    UserLeft(UserLeft),
+    Warning(Warning),
+    CommandResult(CommandResult),
+    PublicKey(PublicKey),
+    EncryptedMessage(EncryptedMessage),
+    UploadReady(UploadReady),
+    FileChunk(FileChunk),
+    Attachment(Attachment),
+    DeliveryStatus(DeliveryStatus),
+    Ack(Ack),
+    ReadReceipt(ReadReceipt),
+    PrivateMessage(PrivateMessage),
+    End(End),
+}
+
+// Every hook gets the client, so a bot can answer right away
+pub trait ChatHandler<C: Codec + Clone = BinaryCodec> {
+    fn on_connected(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _handshake: &Handshake,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_chat(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _chat: &Chat,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_user_joined(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _user_joined: &UserJoined,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_user_left(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _user_left: &UserLeft,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_warning(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _warning: &Warning,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_command_result(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _command_result: &CommandResult,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_public_key(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _public_key: &PublicKey,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_encrypted_message(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _encrypted_message: &EncryptedMessage,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_upload_ready(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _upload_ready: &UploadReady,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_file_chunk(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _file_chunk: &FileChunk,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_delivery_status(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _delivery_status: &DeliveryStatus,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_ack(&mut self, _client: &mut ChatClient<C>, _ack: &Ack) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_read_receipt(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _read_receipt: &ReadReceipt,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_private_message(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _private_message: &PrivateMessage,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_attachment(
+        &mut self,
+        _client: &mut ChatClient<C>,
+        _attachment: &Attachment,
+    ) -> Result<(), ChatClientError> {
+        Ok(())
+    }
+
+    fn on_end(&mut self, _end: &End) {}
+}
+
+#[derive(Debug)]
+pub struct ChatClient<C: Codec + Clone = BinaryCodec> {
+    config: ChatClientConfig,
+    codec: C,
+    message_stream: Option<MessageStream<C>>,
+    handshake: Handshake,
+    closed: bool,
+    next_nonce: u64,
+    // Set by any reconnect, next_event reports it before anything the new session receives
+    reconnected: bool,
+}
+
+impl ChatClient {
+    pub fn connect(config: ChatClientConfig) -> Result<ChatClient, ChatClientError> {
+        ChatClient::connect_with_codec(config, BinaryCodec)
+    }
+}
+
+impl<C: Codec + Clone> ChatClient<C> {
+    pub fn connect_with_codec(
+        config: ChatClientConfig,
+        codec: C,
+    ) -> Result<ChatClient<C>, ChatClientError> {
+        let (message_stream, handshake) = open(&config, &codec)?;
+
+        Ok(ChatClient {
+            config,
+            codec,
+            message_stream: Some(message_stream),
+            handshake,
+            closed: false,
+            next_nonce: 0,
+            reconnected: false,
+        })
+    }
+
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    pub fn username(&self) -> &str {
+        self.handshake.username()
+    }
+
+    pub fn send_chat(&mut self, message: &str) -> Result<(), ChatClientError> {
+        self.send(client::Chat::new(None, message.to_owned()).to_message())
+    }
+
+    // Returns the nonce the server's Ack for this chat will carry.
+    // Nonces keep counting across reconnects, so a late Ack can't be mistaken for a newer chat.
+    pub fn send_tracked_chat(&mut self, message: &str) -> Result<u64, ChatClientError> {
+        let nonce = self.next_nonce;
+        self.next_nonce += 1;
+
+        self.send(client::Chat::new(Some(nonce), message.to_owned()).to_message())?;
+
+        Ok(nonce)
+    }
+
+    // Sends the read receipt the sender asked for, unless read receipts are turned off.
+    // Returns whether one was sent.
+    pub fn mark_read(
+        &mut self,
+        encrypted_message: &EncryptedMessage,
+    ) -> Result<bool, ChatClientError> {
+        let receipt_id = match encrypted_message.receipt_id {
+            Some(receipt_id) if self.config.read_receipts => receipt_id,
+            _ => return Ok(false),
+        };
+
+        let read_receipt = client::ReadReceipt::new(encrypted_message.sender.clone(), receipt_id);
+        self.send(read_receipt.to_message())?;
+
+        Ok(true)
+    }
+
+    pub fn send_command(&mut self, command: client::Command) -> Result<(), ChatClientError> {
+        self.send(command.to_message())
+    }
+
+    pub fn send(&mut self, message: Message) -> Result<(), ChatClientError> {
+        if self.closed {
+            return Err(ChatClientError::Closed);
+        }
+
+        if self.message_stream.is_none() {
+            self.reconnect()?;
+        }
+
+        let message_stream = match self.message_stream.as_mut() {
+            Some(message_stream) => message_stream,
+            None => return Err(ChatClientError::Closed),
+        };
+
+        let result = message_stream.send_message(&message);
+        if let Err(MessageStreamError::IoError(_)) = result {
+            // Next read or send reconnects
+            self.message_stream = None;
+        }
+
+        result.map_err(ChatClientError::MessageStreamError)
+    }
+
+    pub fn quit(mut self) -> Result<(), ChatClientError> {
+        let end_packet = client::End::new(EndReason::Quit, None);
+        let result = self.send(end_packet.to_message());
+        self.closed = true;
+
+        result
+    }
+
+    // Blocks until the server sends something, reconnecting whenever the connection drops.
+    // An End from the server is the last event, everything after it is Err(Closed).
+    pub fn next_event(&mut self) -> Result<ChatEvent, ChatClientError> {
+        loop {
+            if self.closed {
+                return Err(ChatClientError::Closed);
+            }
+
+            // Whether send or this loop reconnected, handlers hear about it the same way
+            if mem::take(&mut self.reconnected) {
+                return Ok(ChatEvent::Connected(self.handshake.clone()));
+            }
+
+            let message_stream = match self.message_stream.as_mut() {
+                Some(message_stream) => message_stream,
+                None => {
+                    self.reconnect()?;
+                    continue;
+                }
+            };
+
+            let message = match message_stream.read_message() {
+                Ok(message) => message,
+                Err(MessageStreamError::IoError(_)) => {
+                    self.message_stream = None;
+                    continue;
+                }
+                Err(err) => return Err(ChatClientError::MessageStreamError(err)),
+            };
+
+            let message = match message {
+                Message::Server(message) => message,
+                _ => return Err(ChatClientError::UnexpectedMessage(message)),
+            };
+
+            let event = match message {
+                server::Message::Chat(chat) => ChatEvent::Chat(chat),
+                server::Message::UserJoined(user_joined) => ChatEvent::UserJoined(user_joined),
+                server::Message::UserLeft(user_left) => ChatEvent::UserLeft(user_left),
+                server::Message::Warning(warning) => ChatEvent::Warning(warning),
+                server::Message::CommandResult(command_result) => {
+                    ChatEvent::CommandResult(command_result)
+                }
+                server::Message::PublicKey(public_key) => ChatEvent::PublicKey(public_key),
+                server::Message::EncryptedMessage(encrypted_message) => {
+                    ChatEvent::EncryptedMessage(encrypted_message)
+                }
+                server::Message::UploadReady(upload_ready) => ChatEvent::UploadReady(upload_ready),
+                server::Message::FileChunk(file_chunk) => ChatEvent::FileChunk(file_chunk),
+                server::Message::Attachment(attachment) => ChatEvent::Attachment(attachment),
+                server::Message::DeliveryStatus(delivery_status) => {
+                    ChatEvent::DeliveryStatus(delivery_status)
+                }
+                server::Message::Ack(ack) => ChatEvent::Ack(ack),
+                server::Message::ReadReceipt(read_receipt) => ChatEvent::ReadReceipt(read_receipt),
+                server::Message::PrivateMessage(private_message) => {
+                    ChatEvent::PrivateMessage(private_message)
+                }
+                server::Message::End(end) => {
+                    self.closed = true;
+                    self.message_stream = None;
+                    ChatEvent::End(end)
+                }
+                _ => return Err(ChatClientError::UnexpectedMessage(Message::Server(message))),
+            };
+
+            return Ok(event);
+        }
+    }
+
+    pub fn events(&mut self) -> Events<'_, C> {
+        Events {
+            client: self,
+            done: false,
+        }
+    }
+
+    // Dispatches events to the handler until the server ends the session
+    pub fn run<H: ChatHandler<C>>(&mut self, handler: &mut H) -> Result<End, ChatClientError> {
+        loop {
+            match self.next_event()? {
+                ChatEvent::Connected(handshake) => handler.on_connected(self, &handshake)?,
+                ChatEvent::Chat(chat) => handler.on_chat(self, &chat)?,
+                ChatEvent::UserJoined(user_joined) => handler.on_user_joined(self, &user_joined)?,
+                ChatEvent::UserLeft(user_left) => handler.on_user_left(self, &user_left)?,
+                ChatEvent::Warning(warning) => handler.on_warning(self, &warning)?,
+                ChatEvent::CommandResult(command_result) => {
+                    handler.on_command_result(self, &command_result)?
+                }
+                ChatEvent::PublicKey(public_key) => handler.on_public_key(self, &public_key)?,
+                ChatEvent::EncryptedMessage(encrypted_message) => {
+                    handler.on_encrypted_message(self, &encrypted_message)?
+                }
+                ChatEvent::UploadReady(upload_ready) => {
+                    handler.on_upload_ready(self, &upload_ready)?
+                }
+                ChatEvent::FileChunk(file_chunk) => handler.on_file_chunk(self, &file_chunk)?,
+                ChatEvent::Attachment(attachment) => handler.on_attachment(self, &attachment)?,
+                ChatEvent::DeliveryStatus(delivery_status) => {
+                    handler.on_delivery_status(self, &delivery_status)?
+                }
+                ChatEvent::Ack(ack) => handler.on_ack(self, &ack)?,
+                ChatEvent::ReadReceipt(read_receipt) => {
+                    handler.on_read_receipt(self, &read_receipt)?
+                }
+                ChatEvent::PrivateMessage(private_message) => {
+                    handler.on_private_message(self, &private_message)?
+                }
+                ChatEvent::End(end) => {
+                    handler.on_end(&end);
+                    return Ok(end);
+                }
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> Result<(), ChatClientError> {
+        let policy = self.config.reconnect;
+
+        for attempt in 0..policy.max_attempts {
+            if attempt > 0 {
+                thread::sleep(policy.delay);
+            }
+
+            match open(&self.config, &self.codec) {
+                Ok((message_stream, handshake)) => {
+                    self.message_stream = Some(message_stream);
+                    self.handshake = handshake;
+                    self.reconnected = true;
+                    return Ok(());
+                }
+                // The server answered and said no, asking again won't change that
+                Err(ChatClientError::HandshakeError(HandshakeError::AuthenticationFailed(
+                    reason,
+                    text,
+                ))) => {
+                    self.closed = true;
+                    return Err(ChatClientError::HandshakeError(
+                        HandshakeError::AuthenticationFailed(reason, text),
+                    ));
+                }
+                Err(_) => continue,
+            }
+        }
+
+        self.closed = true;
+        Err(ChatClientError::ReconnectFailed(policy.max_attempts))
+    }
+}
+
+pub struct Events<'a, C: Codec + Clone = BinaryCodec> {
+    client: &'a mut ChatClient<C>,
+    done: bool,
+}
+
+impl<C: Codec + Clone> Iterator for Events<'_, C> {
+    type Item = Result<ChatEvent, ChatClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let event = self.client.next_event();
+        self.done = matches!(event, Ok(ChatEvent::End(_)) | Err(_));
+
+        match event {
+            Err(ChatClientError::Closed) => None,
+            event => Some(event),
+        }
+    }
+}
+
+fn open<C: Codec + Clone>(
+    config: &ChatClientConfig,
+    codec: &C,
+) -> Result<(MessageStream<C>, Handshake), ChatClientError> {
+    let connection = Connection::connect(&config.address).map_err(ChatClientError::IoError)?;
+    let mut message_stream = MessageStream::with_codec(connection, codec.clone());
+
+    let arguments = HandshakeArguments::new(
+        config.username.clone(),
+        config.signing_key.clone(),
+        config.compression,
+        config.device.clone(),
+    );
+    let handshake = Handshake::perform(&mut message_stream, arguments)
+        .map_err(ChatClientError::HandshakeError)?;
+
+    Ok((message_stream, handshake))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use crate::common::protocol::{handshake::server::HandshakeFixture, packet::Rejection};
+
+    fn accept(listener: &TcpListener) -> MessageStream {
+        let (tcp_stream, _) = listener
+            .accept()
+            .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+        let mut message_stream = MessageStream::new(tcp_stream);
+
+        HandshakeFixture::new()
+            .perform(&mut message_stream)
+            .unwrap_or_else(|err| panic!("Failed to perform handshake: {}", err));
+
+        message_stream
+    }
+
+    fn send(message_stream: &mut MessageStream, packet: impl Packet) {
+        message_stream
+            .send_message(&packet.to_message())
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+    }
+
+    fn listen() -> (TcpListener, ChatClientConfig) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        let mut config = ChatClientConfig::new(address.to_string(), String::from("Bot"));
+        config.reconnect = ReconnectPolicy::new(3, Duration::from_millis(10));
+
+        (listener, config)
+    }
+
+    struct EchoBot {
+        joined: Vec<String>,
+    }
+
+    impl ChatHandler for EchoBot {
+        fn on_chat(&mut self, client: &mut ChatClient, chat: &Chat) -> Result<(), ChatClientError> {
+            client.send_chat(&format!("{} said {}", chat.username, chat.message))
+        }
+
+        fn on_user_joined(
+            &mut self,
+            _client: &mut ChatClient,
+            user_joined: &UserJoined,
+        ) -> Result<(), ChatClientError> {
+            self.joined.push(user_joined.username.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chat_client_dispatches_events_to_handler() {
+        let (listener, config) = listen();
+
+        let server = thread::spawn(move || {
+            let mut message_stream = accept(&listener);
+            send(&mut message_stream, UserJoined::new(String::from("Alice")));
+            send(
+                &mut message_stream,
+                Chat::new(String::from("Alice"), String::from("Hi")),
+            );
+
+            let reply = message_stream
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read reply: {}", err));
+            send(
+                &mut message_stream,
+                End::new(EndReason::ServerShutdown, None),
+            );
+
+            reply
+        });
+
+        let mut client =
+            ChatClient::connect(config).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let mut bot = EchoBot { joined: Vec::new() };
+        let end = client
+            .run(&mut bot)
+            .unwrap_or_else(|err| panic!("Failed to run bot: {}", err));
+
+        let reply = server
+            .join()
+            .unwrap_or_else(|_| panic!("Server thread panicked"));
+
+        assert_eq!(end.reason, EndReason::ServerShutdown);
+        assert_eq!(bot.joined, vec![String::from("Alice")]);
+        assert_eq!(
+            reply,
+            client::Chat::new(None, String::from("Alice said Hi")).to_message()
+        );
+        assert!(matches!(client.next_event(), Err(ChatClientError::Closed)));
+    }
+
+    #[test]
+    fn chat_client_tracks_chats_and_sends_read_receipts() {
+        let (listener, mut config) = listen();
+        config.read_receipts = true;
+
+        let server = thread::spawn(move || {
+            let mut message_stream = accept(&listener);
+            let mut read = || {
+                message_stream
+                    .read_message()
+                    .unwrap_or_else(|err| panic!("Failed to read message: {}", err))
+            };
+            let received = vec![read(), read(), read()];
+
+            send(
+                &mut message_stream,
+                Ack::new(0, Some(Rejection::RateLimited)),
+            );
+            send(&mut message_stream, Ack::new(1, None));
+            send(
+                &mut message_stream,
+                End::new(EndReason::ServerShutdown, None),
+            );
+
+            received
+        });
+
+        let mut client =
+            ChatClient::connect(config).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let send_tracked_chat = |client: &mut ChatClient| {
+            client
+                .send_tracked_chat("Deploy finished")
+                .unwrap_or_else(|err| panic!("Failed to send chat: {}", err))
+        };
+        assert_eq!(send_tracked_chat(&mut client), 0);
+        assert_eq!(send_tracked_chat(&mut client), 1);
+
+        let mark_read = |client: &mut ChatClient, receipt_id| {
+            client
+                .mark_read(&EncryptedMessage::new(
+                    String::from("Alice"),
+                    None,
+                    receipt_id,
+                    vec![1],
+                ))
+                .unwrap_or_else(|err| panic!("Failed to mark as read: {}", err))
+        };
+        assert!(!mark_read(&mut client, None));
+        assert!(mark_read(&mut client, Some(7)));
+
+        let events = client
+            .events()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|err| panic!("Failed to receive events: {}", err));
+        let received = server
+            .join()
+            .unwrap_or_else(|_| panic!("Server thread panicked"));
+
+        assert_eq!(
+            received,
+            vec![
+                client::Chat::new(Some(0), String::from("Deploy finished")).to_message(),
+                client::Chat::new(Some(1), String::from("Deploy finished")).to_message(),
+                client::ReadReceipt::new(String::from("Alice"), 7).to_message(),
+            ]
+        );
+        match events.as_slice() {
+            [ChatEvent::Ack(rejected), ChatEvent::Ack(accepted), ChatEvent::End(_)] => {
+                assert!(rejected
+                    .rejection
+                    .is_some_and(|rejection| rejection.is_retryable()));
+                assert!(accepted.is_accepted());
+            }
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn chat_client_reconnects_after_connection_loss() {
+        let (listener, config) = listen();
+
+        let server = thread::spawn(move || {
+            drop(accept(&listener));
+
+            let mut message_stream = accept(&listener);
+            send(
+                &mut message_stream,
+                Warning::new(String::from("Welcome back")),
+            );
+            send(
+                &mut message_stream,
+                End::new(EndReason::ServerShutdown, None),
+            );
+        });
+
+        let mut client =
+            ChatClient::connect(config).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let events = client
+            .events()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|err| panic!("Failed to receive events: {}", err));
+
+        server
+            .join()
+            .unwrap_or_else(|_| panic!("Server thread panicked"));
+
+        match events.as_slice() {
+            [ChatEvent::Connected(handshake), ChatEvent::Warning(warning), ChatEvent::End(_)] => {
+                assert_eq!(handshake.username(), "Bot");
+                assert_eq!(warning.text, "Welcome back");
+            }
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn chat_client_reports_reconnects_made_by_send() {
+        let (listener, config) = listen();
+
+        let server = thread::spawn(move || {
+            drop(accept(&listener));
+
+            let mut message_stream = accept(&listener);
+            let chat = message_stream
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read chat: {}", err));
+            send(
+                &mut message_stream,
+                End::new(EndReason::ServerShutdown, None),
+            );
+
+            chat
+        });
+
+        let mut client =
+            ChatClient::connect(config).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        // As if an earlier send had found the connection gone
+        client.message_stream = None;
+        client
+            .send_chat("Still there?")
+            .unwrap_or_else(|err| panic!("Failed to send chat: {}", err));
+        let events = client
+            .events()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|err| panic!("Failed to receive events: {}", err));
+
+        let chat = server
+            .join()
+            .unwrap_or_else(|_| panic!("Server thread panicked"));
+
+        assert_eq!(
+            chat,
+            client::Chat::new(None, String::from("Still there?")).to_message()
+        );
+        assert!(matches!(
+            events.as_slice(),
+            [ChatEvent::Connected(_), ChatEvent::End(_)]
+        ));
+    }
+}
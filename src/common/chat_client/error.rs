@@ -0,0 +1,35 @@
+use std::fmt::Display;
+
+use crate::common::{
+    message_stream::error::MessageStreamError,
+    protocol::{error::HandshakeError, message::Message},
+};
+
+#[derive(Debug)]
+pub enum ChatClientError {
+    IoError(std::io::Error),
+    HandshakeError(HandshakeError),
+    MessageStreamError(MessageStreamError),
+    UnexpectedMessage(Message),
+    ReconnectFailed(u32),
+    Closed,
+}
+
+impl Display for ChatClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatClientError::IoError(err) => write!(f, "IoError while connecting: {}", err),
+            ChatClientError::HandshakeError(err) => write!(f, "Handshake failed: {}", err),
+            ChatClientError::MessageStreamError(err) => {
+                write!(f, "Error while streaming message: {}", err)
+            }
+            ChatClientError::UnexpectedMessage(message) => {
+                write!(f, "Unexpected message: {}", message)
+            }
+            ChatClientError::ReconnectFailed(attempts) => {
+                write!(f, "Failed to reconnect after {} attempts", attempts)
+            }
+            ChatClientError::Closed => write!(f, "The connection was closed by the server"),
+        }
+    }
+}
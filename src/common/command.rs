@@ -0,0 +1,633 @@
+pub mod error;
+
+use self::error::CommandError;
+
+use crate::common::protocol::packet::{
+    client::{Chat, Command},
+    server::CommandResult,
+    Permission, PermissionSet,
+};
+
+// What a line typed by the user turns into
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Chat(Chat),
+    Command(Command),
+    // Nothing worth sending, the server would reject an empty chat
+    Empty,
+}
+
+impl Input {
+    // "/name arg \"quoted arg\"" becomes a command, "//text" sends "/text" as a chat message
+    pub fn parse(line: &str) -> Result<Input, CommandError> {
+        if line.trim().is_empty() {
+            return Ok(Input::Empty);
+        }
+
+        let command_line = match line.strip_prefix('/') {
+            Some(command_line) if !command_line.starts_with('/') => command_line,
+            Some(escaped_line) => {
+                return Ok(Input::Chat(Chat::new(None, String::from(escaped_line))))
+            }
+            None => return Ok(Input::Chat(Chat::new(None, String::from(line)))),
+        };
+
+        let mut words = split_arguments(command_line)?.into_iter();
+        let name = match words.next() {
+            Some(name) => name.to_lowercase(),
+            None => return Err(CommandError::EmptyName),
+        };
+
+        Ok(Input::Command(Command::new(name, words.collect())))
+    }
+}
+
+fn split_arguments(line: &str) -> Result<Vec<String>, CommandError> {
+    let mut arguments = Vec::new();
+    let mut argument = String::new();
+    let mut in_argument = false;
+    let mut quoted = false;
+    let mut characters = line.chars();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                in_argument = true;
+            }
+            '\\' if quoted => {
+                if let Some(escaped) = characters.next() {
+                    argument.push(escaped);
+                }
+            }
+            character if character.is_whitespace() && !quoted => {
+                if in_argument {
+                    arguments.push(std::mem::take(&mut argument));
+                    in_argument = false;
+                }
+            }
+            character => {
+                argument.push(character);
+                in_argument = true;
+            }
+        }
+    }
+
+    if quoted {
+        return Err(CommandError::UnterminatedQuote);
+    }
+
+    if in_argument {
+        arguments.push(argument);
+    }
+
+    Ok(arguments)
+}
+
+pub type CommandHandler<C> = Box<dyn Fn(&mut C, &[String]) -> Result<String, String> + Send + Sync>;
+
+pub struct CommandDefinition<C> {
+    pub name: String,
+    pub usage: String,
+    pub description: String,
+    pub permission: Option<Permission>,
+    handler: CommandHandler<C>,
+}
+
+impl<C> CommandDefinition<C> {
+    pub fn new(
+        name: &str,
+        usage: &str,
+        description: &str,
+        permission: Option<Permission>,
+        handler: impl Fn(&mut C, &[String]) -> Result<String, String> + Send + Sync + 'static,
+    ) -> CommandDefinition<C> {
+        CommandDefinition {
+            name: name.to_lowercase(),
+            usage: String::from(usage),
+            description: String::from(description),
+            permission,
+            handler: Box::new(handler),
+        }
+    }
+}
+
+impl<C> std::fmt::Debug for CommandDefinition<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommandDefinition")
+            .field("name", &self.name)
+            .field("usage", &self.usage)
+            .field("description", &self.description)
+            .field("permission", &self.permission)
+            .finish_non_exhaustive()
+    }
+}
+
+// C is whatever state the commands act on, e.g. the client's UI or the server's session
+#[derive(Debug)]
+pub struct CommandRegistry<C> {
+    commands: Vec<CommandDefinition<C>>,
+}
+
+impl<C> CommandRegistry<C> {
+    pub fn new() -> CommandRegistry<C> {
+        CommandRegistry {
+            commands: Vec::new(),
+        }
+    }
+
+    // Registering a name twice replaces the earlier command
+    pub fn register(&mut self, command: CommandDefinition<C>) {
+        self.commands
+            .retain(|existing| existing.name != command.name);
+        self.commands.push(command);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        name == "help" || self.find(name).is_some()
+    }
+
+    pub fn help(&self, permissions: PermissionSet) -> String {
+        let mut lines = vec![String::from("/help - Lists the available commands")];
+
+        for command in &self.commands {
+            if let Some(permission) = command.permission {
+                if !permissions.contains(permission) {
+                    continue;
+                }
+            }
+
+            match command.usage.is_empty() {
+                true => lines.push(format!("/{} - {}", command.name, command.description)),
+                false => lines.push(format!(
+                    "/{} {} - {}",
+                    command.name, command.usage, command.description
+                )),
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn execute(
+        &self,
+        context: &mut C,
+        permissions: PermissionSet,
+        command: &Command,
+    ) -> Result<String, CommandError> {
+        let definition = match self.find(&command.name) {
+            Some(definition) => definition,
+            None if command.name == "help" => return Ok(self.help(permissions)),
+            None => return Err(CommandError::UnknownCommand(command.name.clone())),
+        };
+
+        if let Some(permission) = definition.permission {
+            if !permissions.contains(permission) {
+                return Err(CommandError::NotPermitted(command.name.clone(), permission));
+            }
+        }
+
+        (definition.handler)(context, &command.arguments).map_err(CommandError::Failed)
+    }
+
+    // Executes the command and wraps the outcome into the packet sent back to the client
+    pub fn respond(
+        &self,
+        context: &mut C,
+        permissions: PermissionSet,
+        command: &Command,
+    ) -> CommandResult {
+        match self.execute(context, permissions, command) {
+            Ok(output) => CommandResult::new(true, output),
+            Err(err) => CommandResult::new(false, err.to_string()),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&CommandDefinition<C>> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+}
+
+impl<C> Default for CommandRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The client-local built-in commands act on the client through this
+pub trait ClientCommandContext {
+    fn change_nick(&mut self, nick: String) -> Result<(), String>;
+
+    fn clear(&mut self);
+
+    fn quit(&mut self);
+
+    // Own fingerprint without a username
+    fn fingerprint(&self, _username: Option<&str>) -> Result<String, String> {
+        Err(String::from("End-to-end encryption is not enabled"))
+    }
+}
+
+impl<C: ClientCommandContext> CommandRegistry<C> {
+    pub fn with_client_builtins() -> CommandRegistry<C> {
+        let mut registry = CommandRegistry::new();
+
+        registry.register(CommandDefinition::new(
+            "nick",
+            "<name>",
+            "Reconnects with another username",
+            None,
+            |context: &mut C, arguments: &[String]| match arguments {
+                [nick] => context
+                    .change_nick(nick.clone())
+                    .map(|_| format!("Changing username to {}", nick)),
+                _ => Err(String::from("Usage: /nick <name>")),
+            },
+        ));
+        registry.register(CommandDefinition::new(
+            "clear",
+            "",
+            "Clears the chat history",
+            None,
+            |context: &mut C, _: &[String]| {
+                context.clear();
+                Ok(String::new())
+            },
+        ));
+        registry.register(CommandDefinition::new(
+            "fingerprint",
+            "[username]",
+            "Shows a key fingerprint to compare with the other person",
+            None,
+            |context: &mut C, arguments: &[String]| match arguments {
+                [] => context.fingerprint(None),
+                [username] => context.fingerprint(Some(username)),
+                _ => Err(String::from("Usage: /fingerprint [username]")),
+            },
+        ));
+        registry.register(CommandDefinition::new(
+            "quit",
+            "",
+            "Leaves the chat",
+            None,
+            |context: &mut C, _: &[String]| {
+                context.quit();
+                Ok(String::new())
+            },
+        ));
+
+        registry
+    }
+}
+
+// The server-side built-in commands act on the calling user's account through this
+pub trait ServerCommandContext {
+    // One line per session of the calling user, oldest first
+    fn sessions(&self) -> Vec<String>;
+
+    // Only the calling user's own sessions can be revoked
+    fn revoke_session(&mut self, session_id: u64) -> Result<(), String>;
+
+    // Reaches every session of the recipient, fails when they aren't online
+    fn send_private_message(&mut self, recipient: &str, message: String) -> Result<(), String>;
+
+    // One line per session of any user, with the address it connects from
+    fn whois(&self, username: &str) -> Vec<String>;
+
+    // Everyone online, each user once
+    fn usernames(&self) -> Vec<String>;
+}
+
+impl<C: ServerCommandContext> CommandRegistry<C> {
+    pub fn with_server_builtins() -> CommandRegistry<C> {
+        let mut registry = CommandRegistry::new();
+
+        registry.register(CommandDefinition::new(
+            "sessions",
+            "",
+            "Lists the devices you are logged in from",
+            None,
+            |context: &mut C, _: &[String]| Ok(context.sessions().join("\n")),
+        ));
+        registry.register(CommandDefinition::new(
+            "revoke",
+            "<session>",
+            "Logs out one of your devices",
+            None,
+            |context: &mut C, arguments: &[String]| {
+                let session_id = match arguments {
+                    [session_id] => session_id
+                        .parse::<u64>()
+                        .map_err(|_| format!("{} is not a session id", session_id))?,
+                    _ => return Err(String::from("Usage: /revoke <session>")),
+                };
+
+                context
+                    .revoke_session(session_id)
+                    .map(|_| format!("Revoked session {}", session_id))
+            },
+        ));
+        registry.register(CommandDefinition::new(
+            "msg",
+            "<user> <message>",
+            "Sends a private message",
+            None,
+            |context: &mut C, arguments: &[String]| {
+                let (recipient, words) = match arguments {
+                    [recipient, words @ ..] if !words.is_empty() => (recipient, words),
+                    _ => return Err(String::from("Usage: /msg <user> <message>")),
+                };
+
+                context
+                    .send_private_message(recipient, words.join(" "))
+                    .map(|_| String::new())
+            },
+        ));
+        registry.register(CommandDefinition::new(
+            "users",
+            "",
+            "Lists who is online",
+            None,
+            |context: &mut C, _: &[String]| Ok(context.usernames().join("\n")),
+        ));
+        registry.register(CommandDefinition::new(
+            "whois",
+            "<user>",
+            "Shows where a user is connected from",
+            Some(Permission::SeeAddresses),
+            |context: &mut C, arguments: &[String]| {
+                let username = match arguments {
+                    [username] => username,
+                    _ => return Err(String::from("Usage: /whois <user>")),
+                };
+
+                match context.whois(username) {
+                    sessions if sessions.is_empty() => Err(format!("No such user: {}", username)),
+                    sessions => Ok(sessions.join("\n")),
+                }
+            },
+        ));
+
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        net::IpAddr,
+        sync::{mpsc, Mutex},
+    };
+
+    use crate::common::{
+        authorized_keys::AuthorizedKeys,
+        moderation::Moderation,
+        peer_credentials::{LocalUser, PeerCredentials},
+        permissions::Permissions,
+        protocol::{
+            packet::{
+                server::{End, PrivateMessage},
+                EndReason, Packet,
+            },
+            username_policy::UsernamePolicy,
+        },
+        server_state::{CommandCaller, ServerState},
+    };
+
+    #[derive(Default)]
+    struct Client {
+        nick: String,
+        cleared: bool,
+        quit: bool,
+    }
+
+    impl ClientCommandContext for Client {
+        fn change_nick(&mut self, nick: String) -> Result<(), String> {
+            self.nick = nick;
+            Ok(())
+        }
+
+        fn clear(&mut self) {
+            self.cleared = true;
+        }
+
+        fn quit(&mut self) {
+            self.quit = true;
+        }
+    }
+
+    fn command(line: &str) -> Command {
+        match Input::parse(line) {
+            Ok(Input::Command(command)) => command,
+            other => panic!("{} was not parsed as a command: {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn input_parses_commands_and_chat() {
+        assert_eq!(
+            command("/Ban troll \"for \\\"spamming\\\" links\"  "),
+            Command::new(
+                String::from("ban"),
+                vec![
+                    String::from("troll"),
+                    String::from("for \"spamming\" links")
+                ]
+            )
+        );
+        assert_eq!(
+            Input::parse("//shrug"),
+            Ok(Input::Chat(Chat::new(None, String::from("/shrug"))))
+        );
+        assert_eq!(
+            Input::parse("hello"),
+            Ok(Input::Chat(Chat::new(None, String::from("hello"))))
+        );
+        assert_eq!(Input::parse(""), Ok(Input::Empty));
+        assert_eq!(Input::parse(" \t "), Ok(Input::Empty));
+        assert_eq!(Input::parse("/"), Err(CommandError::EmptyName));
+        assert_eq!(
+            Input::parse("/nick \"open"),
+            Err(CommandError::UnterminatedQuote)
+        );
+    }
+
+    #[test]
+    fn registry_executes_client_builtins() {
+        let registry = CommandRegistry::with_client_builtins();
+        let mut client = Client::default();
+
+        registry
+            .execute(
+                &mut client,
+                PermissionSet::default(),
+                &command("/nick Kitt3120"),
+            )
+            .unwrap_or_else(|err| panic!("Failed to execute /nick: {}", err));
+        registry
+            .execute(&mut client, PermissionSet::default(), &command("/clear"))
+            .unwrap_or_else(|err| panic!("Failed to execute /clear: {}", err));
+        registry
+            .execute(&mut client, PermissionSet::default(), &command("/quit"))
+            .unwrap_or_else(|err| panic!("Failed to execute /quit: {}", err));
+
+        assert_eq!(client.nick, "Kitt3120");
+        assert!(client.cleared && client.quit);
+        assert!(registry
+            .execute(
+                &mut client,
+                PermissionSet::default(),
+                &command("/fingerprint")
+            )
+            .is_err());
+        assert!(!registry.contains("topic"));
+    }
+
+    #[test]
+    fn registry_executes_server_builtins() {
+        let mut state = ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        );
+        state
+            .peer_credentials_mut()
+            .allow(LocalUser::new(1000, String::from("ops")));
+        let mut join = |device: &str| {
+            let (sender, receiver) = mpsc::channel();
+            let session_id = state
+                .join(String::from("ops"), Some(device.to_owned()), None, sender)
+                .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+            (session_id, receiver)
+        };
+        let (laptop_id, _laptop) = join("Laptop");
+        let (phone_id, phone) = join("Phone");
+        let (sender, alice) = mpsc::channel();
+        state
+            .join(
+                String::from("Alice"),
+                None,
+                Some(IpAddr::from([192, 0, 2, 7])),
+                sender,
+            )
+            .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+
+        let state = Mutex::new(state);
+        let registry = CommandRegistry::with_server_builtins();
+        let mut caller = CommandCaller::new(&state, laptop_id);
+        let permissions = PermissionSet::default();
+
+        let sessions = registry
+            .execute(&mut caller, permissions, &command("/sessions"))
+            .unwrap_or_else(|err| panic!("Failed to execute /sessions: {}", err));
+        let lines: Vec<&str> = sessions.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("{}: Laptop", laptop_id)));
+        assert!(lines[0].ends_with("(this device)"));
+        assert!(lines[1].starts_with(&format!("{}: Phone", phone_id)));
+
+        assert_eq!(
+            registry.execute(
+                &mut caller,
+                permissions,
+                &command(&format!("/revoke {}", phone_id))
+            ),
+            Ok(format!("Revoked session {}", phone_id))
+        );
+        assert_eq!(
+            phone.try_iter().last(),
+            Some(End::new(EndReason::SessionRevoked, None).to_message())
+        );
+        assert!(registry
+            .execute(
+                &mut caller,
+                permissions,
+                &command(&format!("/revoke {}", phone_id))
+            )
+            .is_err());
+        assert!(registry
+            .execute(&mut caller, permissions, &command("/revoke phone"))
+            .is_err());
+
+        registry
+            .execute(&mut caller, permissions, &command("/msg alice Hi there"))
+            .unwrap_or_else(|err| panic!("Failed to execute /msg: {}", err));
+        assert_eq!(
+            alice.try_iter().last(),
+            Some(PrivateMessage::new(String::from("ops"), String::from("Hi there")).to_message())
+        );
+        assert!(registry
+            .execute(&mut caller, permissions, &command("/msg carol Hi"))
+            .is_err());
+        assert!(registry
+            .execute(&mut caller, permissions, &command("/msg alice"))
+            .is_err());
+
+        assert_eq!(
+            registry.execute(&mut caller, permissions, &command("/whois alice")),
+            Err(CommandError::NotPermitted(
+                String::from("whois"),
+                Permission::SeeAddresses
+            ))
+        );
+        assert_eq!(
+            registry.execute(&mut caller, permissions, &command("/users")),
+            Ok(String::from("Alice\nops"))
+        );
+
+        let see_addresses = PermissionSet::new(&[Permission::SeeAddresses]);
+        assert!(registry
+            .execute(&mut caller, see_addresses, &command("/whois alice"))
+            .is_ok_and(|whois| whois.ends_with("from 192.0.2.7")));
+        assert!(registry
+            .execute(&mut caller, see_addresses, &command("/whois carol"))
+            .is_err());
+    }
+
+    #[test]
+    fn registry_checks_permissions_and_generates_help() {
+        let mut registry = CommandRegistry::<Vec<String>>::new();
+        registry.register(CommandDefinition::new(
+            "warn",
+            "<message>",
+            "Posts a warning",
+            Some(Permission::Kick),
+            |warnings: &mut Vec<String>, arguments: &[String]| {
+                warnings.push(arguments.join(" "));
+                Ok(String::from("Warning posted"))
+            },
+        ));
+        let mut warnings = Vec::new();
+
+        assert_eq!(
+            registry.respond(
+                &mut warnings,
+                PermissionSet::default(),
+                &command("/warn Rust")
+            ),
+            CommandResult::new(
+                false,
+                CommandError::NotPermitted(String::from("warn"), Permission::Kick).to_string()
+            )
+        );
+        assert!(!registry.help(PermissionSet::default()).contains("/warn"));
+
+        let permissions = PermissionSet::new(&[Permission::Kick]);
+        assert_eq!(
+            registry.respond(&mut warnings, permissions, &command("/warn Rust")),
+            CommandResult::new(true, String::from("Warning posted"))
+        );
+        assert_eq!(warnings, vec![String::from("Rust")]);
+        assert!(registry
+            .execute(&mut warnings, permissions, &command("/help"))
+            .is_ok_and(|help| help.contains("/warn <message> - Posts a warning")));
+        assert_eq!(
+            registry.execute(&mut warnings, permissions, &command("/dance")),
+            Err(CommandError::UnknownCommand(String::from("dance")))
+        );
+    }
+}
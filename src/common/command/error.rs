@@ -0,0 +1,30 @@
+use std::fmt::Display;
+
+use crate::common::protocol::packet::Permission;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandError {
+    UnterminatedQuote,
+    EmptyName,
+    UnknownCommand(String),
+    NotPermitted(String, Permission),
+    Failed(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::UnterminatedQuote => write!(f, "Unterminated quote"),
+            CommandError::EmptyName => write!(f, "Missing command name"),
+            CommandError::UnknownCommand(name) => {
+                write!(f, "Unknown command /{}, see /help", name)
+            }
+            CommandError::NotPermitted(name, permission) => write!(
+                f,
+                "The command /{} requires the permission {}",
+                name, permission
+            ),
+            CommandError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+pub mod error;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    thread,
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use self::error::DiscoveryError;
+
+use crate::common::{
+    protocol::{error::MessageParseError, wire::WireReader, PROTOCOL_VERSION},
+    threading::CancellationToken,
+};
+
+// An administratively scoped group, so beacons never leave the site
+pub const DISCOVERY_ADDRESS: SocketAddrV4 =
+    SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), 48_231);
+
+const BEACON_MAGIC: &[u8; 4] = b"RCHT";
+const BEACON_HEADER_SIZE: usize = 8;
+const MAX_BEACON_SIZE: usize = 512;
+
+// What a server announces: magic, protocol version and chat port as little-endian u16s, then the name
+#[derive(Clone, Debug, PartialEq)]
+pub struct Beacon {
+    pub name: String,
+    pub port: u16,
+    pub protocol_version: u16,
+}
+
+impl Beacon {
+    pub fn new(name: String, port: u16) -> Beacon {
+        Beacon {
+            name,
+            port,
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = BEACON_MAGIC.to_vec();
+        bytes.extend(self.protocol_version.to_le_bytes());
+        bytes.extend(self.port.to_le_bytes());
+
+        // Long names are cut short on a character boundary, so the rest still parses
+        let mut name_length = self.name.len().min(MAX_BEACON_SIZE - BEACON_HEADER_SIZE);
+        while !self.name.is_char_boundary(name_length) {
+            name_length -= 1;
+        }
+        bytes.extend(&self.name.as_bytes()[..name_length]);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Beacon, DiscoveryError> {
+        parse_beacon(bytes).map_err(|_| DiscoveryError::InvalidBeacon)
+    }
+}
+
+fn parse_beacon(bytes: &[u8]) -> Result<Beacon, MessageParseError> {
+    let mut reader = WireReader::new(bytes);
+
+    if reader.read_array::<4>("Magic")? != *BEACON_MAGIC {
+        return Err(MessageParseError::ByteParse(String::from("Magic")));
+    }
+    let protocol_version = u16::from_le_bytes(reader.read_array("Protocol version")?);
+    let port = u16::from_le_bytes(reader.read_array("Port")?);
+    let name = reader.read_remaining_str("Name")?;
+
+    Ok(Beacon {
+        name: name.to_owned(),
+        port,
+        protocol_version,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredServer {
+    // Where the beacon came from, with the chat port it announced
+    pub address: SocketAddr,
+    pub beacon: Beacon,
+}
+
+impl DiscoveredServer {
+    pub fn is_compatible(&self) -> bool {
+        self.beacon.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+impl Display for DiscoveredServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {} (protocol v{})",
+            self.beacon.name, self.address, self.beacon.protocol_version
+        )
+    }
+}
+
+// Sends a server's beacon to the discovery group. Beacons stay on the local network segment.
+#[derive(Debug)]
+pub struct Announcer {
+    udp_socket: UdpSocket,
+    beacon: Beacon,
+    target: SocketAddrV4,
+}
+
+impl Announcer {
+    pub fn new(beacon: Beacon, target: SocketAddrV4) -> Result<Announcer, DiscoveryError> {
+        let udp_socket =
+            UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(DiscoveryError::IoError)?;
+        udp_socket
+            .set_multicast_ttl_v4(1)
+            .and_then(|_| udp_socket.set_multicast_loop_v4(true))
+            .and_then(|_| udp_socket.set_broadcast(true))
+            .map_err(DiscoveryError::IoError)?;
+
+        Ok(Announcer {
+            udp_socket,
+            beacon,
+            target,
+        })
+    }
+
+    pub fn beacon(&self) -> &Beacon {
+        &self.beacon
+    }
+
+    pub fn announce(&self) -> Result<(), DiscoveryError> {
+        self.udp_socket
+            .send_to(&self.beacon.as_bytes(), self.target)
+            .map_err(DiscoveryError::IoError)?;
+
+        Ok(())
+    }
+
+    // Announces every interval until cancelled, meant to run on its own thread
+    pub fn run(
+        &self,
+        interval: Duration,
+        cancellation_token: &CancellationToken,
+    ) -> Result<(), DiscoveryError> {
+        while !cancellation_token.is_cancelled().unwrap_or(true) {
+            self.announce()?;
+            thread::sleep(interval);
+        }
+
+        Ok(())
+    }
+}
+
+// Listens for beacons for the given duration and lists every server heard, sorted by name.
+// Several clients on one machine can listen at the same time.
+pub fn discover(
+    target: SocketAddrV4,
+    duration: Duration,
+) -> Result<Vec<DiscoveredServer>, DiscoveryError> {
+    let udp_socket = listen(target).map_err(DiscoveryError::IoError)?;
+    let deadline = Instant::now() + duration;
+    let mut servers = HashMap::new();
+    let mut buffer = [0; MAX_BEACON_SIZE];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        udp_socket
+            .set_read_timeout(Some(remaining))
+            .map_err(DiscoveryError::IoError)?;
+
+        let (length, source) = match udp_socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(err) => return Err(DiscoveryError::IoError(err)),
+        };
+
+        // Anyone can send to the group, garbage is skipped
+        if let Ok(beacon) = Beacon::from_bytes(&buffer[..length]) {
+            let address = SocketAddr::new(source.ip(), beacon.port);
+            servers.insert(address, DiscoveredServer { address, beacon });
+        }
+    }
+
+    let mut servers: Vec<DiscoveredServer> = servers.into_values().collect();
+    servers.sort_by(|a, b| {
+        a.beacon
+            .name
+            .cmp(&b.beacon.name)
+            .then(a.address.cmp(&b.address))
+    });
+
+    Ok(servers)
+}
+
+fn listen(target: SocketAddrV4) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, target.port())).into())?;
+
+    if target.ip().is_multicast() {
+        socket.join_multicast_v4(target.ip(), &Ipv4Addr::UNSPECIFIED)?;
+    }
+
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn beacon_round_trips() {
+        let beacon = Beacon::new(String::from("Kitt3120's server ⚡"), 8080);
+
+        let parsed = Beacon::from_bytes(&beacon.as_bytes())
+            .unwrap_or_else(|err| panic!("Failed to parse beacon: {}", err));
+        assert_eq!(parsed, beacon);
+        assert!(matches!(
+            Beacon::from_bytes(b"HTTP/1.1 200 OK"),
+            Err(DiscoveryError::InvalidBeacon)
+        ));
+    }
+
+    #[test]
+    fn discover_finds_announced_servers() {
+        let target = SocketAddrV4::new(*DISCOVERY_ADDRESS.ip(), 48_232);
+        let announcer = Arc::new(
+            Announcer::new(Beacon::new(String::from("Office"), 8080), target)
+                .unwrap_or_else(|err| panic!("Failed to create announcer: {}", err)),
+        );
+        let cancellation_token = Arc::new(CancellationToken::new());
+
+        let announcing = {
+            let announcer = Arc::clone(&announcer);
+            let cancellation_token = Arc::clone(&cancellation_token);
+            thread::spawn(move || announcer.run(Duration::from_millis(50), &cancellation_token))
+        };
+
+        let servers = discover(target, Duration::from_millis(500))
+            .unwrap_or_else(|err| panic!("Failed to discover servers: {}", err));
+        cancellation_token
+            .cancel()
+            .unwrap_or_else(|err| panic!("Failed to cancel: {}", err));
+        let _ = announcing.join();
+
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].beacon, *announcer.beacon());
+        assert_eq!(servers[0].address.port(), 8080);
+        assert!(servers[0].is_compatible());
+    }
+}
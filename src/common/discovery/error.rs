@@ -0,0 +1,16 @@
+use std::{fmt::Display, io::Error};
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    IoError(Error),
+    InvalidBeacon,
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DiscoveryError::IoError(e) => write!(f, "IoError during discovery: {}", e),
+            DiscoveryError::InvalidBeacon => write!(f, "Received an invalid beacon"),
+        }
+    }
+}
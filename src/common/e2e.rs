@@ -0,0 +1,384 @@
+pub mod error;
+
+use std::collections::HashMap;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use self::error::E2eError;
+
+use crate::common::protocol::{
+    packet::{client, server},
+    wire,
+};
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+
+const KEY_INFO: &[u8] = b"rusty_chat e2e v1";
+const TAG_SIZE: usize = 16;
+
+// The long-term X25519 key a client publishes with client::PublishKey.
+// Keep the secret bytes around, a new identity means every peer sees a changed key.
+// A user on several devices copies the secret bytes to each of them,
+// the server refuses a second identity for the same user.
+#[derive(Clone)]
+pub struct Identity {
+    secret: StaticSecret,
+    public_key: PublicKey,
+}
+
+impl Identity {
+    pub fn generate() -> Identity {
+        Identity::from_secret_bytes(StaticSecret::random_from_rng(OsRng).to_bytes())
+    }
+
+    pub fn from_secret_bytes(secret_bytes: [u8; 32]) -> Identity {
+        let secret = StaticSecret::from(secret_bytes);
+        let public_key = PublicKey::from(&secret);
+
+        Identity { secret, public_key }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.public_key.to_bytes()
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key())
+    }
+
+    pub fn publish(&self) -> client::PublishKey {
+        client::PublishKey::new(self.public_key())
+    }
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity")
+            .field("fingerprint", &self.fingerprint())
+            .finish_non_exhaustive()
+    }
+}
+
+// SHA-256 of the key in groups of four hex digits, short enough to read out over another channel
+pub fn fingerprint(public_key: &[u8; PUBLIC_KEY_SIZE]) -> String {
+    let digest = Sha256::digest(public_key);
+
+    digest
+        .chunks(2)
+        .map(|group| format!("{:02x}{:02x}", group[0], group[1]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Peer keys, trusted on first use. A key that changes afterwards is refused until replace is called,
+// which a client should only do once the user compared the new fingerprint.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyRing {
+    keys: HashMap<String, [u8; PUBLIC_KEY_SIZE]>,
+}
+
+impl KeyRing {
+    pub fn new() -> KeyRing {
+        KeyRing {
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, username: &str) -> Option<&[u8; PUBLIC_KEY_SIZE]> {
+        self.keys.get(username)
+    }
+
+    pub fn fingerprint(&self, username: &str) -> Option<String> {
+        self.get(username).map(fingerprint)
+    }
+
+    pub fn trust(
+        &mut self,
+        username: &str,
+        public_key: [u8; PUBLIC_KEY_SIZE],
+    ) -> Result<(), E2eError> {
+        match self.keys.get(username) {
+            Some(known_key) if *known_key != public_key => {
+                Err(E2eError::KeyChanged(username.to_owned()))
+            }
+            _ => {
+                self.keys.insert(username.to_owned(), public_key);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn replace(&mut self, username: &str, public_key: [u8; PUBLIC_KEY_SIZE]) {
+        self.keys.insert(username.to_owned(), public_key);
+    }
+
+    // Takes the answer to a client::RequestKey
+    pub fn trust_public_key(&mut self, public_key: &server::PublicKey) -> Result<(), E2eError> {
+        match public_key.public_key {
+            Some(key) => self.trust(&public_key.username, key),
+            None => Err(E2eError::UnknownKey(public_key.username.clone())),
+        }
+    }
+
+    pub fn session(
+        &self,
+        identity: &Identity,
+        username: &str,
+        peer: &str,
+    ) -> Result<Session, E2eError> {
+        match self.get(peer) {
+            Some(peer_key) => Session::new(identity, username, peer, *peer_key),
+            None => Err(E2eError::UnknownKey(peer.to_owned())),
+        }
+    }
+}
+
+// A pairwise session between two identities.
+// Every message gets a fresh ephemeral key, so its key is derived from both the ephemeral and the static
+// Diffie-Hellman results: only the recipient can read it, and only the sender's identity could have written it.
+// Payload: ephemeral public key (32) + ChaCha20-Poly1305 ciphertext with tag.
+#[derive(Clone)]
+pub struct Session {
+    secret: StaticSecret,
+    public_key: PublicKey,
+    username: String,
+    peer: String,
+    peer_public_key: PublicKey,
+}
+
+impl Session {
+    pub fn new(
+        identity: &Identity,
+        username: &str,
+        peer: &str,
+        peer_public_key: [u8; PUBLIC_KEY_SIZE],
+    ) -> Result<Session, E2eError> {
+        let peer_public_key = PublicKey::from(peer_public_key);
+
+        // A low-order point would make the static secret all zeros
+        if !identity
+            .secret
+            .diffie_hellman(&peer_public_key)
+            .was_contributory()
+        {
+            return Err(E2eError::InvalidPublicKey(peer.to_owned()));
+        }
+
+        Ok(Session {
+            secret: identity.secret.clone(),
+            public_key: identity.public_key,
+            username: username.to_owned(),
+            peer: peer.to_owned(),
+            peer_public_key,
+        })
+    }
+
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    pub fn peer_fingerprint(&self) -> String {
+        fingerprint(self.peer_public_key.as_bytes())
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public_key = PublicKey::from(&ephemeral_secret);
+
+        let ephemeral_shared = ephemeral_secret.diffie_hellman(&self.peer_public_key);
+        let static_shared = self.secret.diffie_hellman(&self.peer_public_key);
+
+        let cipher = message_cipher(
+            ephemeral_shared.as_bytes(),
+            static_shared.as_bytes(),
+            &ephemeral_public_key,
+            &self.public_key,
+            &self.peer_public_key,
+        );
+
+        let aad = associated_data(&self.username, &self.peer);
+        // Only fails past ChaCha20's 256 GiB limit, far beyond MAX_MESSAGE_SIZE
+        let ciphertext = cipher
+            .encrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .unwrap_or_default();
+
+        let mut payload = ephemeral_public_key.as_bytes().to_vec();
+        payload.extend(ciphertext);
+
+        payload
+    }
+
+    pub fn open(&self, payload: &[u8]) -> Result<Vec<u8>, E2eError> {
+        if payload.len() < PUBLIC_KEY_SIZE + TAG_SIZE {
+            return Err(E2eError::PayloadTooShort(payload.len()));
+        }
+
+        let (ephemeral_public_key, ciphertext) = payload.split_at(PUBLIC_KEY_SIZE);
+        let ephemeral_public_key = match <[u8; PUBLIC_KEY_SIZE]>::try_from(ephemeral_public_key) {
+            Ok(bytes) => PublicKey::from(bytes),
+            Err(_) => return Err(E2eError::PayloadTooShort(payload.len())),
+        };
+
+        let ephemeral_shared = self.secret.diffie_hellman(&ephemeral_public_key);
+        let static_shared = self.secret.diffie_hellman(&self.peer_public_key);
+
+        let cipher = message_cipher(
+            ephemeral_shared.as_bytes(),
+            static_shared.as_bytes(),
+            &ephemeral_public_key,
+            &self.peer_public_key,
+            &self.public_key,
+        );
+
+        let aad = associated_data(&self.peer, &self.username);
+        cipher
+            .decrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| E2eError::DecryptionFailed(self.peer.clone()))
+    }
+
+    pub fn encrypt(&self, message: &str) -> client::EncryptedMessage {
+        client::EncryptedMessage::new(self.peer.clone(), None, self.seal(message.as_bytes()))
+    }
+
+    pub fn decrypt(
+        &self,
+        encrypted_message: &server::EncryptedMessage,
+    ) -> Result<String, E2eError> {
+        if encrypted_message.sender != self.peer {
+            return Err(E2eError::DecryptionFailed(encrypted_message.sender.clone()));
+        }
+
+        let plaintext = self.open(&encrypted_message.payload)?;
+        String::from_utf8(plaintext).map_err(|_| E2eError::InvalidUtf8(self.peer.clone()))
+    }
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("username", &self.username)
+            .field("peer", &self.peer)
+            .field("peer_fingerprint", &self.peer_fingerprint())
+            .finish_non_exhaustive()
+    }
+}
+
+// Every message has its own key, so the all-zero nonce is never reused under the same key
+fn message_cipher(
+    ephemeral_shared: &[u8; 32],
+    static_shared: &[u8; 32],
+    ephemeral_public_key: &PublicKey,
+    sender_public_key: &PublicKey,
+    recipient_public_key: &PublicKey,
+) -> ChaCha20Poly1305 {
+    let mut salt = Vec::with_capacity(3 * PUBLIC_KEY_SIZE);
+    salt.extend_from_slice(ephemeral_public_key.as_bytes());
+    salt.extend_from_slice(sender_public_key.as_bytes());
+    salt.extend_from_slice(recipient_public_key.as_bytes());
+
+    let mut input_key = Vec::with_capacity(64);
+    input_key.extend_from_slice(ephemeral_shared);
+    input_key.extend_from_slice(static_shared);
+
+    let mut key = [0; 32];
+    // 32 bytes is far below HKDF-SHA256's output limit
+    let _ = Hkdf::<Sha256>::new(Some(&salt), &input_key).expand(KEY_INFO, &mut key);
+
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+// Binds the ciphertext to both usernames, so the server can't pass it off as coming from someone else
+fn associated_data(sender: &str, recipient: &str) -> Vec<u8> {
+    let mut aad = Vec::new();
+    wire::write_str(&mut aad, sender);
+    wire::write_str(&mut aad, recipient);
+
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> (Session, Session) {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+
+        let alice_session = Session::new(&alice, "Alice", "Bob", bob.public_key())
+            .unwrap_or_else(|err| panic!("Failed to create session: {}", err));
+        let bob_session = Session::new(&bob, "Bob", "Alice", alice.public_key())
+            .unwrap_or_else(|err| panic!("Failed to create session: {}", err));
+
+        (alice_session, bob_session)
+    }
+
+    #[test]
+    fn session_round_trips_and_rejects_tampering() {
+        let (alice, bob) = sessions();
+
+        let encrypted = alice.encrypt("Meet at 5");
+        assert_eq!(encrypted.recipient, "Bob");
+        assert!(!encrypted
+            .payload
+            .windows(9)
+            .any(|window| window == b"Meet at 5"));
+
+        let relayed =
+            server::EncryptedMessage::new(String::from("Alice"), None, None, encrypted.payload);
+        assert_eq!(bob.decrypt(&relayed), Ok(String::from("Meet at 5")));
+
+        let mut tampered = relayed.clone();
+        if let Some(byte) = tampered.payload.last_mut() {
+            *byte ^= 1;
+        }
+        assert_eq!(
+            bob.decrypt(&tampered),
+            Err(E2eError::DecryptionFailed(String::from("Alice")))
+        );
+
+        // The sender's own session can't open what it sealed for Bob
+        assert!(alice.open(&relayed.payload).is_err());
+    }
+
+    #[test]
+    fn key_ring_refuses_changed_keys() {
+        let mut key_ring = KeyRing::new();
+        let first = Identity::generate();
+        let second = Identity::generate();
+
+        assert_eq!(key_ring.trust("Bob", first.public_key()), Ok(()));
+        assert_eq!(key_ring.trust("Bob", first.public_key()), Ok(()));
+        assert_eq!(
+            key_ring.trust("Bob", second.public_key()),
+            Err(E2eError::KeyChanged(String::from("Bob")))
+        );
+        assert_eq!(key_ring.fingerprint("Bob"), Some(first.fingerprint()));
+
+        key_ring.replace("Bob", second.public_key());
+        assert_eq!(key_ring.fingerprint("Bob"), Some(second.fingerprint()));
+        assert_eq!(second.fingerprint().split(' ').count(), 16);
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum E2eError {
+    InvalidPublicKey(String),
+    KeyChanged(String),
+    UnknownKey(String),
+    PayloadTooShort(usize),
+    DecryptionFailed(String),
+    InvalidUtf8(String),
+}
+
+impl Display for E2eError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            E2eError::InvalidPublicKey(username) => {
+                write!(f, "The public key of {} is not usable", username)
+            }
+            E2eError::KeyChanged(username) => write!(
+                f,
+                "The public key of {} changed, verify the new fingerprint before trusting it",
+                username
+            ),
+            E2eError::UnknownKey(username) => write!(f, "No public key known for {}", username),
+            E2eError::PayloadTooShort(length) => {
+                write!(f, "Encrypted payload of {} bytes is too short", length)
+            }
+            E2eError::DecryptionFailed(username) => {
+                write!(f, "Failed to decrypt message from {}", username)
+            }
+            E2eError::InvalidUtf8(username) => {
+                write!(f, "Decrypted message from {} is not valid UTF-8", username)
+            }
+        }
+    }
+}
@@ -0,0 +1,638 @@
+pub mod address;
+pub mod error;
+pub mod link;
+pub mod message;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    net::Shutdown,
+    sync::{
+        mpsc::{self, Sender},
+        Mutex, PoisonError,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use self::{
+    address::FederatedName,
+    error::FederationError,
+    link::LinkStream,
+    message::{LinkMessage, Presence, RelayedChat},
+};
+
+use crate::common::{
+    protocol::{
+        message::Message,
+        packet::{
+            server::{Chat, UserJoined, UserLeft},
+            Packet,
+        },
+    },
+    server_state::ServerState,
+};
+
+// How many relayed chats are remembered to drop copies arriving over a second route
+const SEEN_CHATS: usize = 4096;
+
+#[derive(Debug)]
+struct Link {
+    peer: String,
+    sender: Sender<LinkMessage>,
+}
+
+#[derive(Debug)]
+struct Origin {
+    presence: Presence,
+    // The link the newest record came over, the origin is unreachable once it goes down
+    link_id: u64,
+}
+
+// The view of every linked server. Remote users are shown as "name@server" and each server's
+// presence record is replaced only by a newer version of it, so a split and rejoin ends in the
+// same state however the records arrive.
+#[derive(Debug)]
+pub struct Federation {
+    server: String,
+    presence: Presence,
+    next_sequence: u64,
+    links: HashMap<u64, Link>,
+    next_link_id: u64,
+    origins: BTreeMap<String, Origin>,
+    // Keyed by origin, epoch and sequence
+    seen: HashSet<(String, u64, u64)>,
+    seen_order: VecDeque<(String, u64, u64)>,
+}
+
+impl Federation {
+    pub fn new(server: String) -> Federation {
+        let epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0);
+
+        Federation {
+            presence: Presence::new(server.clone(), epoch, 0, Vec::new()),
+            server,
+            next_sequence: 0,
+            links: HashMap::new(),
+            next_link_id: 0,
+            origins: BTreeMap::new(),
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn peers(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self.links.values().map(|link| link.peer.clone()).collect();
+        peers.sort();
+
+        peers
+    }
+
+    // Every user on other servers, sorted
+    pub fn remote_users(&self) -> Vec<FederatedName> {
+        self.origins
+            .values()
+            .flat_map(|origin| {
+                origin.presence.users.iter().map(|username| {
+                    FederatedName::new(username.clone(), origin.presence.origin.clone())
+                })
+            })
+            .collect()
+    }
+
+    pub fn is_present(&self, name: &FederatedName) -> bool {
+        match name.server == self.server {
+            true => self.presence.users.contains(&name.username),
+            false => self
+                .origins
+                .get(&name.server)
+                .is_some_and(|origin| origin.presence.users.contains(&name.username)),
+        }
+    }
+
+    // Starts sharing state with a peer that passed link::authenticate
+    pub fn add_link(
+        &mut self,
+        peer: String,
+        sender: Sender<LinkMessage>,
+    ) -> Result<u64, FederationError> {
+        if peer == self.server || self.links.values().any(|link| link.peer == peer) {
+            return Err(FederationError::AlreadyLinked(peer));
+        }
+
+        let link_id = self.next_link_id;
+        self.next_link_id += 1;
+        self.links.insert(link_id, Link { peer, sender });
+        self.send_presences(link_id);
+
+        Ok(link_id)
+    }
+
+    // Returns the UserLeft messages for everyone who was only reachable over the link
+    pub fn remove_link(&mut self, link_id: u64) -> Vec<Message> {
+        if self.links.remove(&link_id).is_none() {
+            return Vec::new();
+        }
+
+        let lost: Vec<String> = self
+            .origins
+            .iter()
+            .filter(|(_, origin)| origin.link_id == link_id)
+            .map(|(server, _)| server.clone())
+            .collect();
+
+        let mut messages = Vec::new();
+        for server in lost {
+            if let Some(origin) = self.origins.remove(&server) {
+                messages.extend(presence_changes(&origin.presence.users, &[], &server));
+            }
+        }
+
+        // In a mesh the lost servers may still be reachable another way
+        if !messages.is_empty() {
+            self.send_all(&LinkMessage::Resync, None);
+        }
+
+        messages
+    }
+
+    // Called with the local usernames whenever someone joins or leaves
+    pub fn set_local_users(&mut self, mut usernames: Vec<String>) {
+        usernames.sort();
+        if usernames == self.presence.users {
+            return;
+        }
+
+        self.presence.counter += 1;
+        self.presence.users = usernames;
+        self.send_all(&LinkMessage::Presence(self.presence.clone()), None);
+    }
+
+    // Passes a chat from a local user on to every linked server
+    pub fn relay_local(&mut self, chat: &Chat) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.mark_seen(self.server.clone(), self.presence.epoch, sequence);
+
+        let relayed_chat = RelayedChat::new(
+            self.server.clone(),
+            self.presence.epoch,
+            sequence,
+            vec![self.server.clone()],
+            chat.username.clone(),
+            chat.message.clone(),
+        );
+        self.send_all(&LinkMessage::Chat(relayed_chat), None);
+    }
+
+    // Returns what local users have to be told
+    pub fn receive(
+        &mut self,
+        link_id: u64,
+        message: LinkMessage,
+    ) -> Result<Vec<Message>, FederationError> {
+        match message {
+            LinkMessage::Presence(presence) => Ok(self.receive_presence(link_id, presence)),
+            LinkMessage::Chat(relayed_chat) => Ok(self.receive_chat(link_id, relayed_chat)),
+            LinkMessage::Resync => {
+                self.send_presences(link_id);
+                Ok(Vec::new())
+            }
+            message => Err(FederationError::UnexpectedMessage(message.to_string())),
+        }
+    }
+
+    // Serves an authenticated link until it closes, relaying into the server state's federation.
+    // Sending runs on its own thread, so a slow peer never holds up the reader.
+    pub fn serve_link(
+        state: &Mutex<ServerState>,
+        link_stream: LinkStream,
+        peer: String,
+    ) -> Result<(), FederationError> {
+        let mut writer = link_stream.try_clone()?;
+        let mut reader = link_stream;
+        let (sender, receiver) = mpsc::channel::<LinkMessage>();
+
+        let link_id = state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .federation_mut()
+            .ok_or(FederationError::NotFederated)?
+            .add_link(peer, sender)?;
+
+        thread::spawn(move || {
+            for message in receiver {
+                if writer.send_message(&message).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.connection().shutdown(Shutdown::Both);
+        });
+
+        let result = loop {
+            let message = match reader.read_message() {
+                Ok(message) => message,
+                Err(err) => break Err(err),
+            };
+
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            let messages = match state.federation_mut() {
+                Some(federation) => federation.receive(link_id, message),
+                None => Err(FederationError::NotFederated),
+            };
+            match messages {
+                Ok(messages) => state.relay_federated(messages),
+                Err(err) => break Err(err),
+            }
+        };
+
+        // Dropping the link's sender ends the writer thread
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(messages) = state
+            .federation_mut()
+            .map(|federation| federation.remove_link(link_id))
+        {
+            state.relay_federated(messages);
+        }
+        drop(state);
+        let _ = reader.connection().shutdown(Shutdown::Both);
+
+        result
+    }
+
+    fn receive_presence(&mut self, link_id: u64, mut presence: Presence) -> Vec<Message> {
+        if presence.origin == self.server {
+            return Vec::new();
+        }
+
+        let previous_users = match self.origins.get(&presence.origin) {
+            Some(origin) if origin.presence.version() >= presence.version() => return Vec::new(),
+            Some(origin) => origin.presence.users.clone(),
+            None => Vec::new(),
+        };
+
+        presence.users.sort();
+        presence.users.dedup();
+        let messages = presence_changes(&previous_users, &presence.users, &presence.origin);
+
+        self.send_all(&LinkMessage::Presence(presence.clone()), Some(link_id));
+        self.origins
+            .insert(presence.origin.clone(), Origin { presence, link_id });
+
+        messages
+    }
+
+    fn receive_chat(&mut self, link_id: u64, mut relayed_chat: RelayedChat) -> Vec<Message> {
+        if relayed_chat.origin == self.server
+            || relayed_chat.path.contains(&self.server)
+            || self.seen.contains(&(
+                relayed_chat.origin.clone(),
+                relayed_chat.epoch,
+                relayed_chat.sequence,
+            ))
+        {
+            return Vec::new();
+        }
+        self.mark_seen(
+            relayed_chat.origin.clone(),
+            relayed_chat.epoch,
+            relayed_chat.sequence,
+        );
+
+        let name = FederatedName::new(relayed_chat.username.clone(), relayed_chat.origin.clone());
+        let message = Chat::new(name.to_string(), relayed_chat.message.clone()).to_message();
+
+        relayed_chat.path.push(self.server.clone());
+        for (other_link_id, link) in &self.links {
+            if *other_link_id != link_id && !relayed_chat.path.contains(&link.peer) {
+                let _ = link.sender.send(LinkMessage::Chat(relayed_chat.clone()));
+            }
+        }
+
+        vec![message]
+    }
+
+    // Sends this server's record and every one learned over other links
+    fn send_presences(&self, link_id: u64) {
+        let link = match self.links.get(&link_id) {
+            Some(link) => link,
+            None => return,
+        };
+
+        let _ = link
+            .sender
+            .send(LinkMessage::Presence(self.presence.clone()));
+        for origin in self.origins.values() {
+            if origin.link_id != link_id {
+                let _ = link
+                    .sender
+                    .send(LinkMessage::Presence(origin.presence.clone()));
+            }
+        }
+    }
+
+    fn send_all(&self, message: &LinkMessage, except: Option<u64>) {
+        // A dropped receiver means the link is gone, its serve_link removes it on the way out
+        for (link_id, link) in &self.links {
+            if Some(*link_id) != except {
+                let _ = link.sender.send(message.clone());
+            }
+        }
+    }
+
+    fn mark_seen(&mut self, origin: String, epoch: u64, sequence: u64) {
+        if self.seen_order.len() == SEEN_CHATS {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        self.seen.insert((origin.clone(), epoch, sequence));
+        self.seen_order.push_back((origin, epoch, sequence));
+    }
+}
+
+// UserLeft for everyone gone, then UserJoined for everyone new, each in sorted order
+fn presence_changes(previous: &[String], current: &[String], server: &str) -> Vec<Message> {
+    let previous: BTreeSet<&String> = previous.iter().collect();
+    let current: BTreeSet<&String> = current.iter().collect();
+
+    let left = previous.difference(&current).map(|username| {
+        UserLeft::new(FederatedName::new((*username).clone(), server.to_owned()).to_string())
+            .to_message()
+    });
+    let joined = current.difference(&previous).map(|username| {
+        UserJoined::new(FederatedName::new((*username).clone(), server.to_owned()).to_string())
+            .to_message()
+    });
+
+    left.chain(joined).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        net::{TcpListener, TcpStream},
+        sync::mpsc::Receiver,
+    };
+
+    use ed25519_dalek::SigningKey;
+
+    use crate::common::federation::link::{self, LinkPeer};
+
+    struct Pipe {
+        receiver: Receiver<LinkMessage>,
+        from: usize,
+        to: usize,
+        link_id: u64,
+    }
+
+    // Servers linked by channels, delivering link messages until every pipe is drained
+    struct Network {
+        servers: Vec<Federation>,
+        pipes: Vec<Pipe>,
+    }
+
+    impl Network {
+        fn new(names: &[&str]) -> Network {
+            Network {
+                servers: names
+                    .iter()
+                    .map(|name| Federation::new((*name).to_owned()))
+                    .collect(),
+                pipes: Vec::new(),
+            }
+        }
+
+        fn link(&mut self, a: usize, b: usize) {
+            let (a_sender, a_receiver) = mpsc::channel();
+            let (b_sender, b_receiver) = mpsc::channel();
+            let b_name = self.servers[b].server().to_owned();
+            let a_name = self.servers[a].server().to_owned();
+
+            let a_link_id = self.servers[a]
+                .add_link(b_name, a_sender)
+                .unwrap_or_else(|err| panic!("Failed to link: {}", err));
+            let b_link_id = self.servers[b]
+                .add_link(a_name, b_sender)
+                .unwrap_or_else(|err| panic!("Failed to link: {}", err));
+
+            self.pipes.push(Pipe {
+                receiver: a_receiver,
+                from: a,
+                to: b,
+                link_id: b_link_id,
+            });
+            self.pipes.push(Pipe {
+                receiver: b_receiver,
+                from: b,
+                to: a,
+                link_id: a_link_id,
+            });
+        }
+
+        // Returns what each side's local users were told about the other going away
+        fn split(&mut self, a: usize, b: usize) -> (Vec<Message>, Vec<Message>) {
+            let mut told = (Vec::new(), Vec::new());
+
+            for pipe in &self.pipes {
+                if pipe.from == b && pipe.to == a {
+                    told.0 = self.servers[a].remove_link(pipe.link_id);
+                }
+                if pipe.from == a && pipe.to == b {
+                    told.1 = self.servers[b].remove_link(pipe.link_id);
+                }
+            }
+            self.pipes
+                .retain(|pipe| !(pipe.from == a && pipe.to == b || pipe.from == b && pipe.to == a));
+
+            told
+        }
+
+        fn deliver(&mut self) -> Vec<Vec<Message>> {
+            let mut told = vec![Vec::new(); self.servers.len()];
+
+            loop {
+                let mut delivered = false;
+                for pipe in &self.pipes {
+                    while let Ok(message) = pipe.receiver.try_recv() {
+                        delivered = true;
+                        let messages = self.servers[pipe.to]
+                            .receive(pipe.link_id, message)
+                            .unwrap_or_else(|err| panic!("Failed to receive: {}", err));
+                        told[pipe.to].extend(messages);
+                    }
+                }
+
+                if !delivered {
+                    return told;
+                }
+            }
+        }
+    }
+
+    fn names(federation: &Federation) -> Vec<String> {
+        federation
+            .remote_users()
+            .iter()
+            .map(FederatedName::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn federation_relays_chat_once_around_loops() {
+        let mut network = Network::new(&["berlin", "paris", "tokyo"]);
+        network.link(0, 1);
+        network.link(1, 2);
+        network.link(2, 0);
+
+        network.servers[0].set_local_users(vec![String::from("Kitt3120")]);
+        network.deliver();
+        assert_eq!(names(&network.servers[1]), vec!["Kitt3120@berlin"]);
+        assert_eq!(names(&network.servers[2]), vec!["Kitt3120@berlin"]);
+
+        network.servers[0].relay_local(&Chat::new(String::from("Kitt3120"), String::from("⚡")));
+        let told = network.deliver();
+        let expected =
+            vec![Chat::new(String::from("Kitt3120@berlin"), String::from("⚡")).to_message()];
+        assert!(told[0].is_empty());
+        assert_eq!(told[1], expected);
+        assert_eq!(told[2], expected);
+
+        // Tokyo is still reachable through Paris
+        network.split(0, 2);
+        network.deliver();
+        assert_eq!(names(&network.servers[2]), vec!["Kitt3120@berlin"]);
+        assert!(network.servers[2].is_present(&FederatedName::new(
+            String::from("Kitt3120"),
+            String::from("berlin")
+        )));
+    }
+
+    #[test]
+    fn federation_reconciles_after_split() {
+        let mut network = Network::new(&["berlin", "paris"]);
+        network.link(0, 1);
+        network.servers[0].set_local_users(vec![String::from("alice")]);
+        network.servers[1].set_local_users(vec![String::from("bob")]);
+        network.deliver();
+
+        let (berlin_told, paris_told) = network.split(0, 1);
+        assert_eq!(
+            berlin_told,
+            vec![UserLeft::new(String::from("bob@paris")).to_message()]
+        );
+        assert_eq!(
+            paris_told,
+            vec![UserLeft::new(String::from("alice@berlin")).to_message()]
+        );
+
+        network.servers[0].set_local_users(vec![String::from("carol"), String::from("alice")]);
+        network.servers[1].set_local_users(Vec::new());
+
+        network.link(1, 0);
+        let told = network.deliver();
+        assert!(told[0].is_empty());
+        assert_eq!(
+            told[1],
+            vec![
+                UserJoined::new(String::from("alice@berlin")).to_message(),
+                UserJoined::new(String::from("carol@berlin")).to_message(),
+            ]
+        );
+        assert_eq!(
+            names(&network.servers[1]),
+            vec!["alice@berlin", "carol@berlin"]
+        );
+        assert!(names(&network.servers[0]).is_empty());
+    }
+
+    #[test]
+    fn federation_relays_chat_from_restarted_origin() {
+        let mut network = Network::new(&["berlin", "paris"]);
+        network.link(0, 1);
+        network.servers[0].relay_local(&Chat::new(String::from("alice"), String::from("Hi")));
+        network.deliver();
+
+        // Berlin comes back with its sequence starting over
+        let epoch = network.servers[0].presence.epoch;
+        network.split(0, 1);
+        network.servers[0] = Federation::new(String::from("berlin"));
+        network.servers[0].presence.epoch = epoch + 1;
+        network.link(0, 1);
+        network.deliver();
+
+        network.servers[0].relay_local(&Chat::new(String::from("alice"), String::from("Back")));
+        let told = network.deliver();
+        assert_eq!(
+            told[1],
+            vec![Chat::new(String::from("alice@berlin"), String::from("Back")).to_message()]
+        );
+    }
+
+    #[test]
+    fn link_authenticates_known_peers() {
+        let berlin_key = SigningKey::from_bytes(&[1; 32]);
+        let paris_key = SigningKey::from_bytes(&[2; 32]);
+        let peers = vec![
+            LinkPeer::new(
+                String::from("berlin"),
+                berlin_key.verifying_key().to_bytes(),
+            ),
+            LinkPeer::new(String::from("paris"), paris_key.verifying_key().to_bytes()),
+        ];
+
+        let authenticate = |paris_key: SigningKey| {
+            let listener = TcpListener::bind("127.0.0.1:0")
+                .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+            let address = listener
+                .local_addr()
+                .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+            let paris_peers = peers.clone();
+            let paris = thread::spawn(move || {
+                let tcp_stream = TcpStream::connect(address)
+                    .unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+                link::authenticate(
+                    &mut LinkStream::new(tcp_stream),
+                    "paris",
+                    &paris_key,
+                    &paris_peers,
+                )
+            });
+
+            let (tcp_stream, _) = listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            let berlin_result = link::authenticate(
+                &mut LinkStream::new(tcp_stream),
+                "berlin",
+                &berlin_key,
+                &peers,
+            );
+            let paris_result = paris
+                .join()
+                .unwrap_or_else(|_| panic!("Paris thread panicked"));
+
+            (berlin_result, paris_result)
+        };
+
+        let (berlin_result, paris_result) = authenticate(paris_key);
+        assert!(berlin_result.is_ok_and(|peer| peer.server == "paris"));
+        assert!(paris_result.is_ok_and(|peer| peer.server == "berlin"));
+
+        let (berlin_result, _) = authenticate(SigningKey::from_bytes(&[3; 32]));
+        assert!(matches!(
+            berlin_result,
+            Err(FederationError::UnknownPeer(server)) if server == "paris"
+        ));
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt::Display;
+
+// A user on another server, written "name@server". Usernames can't contain '@', so the
+// last one separates the server.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FederatedName {
+    pub username: String,
+    pub server: String,
+}
+
+impl FederatedName {
+    pub fn new(username: String, server: String) -> FederatedName {
+        FederatedName { username, server }
+    }
+
+    pub fn parse(name: &str) -> Option<FederatedName> {
+        match name.rsplit_once('@') {
+            Some((username, server)) if !username.is_empty() && !server.is_empty() => {
+                Some(FederatedName::new(username.to_owned(), server.to_owned()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for FederatedName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.username, self.server)
+    }
+}
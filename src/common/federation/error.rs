@@ -0,0 +1,44 @@
+use std::{fmt::Display, io::Error};
+
+use crate::common::protocol::error::MessageParseError;
+
+#[derive(Debug)]
+pub enum FederationError {
+    IoError(Error),
+    MessageParseError(MessageParseError),
+    MessageTooLarge(usize),
+    UnexpectedMessage(String),
+    UnknownPeer(String),
+    InvalidSignature(String),
+    AlreadyLinked(String),
+    NotFederated,
+}
+
+impl Display for FederationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FederationError::IoError(e) => write!(f, "IoError on server link: {}", e),
+            FederationError::MessageParseError(e) => {
+                write!(f, "Error while parsing link message: {}", e)
+            }
+            FederationError::MessageTooLarge(limit) => {
+                write!(
+                    f,
+                    "Link message exceeded the maximum size of {} bytes",
+                    limit
+                )
+            }
+            FederationError::UnexpectedMessage(kind) => {
+                write!(f, "Unexpected link message: {}", kind)
+            }
+            FederationError::UnknownPeer(server) => write!(f, "{} is not a known peer", server),
+            FederationError::InvalidSignature(server) => {
+                write!(f, "Invalid link signature from {}", server)
+            }
+            FederationError::AlreadyLinked(server) => {
+                write!(f, "Already linked to {}", server)
+            }
+            FederationError::NotFederated => write!(f, "Federation is not enabled on this server"),
+        }
+    }
+}
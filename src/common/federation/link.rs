@@ -0,0 +1,154 @@
+use std::io::{Read, Write};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+
+use super::{error::FederationError, message::LinkMessage};
+
+use crate::common::{
+    message_stream::{connection::Connection, MAX_MESSAGE_SIZE},
+    protocol::{serializable::Serializable, wire},
+};
+
+const LINK_CONTEXT: &[u8] = b"rusty_chat federation v1";
+const FRAME_HEADER_SIZE: usize = 4;
+
+// A server this one may link with, identified by its Ed25519 key
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkPeer {
+    pub server: String,
+    pub public_key: [u8; 32],
+}
+
+impl LinkPeer {
+    pub fn new(server: String, public_key: [u8; 32]) -> LinkPeer {
+        LinkPeer { server, public_key }
+    }
+}
+
+// Frames LinkMessages like MessageStream frames Messages: a little-endian u32 length, then the message
+#[derive(Debug)]
+pub struct LinkStream {
+    connection: Connection,
+}
+
+impl LinkStream {
+    pub fn new(connection: impl Into<Connection>) -> LinkStream {
+        LinkStream {
+            connection: connection.into(),
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn try_clone(&self) -> Result<LinkStream, FederationError> {
+        let connection = self
+            .connection
+            .try_clone()
+            .map_err(FederationError::IoError)?;
+
+        Ok(LinkStream { connection })
+    }
+
+    pub fn read_message(&mut self) -> Result<LinkMessage, FederationError> {
+        let mut header = [0; FRAME_HEADER_SIZE];
+        self.connection
+            .read_exact(&mut header)
+            .map_err(FederationError::IoError)?;
+
+        let message_length = u32::from_le_bytes(header) as usize;
+        if message_length > MAX_MESSAGE_SIZE {
+            return Err(FederationError::MessageTooLarge(MAX_MESSAGE_SIZE));
+        }
+
+        let mut message_buffer = vec![0; message_length];
+        self.connection
+            .read_exact(&mut message_buffer)
+            .map_err(FederationError::IoError)?;
+
+        LinkMessage::from_bytes(&message_buffer).map_err(FederationError::MessageParseError)
+    }
+
+    pub fn send_message(&mut self, message: &LinkMessage) -> Result<(), FederationError> {
+        let message_bytes = message.as_bytes();
+        if message_bytes.len() > MAX_MESSAGE_SIZE {
+            return Err(FederationError::MessageTooLarge(MAX_MESSAGE_SIZE));
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + message_bytes.len());
+        frame.extend((message_bytes.len() as u32).to_le_bytes());
+        frame.extend(message_bytes);
+
+        self.connection
+            .write_all(&frame)
+            .map_err(FederationError::IoError)
+    }
+}
+
+// Both sides introduce themselves with a fresh nonce and then sign both nonces, so a proof can't
+// be replayed on another link. Returns the peer, which has to be one of the known ones.
+pub fn authenticate(
+    link_stream: &mut LinkStream,
+    server: &str,
+    signing_key: &SigningKey,
+    peers: &[LinkPeer],
+) -> Result<LinkPeer, FederationError> {
+    let mut nonce = [0; 32];
+    OsRng.fill_bytes(&mut nonce);
+
+    link_stream.send_message(&LinkMessage::Hello {
+        server: server.to_owned(),
+        public_key: signing_key.verifying_key().to_bytes(),
+        nonce,
+    })?;
+
+    let (peer, peer_nonce) = match link_stream.read_message()? {
+        LinkMessage::Hello {
+            server: peer_server,
+            public_key,
+            nonce: peer_nonce,
+        } => match peers
+            .iter()
+            .find(|peer| peer.server == peer_server && peer.public_key == public_key)
+        {
+            Some(peer) if peer.server != server => (peer.clone(), peer_nonce),
+            _ => return Err(FederationError::UnknownPeer(peer_server)),
+        },
+        message => return Err(FederationError::UnexpectedMessage(message.to_string())),
+    };
+
+    let signature = signing_key.sign(&proof_payload(server, &peer_nonce, &nonce));
+    link_stream.send_message(&LinkMessage::Proof {
+        signature: signature.to_bytes().to_vec(),
+    })?;
+
+    let signature = match link_stream.read_message()? {
+        LinkMessage::Proof { signature } => signature,
+        message => return Err(FederationError::UnexpectedMessage(message.to_string())),
+    };
+
+    let invalid_signature = || FederationError::InvalidSignature(peer.server.clone());
+    let verifying_key =
+        VerifyingKey::from_bytes(&peer.public_key).map_err(|_| invalid_signature())?;
+    let signature = Signature::from_slice(&signature).map_err(|_| invalid_signature())?;
+    verifying_key
+        .verify_strict(
+            &proof_payload(&peer.server, &nonce, &peer_nonce),
+            &signature,
+        )
+        .map_err(|_| invalid_signature())?;
+
+    Ok(peer)
+}
+
+// What the signer signs: its own name, the nonce it was challenged with and its own nonce
+fn proof_payload(signer: &str, challenge_nonce: &[u8; 32], signer_nonce: &[u8; 32]) -> Vec<u8> {
+    let mut payload = LINK_CONTEXT.to_vec();
+    wire::write_str(&mut payload, signer);
+    payload.extend_from_slice(challenge_nonce);
+    payload.extend_from_slice(signer_nonce);
+
+    payload
+}
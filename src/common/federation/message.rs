@@ -0,0 +1,258 @@
+use std::fmt::Display;
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+
+// The users connected to a server. Only the origin server changes its record and every change
+// gets a higher version, so the newest one wins no matter which way it arrived.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Presence {
+    pub origin: String,
+    // Set from the clock when the server starts, so a restarted server outranks its old records
+    pub epoch: u64,
+    pub counter: u64,
+    pub users: Vec<String>,
+}
+
+impl Presence {
+    pub fn new(origin: String, epoch: u64, counter: u64, users: Vec<String>) -> Presence {
+        Presence {
+            origin,
+            epoch,
+            counter,
+            users,
+        }
+    }
+
+    pub fn version(&self) -> (u64, u64) {
+        (self.epoch, self.counter)
+    }
+}
+
+// A server::Chat from a user on the origin server, numbered by the origin to drop duplicates.
+// The path lists every server it passed, so it is never sent back to one of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelayedChat {
+    pub origin: String,
+    // The origin's presence epoch, its sequence starts over whenever it restarts
+    pub epoch: u64,
+    pub sequence: u64,
+    pub path: Vec<String>,
+    pub username: String,
+    pub message: String,
+}
+
+impl RelayedChat {
+    pub fn new(
+        origin: String,
+        epoch: u64,
+        sequence: u64,
+        path: Vec<String>,
+        username: String,
+        message: String,
+    ) -> RelayedChat {
+        RelayedChat {
+            origin,
+            epoch,
+            sequence,
+            path,
+            username,
+            message,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkMessage {
+    Hello {
+        server: String,
+        public_key: [u8; 32],
+        nonce: [u8; 32],
+    },
+    Proof {
+        signature: Vec<u8>,
+    },
+    Presence(Presence),
+    Chat(RelayedChat),
+    // Asks the peer for every presence record it has, after another link went down
+    Resync,
+}
+
+impl LinkMessage {
+    fn id(&self) -> u8 {
+        match self {
+            LinkMessage::Hello { .. } => 0,
+            LinkMessage::Proof { .. } => 1,
+            LinkMessage::Presence(_) => 2,
+            LinkMessage::Chat(_) => 3,
+            LinkMessage::Resync => 4,
+        }
+    }
+}
+
+impl Display for LinkMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkMessage::Hello { server, .. } => write!(f, "Hello({})", server),
+            LinkMessage::Proof { .. } => write!(f, "Proof"),
+            LinkMessage::Presence(presence) => write!(
+                f,
+                "Presence({}, {}.{}, {} users)",
+                presence.origin,
+                presence.epoch,
+                presence.counter,
+                presence.users.len()
+            ),
+            LinkMessage::Chat(chat) => write!(
+                f,
+                "Chat({}@{}, {})",
+                chat.username, chat.origin, chat.message
+            ),
+            LinkMessage::Resync => write!(f, "Resync"),
+        }
+    }
+}
+
+impl Serializable for LinkMessage {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.id()];
+
+        match self {
+            LinkMessage::Hello {
+                server,
+                public_key,
+                nonce,
+            } => {
+                bytes.extend_from_slice(public_key);
+                bytes.extend_from_slice(nonce);
+                bytes.extend(server.as_bytes());
+            }
+            LinkMessage::Proof { signature } => bytes.extend(signature),
+            LinkMessage::Presence(presence) => {
+                wire::write_str(&mut bytes, &presence.origin);
+                wire::write_u64(&mut bytes, presence.epoch);
+                wire::write_u64(&mut bytes, presence.counter);
+                write_strs(&mut bytes, &presence.users);
+            }
+            LinkMessage::Chat(chat) => {
+                wire::write_str(&mut bytes, &chat.origin);
+                wire::write_u64(&mut bytes, chat.epoch);
+                wire::write_u64(&mut bytes, chat.sequence);
+                write_strs(&mut bytes, &chat.path);
+                wire::write_str(&mut bytes, &chat.username);
+                bytes.extend(chat.message.as_bytes());
+            }
+            LinkMessage::Resync => {}
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<LinkMessage, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let message = match reader
+            .read_u8()
+            .map_err(|_| MessageParseError::MessageEmpty)?
+        {
+            0 => LinkMessage::Hello {
+                public_key: reader.read_array("Public key")?,
+                nonce: reader.read_array("Nonce")?,
+                server: reader.read_remaining_str("Server")?.to_owned(),
+            },
+            1 => LinkMessage::Proof {
+                signature: reader.read_remaining_bytes().to_vec(),
+            },
+            2 => LinkMessage::Presence(Presence::new(
+                reader.read_str("Origin")?.to_owned(),
+                reader.read_u64("Epoch")?,
+                reader.read_u64("Counter")?,
+                read_strs(&mut reader, "Users")?,
+            )),
+            3 => LinkMessage::Chat(RelayedChat::new(
+                reader.read_str("Origin")?.to_owned(),
+                reader.read_u64("Epoch")?,
+                reader.read_u64("Sequence")?,
+                read_strs(&mut reader, "Path")?,
+                reader.read_str("Username")?.to_owned(),
+                reader.read_remaining_str("Message")?.to_owned(),
+            )),
+            4 => LinkMessage::Resync,
+            kind => return Err(MessageParseError::UnknownKind(kind)),
+        };
+
+        Ok(message)
+    }
+}
+
+fn write_strs(bytes: &mut Vec<u8>, values: &[String]) {
+    bytes.extend(values.len().to_le_bytes());
+    values
+        .iter()
+        .for_each(|value| wire::write_str(bytes, value));
+}
+
+// The count isn't trusted for allocating, a short message runs out of bytes first
+fn read_strs(reader: &mut WireReader, value: &str) -> Result<Vec<String>, MessageParseError> {
+    let count = reader.read_usize(value)?;
+
+    let mut values = Vec::new();
+    for _ in 0..count {
+        values.push(reader.read_str(value)?.to_owned());
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_messages_round_trip() {
+        let messages = [
+            LinkMessage::Hello {
+                server: String::from("berlin"),
+                public_key: [1; 32],
+                nonce: [2; 32],
+            },
+            LinkMessage::Proof {
+                signature: vec![3; 64],
+            },
+            LinkMessage::Presence(Presence::new(
+                String::from("berlin"),
+                1_700_000_000_000,
+                4,
+                vec![String::from("Kitt3120"), String::from("ops")],
+            )),
+            LinkMessage::Chat(RelayedChat::new(
+                String::from("berlin"),
+                1_700_000_000_000,
+                9,
+                vec![String::from("berlin"), String::from("paris")],
+                String::from("Kitt3120"),
+                String::from("⚡"),
+            )),
+            LinkMessage::Resync,
+        ];
+
+        for message in messages {
+            let parsed = LinkMessage::from_bytes(&message.as_bytes())
+                .unwrap_or_else(|err| panic!("Failed to parse {}: {}", message, err));
+            assert_eq!(parsed, message);
+        }
+
+        let mut bytes = vec![2];
+        wire::write_str(&mut bytes, "berlin");
+        wire::write_u64(&mut bytes, 1);
+        wire::write_u64(&mut bytes, 1);
+        bytes.extend(usize::MAX.to_le_bytes());
+        assert_eq!(
+            LinkMessage::from_bytes(&bytes),
+            Err(MessageParseError::UnexcpetedEndOfMessage)
+        );
+    }
+}
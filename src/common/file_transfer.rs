@@ -0,0 +1,7 @@
+pub mod error;
+pub mod store;
+pub mod transfer;
+
+pub use error::FileTransferError;
+pub use store::{FileStore, FileStoreConfig};
+pub use transfer::{FileDownload, FileUpload, UploadChunks};
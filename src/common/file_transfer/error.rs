@@ -0,0 +1,46 @@
+use std::{fmt::Display, io::Error};
+
+#[derive(Debug)]
+pub enum FileTransferError {
+    IoError(Error),
+    InvalidName(String),
+    EmptyFile,
+    TooLarge(u64),
+    QuotaExceeded(u64),
+    UnknownFile(u64),
+    UnexpectedOffset(u64),
+    ChunkTooLarge(usize),
+    SizeMismatch(u64),
+    HashMismatch(String),
+}
+
+impl Display for FileTransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FileTransferError::IoError(e) => write!(f, "IoError during file transfer: {}", e),
+            FileTransferError::InvalidName(name) => write!(f, "Invalid file name {:?}", name),
+            FileTransferError::EmptyFile => write!(f, "Empty files can't be shared"),
+            FileTransferError::TooLarge(limit) => {
+                write!(f, "File exceeds the maximum size of {} bytes", limit)
+            }
+            FileTransferError::QuotaExceeded(quota) => {
+                write!(f, "File exceeds the storage quota of {} bytes", quota)
+            }
+            FileTransferError::UnknownFile(file_id) => {
+                write!(f, "Unknown or expired file {:016x}", file_id)
+            }
+            FileTransferError::UnexpectedOffset(expected) => {
+                write!(f, "Expected a chunk at offset {}", expected)
+            }
+            FileTransferError::ChunkTooLarge(limit) => {
+                write!(f, "Chunk exceeds the maximum size of {} bytes", limit)
+            }
+            FileTransferError::SizeMismatch(size) => {
+                write!(f, "Chunk runs past the announced size of {} bytes", size)
+            }
+            FileTransferError::HashMismatch(name) => {
+                write!(f, "{} doesn't match its announced hash", name)
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use super::error::FileTransferError;
+
+use crate::common::protocol::{
+    packet::{
+        client::{self, OfferFile, RequestChunk},
+        server::{self, Attachment, UploadReady},
+        FileInfo, MAX_CHUNK_SIZE,
+    },
+    username_policy::UsernamePolicy,
+};
+
+const MAX_NAME_LENGTH: usize = 255;
+const UPLOAD_EXTENSION: &str = "upload";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileStoreConfig {
+    pub directory: PathBuf,
+    pub max_file_size: u64,
+    // Per user, counting unfinished uploads
+    pub quota: u64,
+    // Counted from the last chunk for unfinished uploads and from completion for shared files
+    pub expiry: Duration,
+}
+
+impl FileStoreConfig {
+    pub fn new(directory: PathBuf) -> FileStoreConfig {
+        FileStoreConfig {
+            directory,
+            max_file_size: 25 * 1024 * 1024,
+            quota: 100 * 1024 * 1024,
+            expiry: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StoredFile {
+    owner: String,
+    info: FileInfo,
+    received: u64,
+    hasher: Sha256,
+    expires_at: Instant,
+}
+
+impl StoredFile {
+    fn is_complete(&self) -> bool {
+        self.received == self.info.size
+    }
+}
+
+// Holds uploads on disk until they expire. Files are named by their random id, never by the name a client sent.
+#[derive(Debug)]
+pub struct FileStore {
+    config: FileStoreConfig,
+    files: HashMap<u64, StoredFile>,
+}
+
+impl FileStore {
+    // Files left behind by a previous run are deleted, their ids are gone with it
+    pub fn new(config: FileStoreConfig) -> Result<FileStore, FileTransferError> {
+        fs::create_dir_all(&config.directory).map_err(FileTransferError::IoError)?;
+
+        for entry in fs::read_dir(&config.directory).map_err(FileTransferError::IoError)? {
+            let path = entry.map_err(FileTransferError::IoError)?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == UPLOAD_EXTENSION)
+            {
+                fs::remove_file(&path).map_err(FileTransferError::IoError)?;
+            }
+        }
+
+        Ok(FileStore {
+            config,
+            files: HashMap::new(),
+        })
+    }
+
+    pub fn file(&self, file_id: u64) -> Option<&FileInfo> {
+        self.files
+            .get(&file_id)
+            .filter(|file| file.is_complete())
+            .map(|file| &file.info)
+    }
+
+    pub fn usage(&self, username_policy: &UsernamePolicy, username: &str) -> u64 {
+        let owner = username_policy.canonicalize(username);
+
+        self.files
+            .values()
+            .filter(|file| file.owner == owner)
+            .map(|file| file.info.size)
+            .sum()
+    }
+
+    // Offering a file again continues an unfinished upload of it
+    pub fn offer(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        offer_file: &OfferFile,
+    ) -> Result<UploadReady, FileTransferError> {
+        self.remove_expired();
+
+        let info = &offer_file.file;
+        if info.name.is_empty() || info.name.len() > MAX_NAME_LENGTH {
+            return Err(FileTransferError::InvalidName(info.name.clone()));
+        }
+        if info.size == 0 {
+            return Err(FileTransferError::EmptyFile);
+        }
+        if info.size > self.config.max_file_size {
+            return Err(FileTransferError::TooLarge(self.config.max_file_size));
+        }
+
+        let owner = username_policy.canonicalize(username);
+        let expires_at = Instant::now() + self.config.expiry;
+
+        if let Some((file_id, file)) = self
+            .files
+            .iter_mut()
+            .find(|(_, file)| file.owner == owner && file.info == *info && !file.is_complete())
+        {
+            file.expires_at = expires_at;
+            return Ok(UploadReady::new(*file_id, file.received));
+        }
+
+        if self.usage(username_policy, username) + info.size > self.config.quota {
+            return Err(FileTransferError::QuotaExceeded(self.config.quota));
+        }
+
+        let file_id = loop {
+            let file_id = OsRng.next_u64();
+            if !self.files.contains_key(&file_id) {
+                break file_id;
+            }
+        };
+        File::create(self.path(file_id)).map_err(FileTransferError::IoError)?;
+
+        self.files.insert(
+            file_id,
+            StoredFile {
+                owner,
+                info: info.clone(),
+                received: 0,
+                hasher: Sha256::new(),
+                expires_at,
+            },
+        );
+
+        Ok(UploadReady::new(file_id, 0))
+    }
+
+    // Returns the attachment to broadcast once the last chunk arrived and the hash checks out
+    pub fn write_chunk(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        file_chunk: &client::FileChunk,
+    ) -> Result<Option<Attachment>, FileTransferError> {
+        let owner = username_policy.canonicalize(username);
+        let path = self.path(file_chunk.file_id);
+
+        let file = match self.files.get_mut(&file_chunk.file_id) {
+            Some(file) if file.owner == owner && !file.is_complete() => file,
+            _ => return Err(FileTransferError::UnknownFile(file_chunk.file_id)),
+        };
+
+        if file_chunk.data.len() > MAX_CHUNK_SIZE {
+            return Err(FileTransferError::ChunkTooLarge(MAX_CHUNK_SIZE));
+        }
+        if file_chunk.offset != file.received {
+            return Err(FileTransferError::UnexpectedOffset(file.received));
+        }
+        if file.received + file_chunk.data.len() as u64 > file.info.size {
+            return Err(FileTransferError::SizeMismatch(file.info.size));
+        }
+
+        let written = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut disk_file| disk_file.write_all(&file_chunk.data));
+        if let Err(err) = written {
+            // What made it to disk is unknown now, so the upload can't be resumed
+            self.remove(file_chunk.file_id);
+            return Err(FileTransferError::IoError(err));
+        }
+
+        file.hasher.update(&file_chunk.data);
+        file.received += file_chunk.data.len() as u64;
+        file.expires_at = Instant::now() + self.config.expiry;
+
+        if !file.is_complete() {
+            return Ok(None);
+        }
+
+        if <[u8; 32]>::from(file.hasher.finalize_reset()) != file.info.hash {
+            let name = file.info.name.clone();
+            self.remove(file_chunk.file_id);
+            return Err(FileTransferError::HashMismatch(name));
+        }
+
+        Ok(Some(Attachment::new(
+            username.to_owned(),
+            file_chunk.file_id,
+            file.info.clone(),
+        )))
+    }
+
+    pub fn read_chunk(
+        &mut self,
+        request_chunk: &RequestChunk,
+    ) -> Result<server::FileChunk, FileTransferError> {
+        self.remove_expired();
+
+        let size = match self.file(request_chunk.file_id) {
+            Some(info) => info.size,
+            None => return Err(FileTransferError::UnknownFile(request_chunk.file_id)),
+        };
+        if request_chunk.offset > size {
+            return Err(FileTransferError::UnexpectedOffset(size));
+        }
+
+        let length = request_chunk.length.min(MAX_CHUNK_SIZE as u64);
+        let mut data = Vec::new();
+
+        let mut disk_file =
+            File::open(self.path(request_chunk.file_id)).map_err(FileTransferError::IoError)?;
+        disk_file
+            .seek(SeekFrom::Start(request_chunk.offset))
+            .and_then(|_| disk_file.take(length).read_to_end(&mut data))
+            .map_err(FileTransferError::IoError)?;
+
+        Ok(server::FileChunk::new(
+            request_chunk.file_id,
+            request_chunk.offset,
+            data,
+        ))
+    }
+
+    // Returns the number of files removed
+    pub fn remove_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<u64> = self
+            .files
+            .iter()
+            .filter(|(_, file)| file.expires_at <= now)
+            .map(|(file_id, _)| *file_id)
+            .collect();
+
+        for file_id in &expired {
+            self.remove(*file_id);
+        }
+
+        expired.len()
+    }
+
+    fn remove(&mut self, file_id: u64) {
+        self.files.remove(&file_id);
+        let _ = fs::remove_file(self.path(file_id));
+    }
+
+    fn path(&self, file_id: u64) -> PathBuf {
+        self.config
+            .directory
+            .join(format!("{:016x}.{}", file_id, UPLOAD_EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::file_transfer::{FileDownload, FileUpload};
+
+    fn directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty_chat_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn file_store_resumes_uploads_and_serves_downloads() {
+        let directory = directory("file_store");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory)
+            .unwrap_or_else(|err| panic!("Failed to create directory: {}", err));
+
+        let source = directory.join("trace.log");
+        let content = "thread 'main' panicked at src/lib.rs:1\n".repeat(5000);
+        fs::write(&source, &content).unwrap_or_else(|err| panic!("Failed to write file: {}", err));
+
+        let username_policy = UsernamePolicy::new();
+        let mut file_store = FileStore::new(FileStoreConfig::new(directory.join("store")))
+            .unwrap_or_else(|err| panic!("Failed to create file store: {}", err));
+        let upload = FileUpload::open(&source, "text/plain")
+            .unwrap_or_else(|err| panic!("Failed to open upload: {}", err));
+
+        // The connection drops after the first chunk
+        let upload_ready = file_store
+            .offer(&username_policy, "Alice", &upload.offer())
+            .unwrap_or_else(|err| panic!("Failed to offer file: {}", err));
+        let first_chunk = upload
+            .chunks(&upload_ready)
+            .unwrap_or_else(|err| panic!("Failed to read chunks: {}", err))
+            .next()
+            .unwrap_or_else(|| panic!("No chunks"))
+            .unwrap_or_else(|err| panic!("Failed to read chunk: {}", err));
+        assert!(matches!(
+            file_store.write_chunk(&username_policy, "Alice", &first_chunk),
+            Ok(None)
+        ));
+
+        let resumed = file_store
+            .offer(&username_policy, "alice", &upload.offer())
+            .unwrap_or_else(|err| panic!("Failed to offer file: {}", err));
+        assert_eq!(
+            resumed,
+            UploadReady::new(upload_ready.file_id, MAX_CHUNK_SIZE as u64)
+        );
+
+        let mut attachment = None;
+        for chunk in upload
+            .chunks(&resumed)
+            .unwrap_or_else(|err| panic!("Failed to read chunks: {}", err))
+        {
+            let chunk = chunk.unwrap_or_else(|err| panic!("Failed to read chunk: {}", err));
+            attachment = file_store
+                .write_chunk(&username_policy, "Alice", &chunk)
+                .unwrap_or_else(|err| panic!("Failed to write chunk: {}", err));
+        }
+        let attachment = attachment.unwrap_or_else(|| panic!("Upload didn't complete"));
+        assert_eq!(attachment.file, upload.info().clone());
+
+        let target = directory.join("download.log");
+        let mut download = FileDownload::create(attachment, &target)
+            .unwrap_or_else(|err| panic!("Failed to create download: {}", err));
+        while let Some(request_chunk) = download.next_request() {
+            let file_chunk = file_store
+                .read_chunk(&request_chunk)
+                .unwrap_or_else(|err| panic!("Failed to read chunk: {}", err));
+            download
+                .write_chunk(&file_chunk)
+                .unwrap_or_else(|err| panic!("Failed to write chunk: {}", err));
+        }
+        assert_eq!(fs::read_to_string(&target).ok(), Some(content));
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn file_store_enforces_quota() {
+        let directory = directory("file_store_quota");
+        let mut config = FileStoreConfig::new(directory.clone());
+        config.quota = 100;
+        let mut file_store = FileStore::new(config)
+            .unwrap_or_else(|err| panic!("Failed to create file store: {}", err));
+        let username_policy = UsernamePolicy::new();
+
+        let offer = |size| {
+            OfferFile::new(FileInfo::new(
+                String::from("screenshot.png"),
+                size,
+                String::from("image/png"),
+                [0; 32],
+            ))
+        };
+
+        assert!(file_store
+            .offer(&username_policy, "Alice", &offer(60))
+            .is_ok());
+        assert!(matches!(
+            file_store.offer(&username_policy, "Alice", &offer(50)),
+            Err(FileTransferError::QuotaExceeded(100))
+        ));
+        assert!(file_store
+            .offer(&username_policy, "Bob", &offer(50))
+            .is_ok());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
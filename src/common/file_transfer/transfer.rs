@@ -0,0 +1,178 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::error::FileTransferError;
+
+use crate::common::protocol::packet::{
+    client::{self, OfferFile, RequestChunk},
+    server::{self, Attachment, UploadReady},
+    FileInfo, MAX_CHUNK_SIZE,
+};
+
+// A local file to share. Chunks are read lazily so the caller can send them between other
+// messages instead of holding up the connection for the whole file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileUpload {
+    path: PathBuf,
+    info: FileInfo,
+}
+
+impl FileUpload {
+    pub fn open(path: &Path, mime_type: &str) -> Result<FileUpload, FileTransferError> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| FileTransferError::InvalidName(path.display().to_string()))?;
+
+        let mut file = File::open(path).map_err(FileTransferError::IoError)?;
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut file, &mut hasher).map_err(FileTransferError::IoError)?;
+
+        Ok(FileUpload {
+            path: path.to_path_buf(),
+            info: FileInfo::new(
+                name.to_owned(),
+                size,
+                mime_type.to_owned(),
+                hasher.finalize().into(),
+            ),
+        })
+    }
+
+    pub fn info(&self) -> &FileInfo {
+        &self.info
+    }
+
+    pub fn offer(&self) -> OfferFile {
+        OfferFile::new(self.info.clone())
+    }
+
+    // Starts at the offset the server asked for, which is past what it already has when resuming
+    pub fn chunks(&self, upload_ready: &UploadReady) -> Result<UploadChunks, FileTransferError> {
+        let mut file = File::open(&self.path).map_err(FileTransferError::IoError)?;
+        file.seek(SeekFrom::Start(upload_ready.offset))
+            .map_err(FileTransferError::IoError)?;
+
+        Ok(UploadChunks {
+            file,
+            file_id: upload_ready.file_id,
+            offset: upload_ready.offset,
+            size: self.info.size,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UploadChunks {
+    file: File,
+    file_id: u64,
+    offset: u64,
+    size: u64,
+}
+
+impl Iterator for UploadChunks {
+    type Item = Result<client::FileChunk, FileTransferError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.size {
+            return None;
+        }
+
+        let length = (self.size - self.offset).min(MAX_CHUNK_SIZE as u64);
+        let mut data = vec![0; length as usize];
+        if let Err(err) = self.file.read_exact(&mut data) {
+            self.offset = self.size;
+            return Some(Err(FileTransferError::IoError(err)));
+        }
+
+        let chunk = client::FileChunk::new(self.file_id, self.offset, data);
+        self.offset += length;
+        Some(Ok(chunk))
+    }
+}
+
+// Pulls an attachment one chunk at a time, so the client decides how much of the
+// connection a download takes up
+#[derive(Debug)]
+pub struct FileDownload {
+    attachment: Attachment,
+    file: File,
+    offset: u64,
+    hasher: Sha256,
+}
+
+impl FileDownload {
+    pub fn create(attachment: Attachment, path: &Path) -> Result<FileDownload, FileTransferError> {
+        let file = File::create(path).map_err(FileTransferError::IoError)?;
+
+        Ok(FileDownload {
+            attachment,
+            file,
+            offset: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    pub fn attachment(&self) -> &Attachment {
+        &self.attachment
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.attachment.file.size
+    }
+
+    pub fn next_request(&self) -> Option<RequestChunk> {
+        if self.is_complete() {
+            return None;
+        }
+
+        Some(RequestChunk::new(
+            self.attachment.file_id,
+            self.offset,
+            MAX_CHUNK_SIZE as u64,
+        ))
+    }
+
+    // Returns whether the download is complete, which includes a verified hash
+    pub fn write_chunk(
+        &mut self,
+        file_chunk: &server::FileChunk,
+    ) -> Result<bool, FileTransferError> {
+        if file_chunk.file_id != self.attachment.file_id {
+            return Err(FileTransferError::UnknownFile(file_chunk.file_id));
+        }
+        if file_chunk.offset != self.offset {
+            return Err(FileTransferError::UnexpectedOffset(self.offset));
+        }
+        if self.offset + file_chunk.data.len() as u64 > self.attachment.file.size {
+            return Err(FileTransferError::SizeMismatch(self.attachment.file.size));
+        }
+
+        self.file
+            .write_all(&file_chunk.data)
+            .map_err(FileTransferError::IoError)?;
+        self.hasher.update(&file_chunk.data);
+        self.offset += file_chunk.data.len() as u64;
+
+        if !self.is_complete() {
+            return Ok(false);
+        }
+
+        if <[u8; 32]>::from(self.hasher.finalize_reset()) != self.attachment.file.hash {
+            return Err(FileTransferError::HashMismatch(
+                self.attachment.file.name.clone(),
+            ));
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,283 @@
+pub mod error;
+
+use std::io::{BufRead, Read, Write};
+
+use self::error::HttpError;
+
+pub const MAX_HEAD_SIZE: usize = 16 * 1024;
+pub const MAX_BODY_SIZE: usize = 64 * 1024;
+
+// Just enough HTTP/1.1 for the admin API and webhooks: one request per connection, no chunked bodies
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn new(
+        method: &str,
+        path: &str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> HttpRequest {
+        HttpRequest {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers,
+            body,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<HttpRequest, HttpError> {
+        let (request_line, headers) = read_head(reader)?;
+
+        let mut parts = request_line.split(' ');
+        let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method, path)
+            }
+            _ => return Err(HttpError::Malformed(String::from("request line"))),
+        };
+
+        let body = match find_header(&headers, "Content-Length") {
+            Some(_) => read_body(reader, &headers)?,
+            None => Vec::new(),
+        };
+
+        Ok(HttpRequest::new(method, path, headers, body))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W, host: &str) -> Result<(), HttpError> {
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.method,
+            self.path,
+            host,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        writer
+            .write_all(head.as_bytes())
+            .and_then(|_| writer.write_all(&self.body))
+            .map_err(HttpError::IoError)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // Without a Content-Length the body runs until the connection closes
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<HttpResponse, HttpError> {
+        let (status_line, headers) = read_head(reader)?;
+
+        let status = match status_line.split(' ').collect::<Vec<_>>().as_slice() {
+            [version, status, ..] if version.starts_with("HTTP/1.") => status
+                .parse::<u16>()
+                .map_err(|_| HttpError::Malformed(String::from("status code")))?,
+            _ => return Err(HttpError::Malformed(String::from("status line"))),
+        };
+
+        let body = match find_header(&headers, "Content-Length") {
+            Some(_) => read_body(reader, &headers)?,
+            None => {
+                let mut body = Vec::new();
+                reader
+                    .by_ref()
+                    .take(MAX_BODY_SIZE as u64)
+                    .read_to_end(&mut body)
+                    .map_err(HttpError::IoError)?;
+                body
+            }
+        };
+
+        Ok(HttpResponse::new(status, headers, body))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), HttpError> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        writer
+            .write_all(head.as_bytes())
+            .and_then(|_| writer.write_all(&self.body))
+            .map_err(HttpError::IoError)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn read_head<R: BufRead>(reader: &mut R) -> Result<(String, Vec<(String, String)>), HttpError> {
+    let mut head_size = 0;
+    let mut start_line = None;
+    let mut headers = Vec::new();
+
+    loop {
+        let mut line = Vec::new();
+        let read = reader
+            .by_ref()
+            .take((MAX_HEAD_SIZE - head_size) as u64)
+            .read_until(b'\n', &mut line)
+            .map_err(HttpError::IoError)?;
+        head_size += read;
+
+        if !line.ends_with(b"\n") {
+            return match read {
+                0 if start_line.is_none() => {
+                    Err(HttpError::Malformed(String::from("empty request")))
+                }
+                _ if head_size >= MAX_HEAD_SIZE => Err(HttpError::HeadTooLarge(MAX_HEAD_SIZE)),
+                _ => Err(HttpError::Malformed(String::from("head"))),
+            };
+        }
+
+        let line = String::from_utf8(line)
+            .map_err(|_| HttpError::Malformed(String::from("head encoding")))?;
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if start_line.is_none() {
+            start_line = Some(line.to_owned());
+            continue;
+        }
+
+        if line.is_empty() {
+            break;
+        }
+
+        match line.split_once(':') {
+            Some((name, value)) => headers.push((name.trim().to_owned(), value.trim().to_owned())),
+            None => return Err(HttpError::Malformed(String::from("header"))),
+        }
+    }
+
+    Ok((start_line.unwrap_or_default(), headers))
+}
+
+fn read_body<R: BufRead>(
+    reader: &mut R,
+    headers: &[(String, String)],
+) -> Result<Vec<u8>, HttpError> {
+    let content_length = find_header(headers, "Content-Length")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .map_err(|_| HttpError::Malformed(String::from("Content-Length")))?;
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(HttpError::BodyTooLarge(MAX_BODY_SIZE));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(HttpError::IoError)?;
+
+    Ok(body)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_request_round_trips() {
+        let request = HttpRequest::new(
+            "POST",
+            "/messages",
+            vec![(
+                String::from("Content-Type"),
+                String::from("application/json"),
+            )],
+            b"{\"message\":\"Hi\"}".to_vec(),
+        );
+
+        let mut bytes = Vec::new();
+        request
+            .write_to(&mut bytes, "localhost")
+            .unwrap_or_else(|err| panic!("Failed to write request: {}", err));
+
+        let parsed = HttpRequest::read_from(&mut bytes.as_slice())
+            .unwrap_or_else(|err| panic!("Failed to read request: {}", err));
+
+        assert_eq!(parsed.method, "POST");
+        assert_eq!(parsed.path, "/messages");
+        assert_eq!(parsed.header("content-type"), Some("application/json"));
+        assert_eq!(parsed.body, request.body);
+    }
+
+    #[test]
+    fn http_request_rejects_oversized_head_and_body() {
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        assert!(matches!(
+            HttpRequest::read_from(&mut long_header.as_bytes()),
+            Err(HttpError::HeadTooLarge(_))
+        ));
+
+        let huge_body = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(matches!(
+            HttpRequest::read_from(&mut huge_body.as_bytes()),
+            Err(HttpError::BodyTooLarge(_))
+        ));
+    }
+}
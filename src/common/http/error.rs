@@ -0,0 +1,24 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum HttpError {
+    IoError(std::io::Error),
+    HeadTooLarge(usize),
+    BodyTooLarge(usize),
+    Malformed(String),
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::IoError(err) => write!(f, "IoError on HTTP connection: {}", err),
+            HttpError::HeadTooLarge(limit) => {
+                write!(f, "HTTP head exceeded the maximum size of {} bytes", limit)
+            }
+            HttpError::BodyTooLarge(limit) => {
+                write!(f, "HTTP body exceeded the maximum size of {} bytes", limit)
+            }
+            HttpError::Malformed(what) => write!(f, "Malformed HTTP {}", what),
+        }
+    }
+}
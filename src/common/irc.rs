@@ -0,0 +1,7 @@
+pub mod error;
+pub mod gateway;
+pub mod message;
+
+pub use error::IrcError;
+pub use gateway::{IrcGateway, IrcGatewayConfig};
+pub use message::IrcMessage;
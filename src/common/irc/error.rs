@@ -0,0 +1,28 @@
+use std::fmt::Display;
+
+use crate::common::{message_stream::error::MessageStreamError, protocol::error::HandshakeError};
+
+#[derive(Debug)]
+pub enum IrcError {
+    IoError(std::io::Error),
+    EmptyMessage,
+    LineTooLong(usize),
+    HandshakeError(HandshakeError),
+    MessageStreamError(MessageStreamError),
+}
+
+impl Display for IrcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IrcError::IoError(err) => write!(f, "IoError on IRC connection: {}", err),
+            IrcError::EmptyMessage => write!(f, "Empty IRC message"),
+            IrcError::LineTooLong(limit) => {
+                write!(f, "IRC line exceeded the maximum length of {} bytes", limit)
+            }
+            IrcError::HandshakeError(err) => write!(f, "Upstream handshake failed: {}", err),
+            IrcError::MessageStreamError(err) => {
+                write!(f, "Error while streaming upstream message: {}", err)
+            }
+        }
+    }
+}
@@ -0,0 +1,1018 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+
+use super::{
+    error::IrcError,
+    message::{IrcMessage, MAX_LINE_LENGTH},
+};
+
+use crate::common::{
+    message_stream::MessageStream,
+    protocol::{
+        error::HandshakeError,
+        handshake::client::{Handshake, HandshakeArguments},
+        message::{server, Message},
+        packet::{client, server::Chat, Compression, EndReason, Packet},
+    },
+};
+
+// IRC lines are limited to 512 bytes, this leaves room for the prefix and the other 353 parameters
+const NAMES_LINE_LENGTH: usize = 400;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcGatewayConfig {
+    pub upstream_address: String,
+    // rusty_chat has a single conversation, which IRC clients see as this channel
+    pub channel: String,
+    pub server_name: String,
+}
+
+impl IrcGatewayConfig {
+    pub fn new(upstream_address: String) -> IrcGatewayConfig {
+        IrcGatewayConfig {
+            upstream_address,
+            channel: String::from("#rusty-chat"),
+            server_name: String::from("rusty-chat"),
+        }
+    }
+}
+
+// Every IRC connection gets its own upstream rusty_chat connection, authenticated with the IRC nick
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcGateway {
+    config: IrcGatewayConfig,
+}
+
+impl IrcGateway {
+    pub fn new(config: IrcGatewayConfig) -> IrcGateway {
+        IrcGateway { config }
+    }
+
+    pub fn serve(&self, listener: TcpListener) -> Result<(), IrcError> {
+        for tcp_stream in listener.incoming() {
+            let tcp_stream = tcp_stream.map_err(IrcError::IoError)?;
+            let gateway = self.clone();
+
+            thread::spawn(move || gateway.handle_connection(tcp_stream));
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_connection(&self, tcp_stream: TcpStream) -> Result<(), IrcError> {
+        let writer = IrcWriter::new(tcp_stream.try_clone().map_err(IrcError::IoError)?);
+        let mut reader = BufReader::new(tcp_stream);
+
+        let (nick, message_stream) = match self.register(&mut reader, &writer)? {
+            Some(registration) => registration,
+            None => return Ok(()),
+        };
+
+        let session = Session {
+            config: self.config.clone(),
+            nick,
+            writer,
+            joined: Arc::new(AtomicBool::new(false)),
+            next_nonce: Arc::new(AtomicU64::new(0)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            pending_commands: Arc::new(Mutex::new(VecDeque::new())),
+        };
+        session.relay(reader, message_stream)
+    }
+
+    fn register(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        writer: &IrcWriter,
+    ) -> Result<Option<(String, MessageStream)>, IrcError> {
+        let server_name = &self.config.server_name;
+        let mut nick: Option<String> = None;
+        let mut user = false;
+
+        loop {
+            let message = match read_message(reader)? {
+                Some(message) => message,
+                None => return Ok(None),
+            };
+
+            match message.command.as_str() {
+                "CAP" if param(&message, 0) == "LS" => writer.send(&IrcMessage::new(
+                    "CAP",
+                    vec![String::from("*"), String::from("LS"), String::new()],
+                ))?,
+                "CAP" | "PASS" => {}
+                "NICK" => match message.params.first() {
+                    Some(requested) => nick = Some(requested.clone()),
+                    None => writer.send(&numeric(server_name, "431", "*", "No nickname given"))?,
+                },
+                "USER" if message.params.len() < 4 => {
+                    writer.send(&numeric(server_name, "461", "*", "Not enough parameters"))?
+                }
+                "USER" => user = true,
+                "PING" => writer.send(&pong(server_name, &message))?,
+                "QUIT" => return Ok(None),
+                _ => writer.send(&numeric(server_name, "451", "*", "You have not registered"))?,
+            }
+
+            let requested = match (&nick, user) {
+                (Some(requested), true) => requested.clone(),
+                _ => continue,
+            };
+
+            match connect_upstream(&self.config, &requested) {
+                Ok((message_stream, handshake)) => {
+                    let nick = handshake.username().to_owned();
+                    self.welcome(writer, &nick)?;
+                    return Ok(Some((nick, message_stream)));
+                }
+                // Unusable nicks can be retried, just like on a real IRC server
+                Err(IrcError::HandshakeError(HandshakeError::AuthenticationFailed(
+                    EndReason::UsernameTaken,
+                    _,
+                ))) => {
+                    writer.send(&IrcMessage::with_prefix(
+                        server_name.clone(),
+                        "433",
+                        vec![
+                            String::from("*"),
+                            requested,
+                            String::from("Nickname is already in use"),
+                        ],
+                    ))?;
+                    nick = None;
+                }
+                Err(IrcError::HandshakeError(HandshakeError::AuthenticationFailed(
+                    EndReason::UsernameInvalid | EndReason::UsernameReserved,
+                    text,
+                ))) => {
+                    writer.send(&IrcMessage::with_prefix(
+                        server_name.clone(),
+                        "432",
+                        vec![
+                            String::from("*"),
+                            requested,
+                            text.unwrap_or_else(|| String::from("Erroneous nickname")),
+                        ],
+                    ))?;
+                    nick = None;
+                }
+                Err(err) => {
+                    writer.send(&error(&format!("Closing link: {}", err)))?;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    fn welcome(&self, writer: &IrcWriter, nick: &str) -> Result<(), IrcError> {
+        let server_name = &self.config.server_name;
+
+        writer.send(&numeric(
+            server_name,
+            "001",
+            nick,
+            &format!("Welcome to rusty_chat, {}", nick),
+        ))?;
+        writer.send(&numeric(
+            server_name,
+            "002",
+            nick,
+            &format!("Your host is {}, bridged to rusty_chat", server_name),
+        ))?;
+        writer.send(&numeric(
+            server_name,
+            "422",
+            nick,
+            &format!("Join {} to start chatting", self.config.channel),
+        ))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Session {
+    config: IrcGatewayConfig,
+    nick: String,
+    writer: IrcWriter,
+    joined: Arc<AtomicBool>,
+    next_nonce: Arc<AtomicU64>,
+    // Channel messages sent upstream that the server hasn't acknowledged yet
+    in_flight: Arc<AtomicUsize>,
+    // The server answers commands in order, this says what each CommandResult is for
+    pending_commands: Arc<Mutex<VecDeque<CommandReply>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CommandReply {
+    Notices,
+    Names,
+}
+
+impl Session {
+    fn relay(
+        &self,
+        mut reader: BufReader<TcpStream>,
+        mut message_stream: MessageStream,
+    ) -> Result<(), IrcError> {
+        let upstream = message_stream
+            .try_clone()
+            .map_err(IrcError::MessageStreamError)?;
+        let session = self.clone();
+        let forwarder = thread::spawn(move || session.forward_upstream(upstream));
+
+        let result = self.relay_irc(&mut reader, &mut message_stream);
+
+        // Unblocks the forwarder, which then closes the IRC side
+        let _ = message_stream.shutdown(Shutdown::Both);
+        let _ = forwarder.join();
+
+        result
+    }
+
+    fn relay_irc(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        message_stream: &mut MessageStream,
+    ) -> Result<(), IrcError> {
+        loop {
+            let message = match read_message(reader)? {
+                Some(message) => message,
+                None => {
+                    let end_packet = client::End::new(EndReason::Quit, None);
+                    let _ = message_stream.send_message(&end_packet.to_message());
+                    return Ok(());
+                }
+            };
+
+            let upstream_message = match message.command.as_str() {
+                "PING" => {
+                    self.writer
+                        .send(&pong(&self.config.server_name, &message))?;
+                    None
+                }
+                "PONG" | "CAP" | "USER" => None,
+                "NICK" => {
+                    self.send_numeric("447", "Nickname changes are not supported")?;
+                    None
+                }
+                "JOIN" if message.params.is_empty() => {
+                    self.send_numeric("461", "Not enough parameters")?;
+                    None
+                }
+                "JOIN" => {
+                    for channel in message.params[0].split(',') {
+                        if let Some(users_command) = self.join(channel)? {
+                            message_stream
+                                .send_message(&users_command)
+                                .map_err(IrcError::MessageStreamError)?;
+                        }
+                    }
+                    None
+                }
+                "NAMES" => Some(self.request_names()),
+                "PART" if message.params.is_empty() => {
+                    self.send_numeric("461", "Not enough parameters")?;
+                    None
+                }
+                "PART" => {
+                    for channel in message.params[0].split(',') {
+                        self.part(channel)?;
+                    }
+                    None
+                }
+                "PRIVMSG" | "NOTICE" if message.params.len() < 2 => {
+                    self.send_numeric("461", "Not enough parameters")?;
+                    None
+                }
+                "PRIVMSG" | "NOTICE" => {
+                    self.private_message(&message.params[0], &message.params[1])?
+                }
+                "QUIT" => {
+                    let end_packet =
+                        client::End::new(EndReason::Quit, message.params.first().cloned());
+                    message_stream
+                        .send_message(&end_packet.to_message())
+                        .map_err(IrcError::MessageStreamError)?;
+                    return Ok(());
+                }
+                command => {
+                    self.writer.send(&IrcMessage::with_prefix(
+                        self.config.server_name.clone(),
+                        "421",
+                        vec![
+                            self.nick.clone(),
+                            command.to_owned(),
+                            String::from("Unknown command"),
+                        ],
+                    ))?;
+                    None
+                }
+            };
+
+            if let Some(upstream_message) = upstream_message {
+                message_stream
+                    .send_message(&upstream_message)
+                    .map_err(IrcError::MessageStreamError)?;
+            }
+        }
+    }
+
+    // Returns the command asking upstream who is online, the names follow once it answers
+    fn join(&self, channel: &str) -> Result<Option<Message>, IrcError> {
+        if !self.is_channel(channel) {
+            self.send_channel_numeric("403", channel, "No such channel")?;
+            return Ok(None);
+        }
+
+        self.joined.store(true, Ordering::SeqCst);
+
+        let channel = self.config.channel.clone();
+        self.writer.send(&IrcMessage::with_prefix(
+            self.user_prefix(&self.nick),
+            "JOIN",
+            vec![channel.clone()],
+        ))?;
+        self.send_channel_numeric("331", &channel, "No topic is set")?;
+
+        Ok(Some(self.request_names()))
+    }
+
+    fn request_names(&self) -> Message {
+        self.expect_command_reply(CommandReply::Names);
+        client::Command::new(String::from("users"), Vec::new()).to_message()
+    }
+
+    // Registered before the command goes out, so the reply can't arrive first
+    fn expect_command_reply(&self, command_reply: CommandReply) {
+        self.pending_commands
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(command_reply);
+    }
+
+    fn part(&self, channel: &str) -> Result<(), IrcError> {
+        if !self.is_channel(channel) || !self.joined.swap(false, Ordering::SeqCst) {
+            return self.send_channel_numeric("442", channel, "You're not on that channel");
+        }
+
+        self.writer.send(&IrcMessage::with_prefix(
+            self.user_prefix(&self.nick),
+            "PART",
+            vec![self.config.channel.clone()],
+        ))
+    }
+
+    // Messages to a nick become a /msg command, which the server delivers as a PrivateMessage
+    fn private_message(&self, target: &str, text: &str) -> Result<Option<Message>, IrcError> {
+        if self.is_channel(target) {
+            if !self.joined.load(Ordering::SeqCst) {
+                self.send_channel_numeric("404", target, "Cannot send to channel")?;
+                return Ok(None);
+            }
+
+            // Numbered, so the server acknowledges it and the echo can be told apart
+            let nonce = self.next_nonce.fetch_add(1, Ordering::SeqCst);
+            self.in_flight.fetch_add(1, Ordering::SeqCst);
+
+            let chat_packet = client::Chat::new(Some(nonce), text.to_owned());
+            return Ok(Some(chat_packet.to_message()));
+        }
+
+        if target.starts_with(['#', '&']) {
+            self.send_channel_numeric("403", target, "No such channel")?;
+            return Ok(None);
+        }
+
+        self.expect_command_reply(CommandReply::Notices);
+        let command_packet = client::Command::new(
+            String::from("msg"),
+            vec![target.to_owned(), text.to_owned()],
+        );
+        Ok(Some(command_packet.to_message()))
+    }
+
+    fn forward_upstream(&self, mut message_stream: MessageStream) {
+        let mut held = None;
+
+        loop {
+            let message = match message_stream.read_message() {
+                Ok(message) => message,
+                Err(_) => {
+                    let _ = self
+                        .writer
+                        .send(&error("Closing link: connection to rusty_chat lost"));
+                    break;
+                }
+            };
+
+            let ended = matches!(message, Message::Server(server::Message::End(_)));
+            let sent = self
+                .without_echo(&mut held, message)
+                .into_iter()
+                .flat_map(|message| self.translate(message))
+                .try_for_each(|irc_message| self.writer.send(&irc_message));
+
+            if ended || sent.is_err() {
+                break;
+            }
+        }
+
+        self.writer.shutdown();
+    }
+
+    // IRC clients show their own messages themselves, but the user's other devices chat under
+    // the same nick. The server acknowledges an accepted chat right after relaying it, so while
+    // chats are in flight an own chat is held until the next message tells whether it was the echo.
+    fn without_echo(&self, held: &mut Option<Chat>, message: Message) -> Vec<Message> {
+        let mut released = Vec::new();
+
+        match message {
+            Message::Server(server::Message::Ack(ack)) => {
+                let _ =
+                    self.in_flight
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |in_flight| {
+                            in_flight.checked_sub(1)
+                        });
+
+                // A rejected chat was never relayed, so what came before it wasn't its echo
+                if let (Some(chat), Some(_)) = (held.take(), ack.rejection) {
+                    released.push(chat.to_message());
+                }
+            }
+            Message::Server(server::Message::Chat(chat))
+                if chat.username == self.nick && self.in_flight.load(Ordering::SeqCst) > 0 =>
+            {
+                released.extend(held.replace(chat).map(Chat::to_message));
+            }
+            message => {
+                released.extend(held.take().map(Chat::to_message));
+                released.push(message);
+            }
+        }
+
+        released
+    }
+
+    fn translate(&self, message: Message) -> Vec<IrcMessage> {
+        let message = match message {
+            Message::Server(message) => message,
+            Message::Client(_) => return Vec::new(),
+        };
+        let joined = self.joined.load(Ordering::SeqCst);
+        let channel = &self.config.channel;
+
+        match message {
+            server::Message::Chat(chat) if joined => chat
+                .message
+                .lines()
+                .map(|line| {
+                    IrcMessage::with_prefix(
+                        self.user_prefix(&chat.username),
+                        "PRIVMSG",
+                        vec![channel.clone(), line.to_owned()],
+                    )
+                })
+                .collect(),
+            server::Message::UserJoined(user_joined)
+                if joined && user_joined.username != self.nick =>
+            {
+                vec![IrcMessage::with_prefix(
+                    self.user_prefix(&user_joined.username),
+                    "JOIN",
+                    vec![channel.clone()],
+                )]
+            }
+            server::Message::UserLeft(user_left) if joined => vec![IrcMessage::with_prefix(
+                self.user_prefix(&user_left.username),
+                "PART",
+                vec![channel.clone()],
+            )],
+            server::Message::PrivateMessage(private_message) => private_message
+                .message
+                .lines()
+                .map(|line| {
+                    IrcMessage::with_prefix(
+                        self.user_prefix(&private_message.sender),
+                        "PRIVMSG",
+                        vec![self.nick.clone(), line.to_owned()],
+                    )
+                })
+                .collect(),
+            server::Message::Warning(warning) => self.notices(&warning.text),
+            server::Message::CommandResult(command_result) => {
+                let command_reply = self
+                    .pending_commands
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .pop_front();
+
+                match command_reply {
+                    Some(CommandReply::Names) => self.names(&command_result.output),
+                    _ => self.notices(&command_result.output),
+                }
+            }
+            server::Message::End(end) => {
+                let text = match end.text {
+                    Some(text) => format!("Closing link: {}: {}", end.reason, text),
+                    None => format!("Closing link: {}", end.reason),
+                };
+                vec![error(&text)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // One username per line, as the users command lists them
+    fn names(&self, usernames: &str) -> Vec<IrcMessage> {
+        let channel = &self.config.channel;
+
+        let mut lines: Vec<String> = Vec::new();
+        for username in usernames.lines() {
+            match lines.last_mut() {
+                Some(line) if line.len() + 1 + username.len() <= NAMES_LINE_LENGTH => {
+                    line.push(' ');
+                    line.push_str(username);
+                }
+                _ => lines.push(username.to_owned()),
+            }
+        }
+
+        let mut messages: Vec<IrcMessage> = lines
+            .into_iter()
+            .map(|line| {
+                IrcMessage::with_prefix(
+                    self.config.server_name.clone(),
+                    "353",
+                    vec![self.nick.clone(), String::from("="), channel.clone(), line],
+                )
+            })
+            .collect();
+        messages.push(self.channel_numeric("366", channel, "End of /NAMES list"));
+
+        messages
+    }
+
+    fn notices(&self, text: &str) -> Vec<IrcMessage> {
+        text.lines()
+            .map(|line| {
+                IrcMessage::with_prefix(
+                    self.config.server_name.clone(),
+                    "NOTICE",
+                    vec![self.nick.clone(), line.to_owned()],
+                )
+            })
+            .collect()
+    }
+
+    fn is_channel(&self, channel: &str) -> bool {
+        channel.eq_ignore_ascii_case(&self.config.channel)
+    }
+
+    fn user_prefix(&self, username: &str) -> String {
+        format!("{}!{}@{}", username, username, self.config.server_name)
+    }
+
+    fn send_numeric(&self, code: &str, text: &str) -> Result<(), IrcError> {
+        self.writer
+            .send(&numeric(&self.config.server_name, code, &self.nick, text))
+    }
+
+    fn send_channel_numeric(&self, code: &str, channel: &str, text: &str) -> Result<(), IrcError> {
+        self.writer.send(&self.channel_numeric(code, channel, text))
+    }
+
+    fn channel_numeric(&self, code: &str, channel: &str, text: &str) -> IrcMessage {
+        IrcMessage::with_prefix(
+            self.config.server_name.clone(),
+            code,
+            vec![self.nick.clone(), channel.to_owned(), text.to_owned()],
+        )
+    }
+}
+
+// Both directions write to the IRC client, so whole lines are written under a lock
+#[derive(Clone, Debug)]
+struct IrcWriter {
+    tcp_stream: Arc<Mutex<TcpStream>>,
+}
+
+impl IrcWriter {
+    fn new(tcp_stream: TcpStream) -> IrcWriter {
+        IrcWriter {
+            tcp_stream: Arc::new(Mutex::new(tcp_stream)),
+        }
+    }
+
+    fn send(&self, message: &IrcMessage) -> Result<(), IrcError> {
+        let mut tcp_stream = self
+            .tcp_stream
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        tcp_stream
+            .write_all(format!("{}\r\n", message).as_bytes())
+            .map_err(IrcError::IoError)
+    }
+
+    fn shutdown(&self) {
+        let tcp_stream = self
+            .tcp_stream
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let _ = tcp_stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn connect_upstream(
+    config: &IrcGatewayConfig,
+    nick: &str,
+) -> Result<(MessageStream, Handshake), IrcError> {
+    let tcp_stream =
+        TcpStream::connect(config.upstream_address.as_str()).map_err(IrcError::IoError)?;
+    let mut message_stream = MessageStream::new(tcp_stream);
+
+    // IRC has no way to answer a key challenge, so gateway users log in by name only
+    let arguments = HandshakeArguments::new(
+        nick.to_owned(),
+        None,
+        Compression::Deflate,
+        Some(String::from("IRC")),
+    );
+    let handshake =
+        Handshake::perform(&mut message_stream, arguments).map_err(IrcError::HandshakeError)?;
+
+    Ok((message_stream, handshake))
+}
+
+fn read_message(reader: &mut BufReader<TcpStream>) -> Result<Option<IrcMessage>, IrcError> {
+    loop {
+        let mut line = Vec::new();
+        let read = reader
+            .by_ref()
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .map_err(IrcError::IoError)?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(IrcError::LineTooLong(MAX_LINE_LENGTH));
+        }
+
+        // Plenty of IRC clients still send Latin-1 now and then
+        match IrcMessage::parse(&String::from_utf8_lossy(&line)) {
+            Ok(message) => return Ok(Some(message)),
+            Err(IrcError::EmptyMessage) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn param(message: &IrcMessage, index: usize) -> &str {
+    message
+        .params
+        .get(index)
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+fn numeric(server_name: &str, code: &str, target: &str, text: &str) -> IrcMessage {
+    IrcMessage::with_prefix(
+        server_name.to_owned(),
+        code,
+        vec![target.to_owned(), text.to_owned()],
+    )
+}
+
+fn pong(server_name: &str, ping: &IrcMessage) -> IrcMessage {
+    IrcMessage::with_prefix(
+        server_name.to_owned(),
+        "PONG",
+        vec![server_name.to_owned(), param(ping, 0).to_owned()],
+    )
+}
+
+fn error(text: &str) -> IrcMessage {
+    IrcMessage::new("ERROR", vec![text.to_owned()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, Instant};
+
+    use crate::common::{
+        authorized_keys::AuthorizedKeys,
+        moderation::Moderation,
+        peer_credentials::PeerCredentials,
+        permissions::Permissions,
+        protocol::{
+            handshake::server::HandshakeFixture, packet::server as server_packet,
+            username_policy::UsernamePolicy,
+        },
+        server_state::ServerState,
+    };
+
+    fn bind() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        (listener, address.to_string())
+    }
+
+    fn upstream_send(message_stream: &mut MessageStream, packet: impl Packet) {
+        message_stream
+            .send_message(&packet.to_message())
+            .unwrap_or_else(|err| panic!("Failed to send upstream message: {}", err));
+    }
+
+    fn upstream_read(message_stream: &mut MessageStream) -> Message {
+        message_stream
+            .read_message()
+            .unwrap_or_else(|err| panic!("Failed to read upstream message: {}", err))
+    }
+
+    struct IrcClient {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl IrcClient {
+        fn connect(address: &str) -> IrcClient {
+            let tcp_stream = TcpStream::connect(address)
+                .unwrap_or_else(|err| panic!("Failed to connect to gateway: {}", err));
+            tcp_stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap_or_else(|err| panic!("Failed to set timeout: {}", err));
+            let writer = tcp_stream
+                .try_clone()
+                .unwrap_or_else(|err| panic!("Failed to clone stream: {}", err));
+
+            IrcClient {
+                reader: BufReader::new(tcp_stream),
+                writer,
+            }
+        }
+
+        fn register(address: &str, nick: &str) -> IrcClient {
+            let mut irc_client = IrcClient::connect(address);
+            irc_client.send(&format!("NICK {}", nick));
+            irc_client.send(&format!("USER {} 0 * :{}", nick, nick));
+            irc_client.expect("001");
+
+            irc_client
+        }
+
+        fn send(&mut self, line: &str) {
+            self.writer
+                .write_all(format!("{}\r\n", line).as_bytes())
+                .unwrap_or_else(|err| panic!("Failed to send line: {}", err));
+        }
+
+        fn expect(&mut self, command: &str) -> IrcMessage {
+            loop {
+                let message = read_message(&mut self.reader)
+                    .unwrap_or_else(|err| panic!("Failed to read line: {}", err))
+                    .unwrap_or_else(|| panic!("Gateway closed before {}", command));
+
+                if message.command == command {
+                    return message;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn irc_gateway_bridges_irc_client_to_rusty_chat() {
+        let (upstream_listener, upstream_address) = bind();
+        let (gateway_listener, gateway_address) = bind();
+
+        let upstream = thread::spawn(move || {
+            let (tcp_stream, _) = upstream_listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            let mut message_stream = MessageStream::new(tcp_stream);
+
+            let handshake = HandshakeFixture::new()
+                .perform(&mut message_stream)
+                .unwrap_or_else(|err| panic!("Failed to perform handshake: {}", err));
+            assert_eq!(handshake.username(), "Bob");
+
+            let users = upstream_read(&mut message_stream);
+            upstream_send(
+                &mut message_stream,
+                server_packet::CommandResult::new(true, String::from("Alice\nBob")),
+            );
+            let chat = upstream_read(&mut message_stream);
+            upstream_send(
+                &mut message_stream,
+                server_packet::UserJoined::new(String::from("Alice")),
+            );
+            upstream_send(
+                &mut message_stream,
+                server_packet::Chat::new(String::from("Alice"), String::from("Hi Bob")),
+            );
+            let command = upstream_read(&mut message_stream);
+            let end = upstream_read(&mut message_stream);
+
+            (users, chat, command, end)
+        });
+
+        let gateway = IrcGateway::new(IrcGatewayConfig::new(upstream_address));
+        thread::spawn(move || gateway.serve(gateway_listener));
+
+        let mut irc_client = IrcClient::connect(&gateway_address);
+        irc_client.send("CAP LS 302");
+        irc_client.send("NICK Bob");
+        irc_client.send("USER bob 0 * :Bob");
+        irc_client.expect("001");
+
+        irc_client.send("JOIN #rusty-chat");
+        let names = irc_client.expect("353");
+        assert_eq!(names.params.last().map(String::as_str), Some("Alice Bob"));
+        irc_client.expect("366");
+
+        irc_client.send("PRIVMSG #rusty-chat :Hello everyone");
+        let join = irc_client.expect("JOIN");
+        assert_eq!(join.prefix.as_deref(), Some("Alice!Alice@rusty-chat"));
+
+        let privmsg = irc_client.expect("PRIVMSG");
+        assert_eq!(
+            privmsg.params,
+            vec![String::from("#rusty-chat"), String::from("Hi Bob")]
+        );
+
+        irc_client.send("PING :token");
+        let pong = irc_client.expect("PONG");
+        assert_eq!(pong.params.last().map(String::as_str), Some("token"));
+
+        irc_client.send("PRIVMSG Alice :psst");
+        irc_client.send("QUIT :Bye");
+
+        let (users, chat, command, end) = upstream
+            .join()
+            .unwrap_or_else(|_| panic!("Upstream thread panicked"));
+
+        assert_eq!(
+            users,
+            client::Command::new(String::from("users"), Vec::new()).to_message()
+        );
+        assert_eq!(
+            chat,
+            client::Chat::new(Some(0), String::from("Hello everyone")).to_message()
+        );
+        assert_eq!(
+            command,
+            client::Command::new(
+                String::from("msg"),
+                vec![String::from("Alice"), String::from("psst")]
+            )
+            .to_message()
+        );
+        assert_eq!(
+            end,
+            client::End::new(EndReason::Quit, Some(String::from("Bye"))).to_message()
+        );
+    }
+
+    #[test]
+    fn irc_gateway_hides_the_echo_but_not_other_devices() {
+        let (upstream_listener, upstream_address) = bind();
+        let (gateway_listener, gateway_address) = bind();
+
+        let upstream = thread::spawn(move || {
+            let (tcp_stream, _) = upstream_listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            let mut message_stream = MessageStream::new(tcp_stream);
+            HandshakeFixture::new()
+                .perform(&mut message_stream)
+                .unwrap_or_else(|err| panic!("Failed to perform handshake: {}", err));
+
+            upstream_read(&mut message_stream);
+            upstream_send(
+                &mut message_stream,
+                server_packet::CommandResult::new(true, String::from("Bob")),
+            );
+            let chat = upstream_read(&mut message_stream);
+            // Another device of Bob's gets in before the echo
+            for text in ["Sent from my phone", "Hello everyone"] {
+                upstream_send(
+                    &mut message_stream,
+                    server_packet::Chat::new(String::from("Bob"), String::from(text)),
+                );
+            }
+            upstream_send(&mut message_stream, server_packet::Ack::new(0, None));
+            upstream_send(
+                &mut message_stream,
+                server_packet::Chat::new(String::from("Bob"), String::from("Phone again")),
+            );
+            upstream_send(
+                &mut message_stream,
+                server_packet::End::new(EndReason::ServerShutdown, None),
+            );
+
+            chat
+        });
+
+        let gateway = IrcGateway::new(IrcGatewayConfig::new(upstream_address));
+        thread::spawn(move || gateway.serve(gateway_listener));
+
+        let mut irc_client = IrcClient::register(&gateway_address, "Bob");
+        irc_client.send("JOIN #rusty-chat");
+        irc_client.expect("366");
+        irc_client.send("PRIVMSG #rusty-chat :Hello everyone");
+
+        let mut texts = Vec::new();
+        loop {
+            let message = read_message(&mut irc_client.reader)
+                .unwrap_or_else(|err| panic!("Failed to read line: {}", err))
+                .unwrap_or_else(|| panic!("Gateway closed before ERROR"));
+            match message.command.as_str() {
+                "PRIVMSG" => texts.extend(message.params.last().cloned()),
+                "ERROR" => break,
+                _ => {}
+            }
+        }
+
+        let chat = upstream
+            .join()
+            .unwrap_or_else(|_| panic!("Upstream thread panicked"));
+        assert_eq!(
+            chat,
+            client::Chat::new(Some(0), String::from("Hello everyone")).to_message()
+        );
+        assert_eq!(
+            texts,
+            vec![
+                String::from("Sent from my phone"),
+                String::from("Phone again")
+            ]
+        );
+    }
+
+    #[test]
+    fn irc_gateway_delivers_private_messages_between_nicks() {
+        let (upstream_listener, upstream_address) = bind();
+        let (gateway_listener, gateway_address) = bind();
+        let state = Arc::new(Mutex::new(ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )));
+
+        let upstream_state = state.clone();
+        thread::spawn(move || ServerState::serve(&upstream_state, upstream_listener));
+        let gateway = IrcGateway::new(IrcGatewayConfig::new(upstream_address));
+        thread::spawn(move || gateway.serve(gateway_listener));
+
+        let mut alice = IrcClient::register(&gateway_address, "Alice");
+        // The upstream joins Alice only after her handshake finished
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .usernames()
+            .is_empty()
+        {
+            assert!(Instant::now() < deadline, "Alice never joined upstream");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut bob = IrcClient::register(&gateway_address, "Bob");
+        bob.send("PRIVMSG alice :psst");
+
+        let privmsg = alice.expect("PRIVMSG");
+        assert_eq!(privmsg.prefix.as_deref(), Some("Bob!Bob@rusty-chat"));
+        assert_eq!(
+            privmsg.params,
+            vec![String::from("Alice"), String::from("psst")]
+        );
+
+        bob.send("PRIVMSG Carol :anyone there?");
+        let notice = bob.expect("NOTICE");
+        assert_eq!(
+            notice.params.last().map(String::as_str),
+            Some("No such user: Carol")
+        );
+
+        alice.send("JOIN #rusty-chat");
+        let names = alice.expect("353");
+        assert_eq!(names.params.last().map(String::as_str), Some("Alice Bob"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Display;
+
+use super::error::IrcError;
+
+// RFC 1459 allows 512 bytes, IRCv3 message tags add up to 8191 more
+pub const MAX_LINE_LENGTH: usize = 512 + 8191;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcMessage {
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    pub fn new(command: &str, params: Vec<String>) -> IrcMessage {
+        IrcMessage {
+            prefix: None,
+            command: command.to_owned(),
+            params,
+        }
+    }
+
+    pub fn with_prefix(prefix: String, command: &str, params: Vec<String>) -> IrcMessage {
+        IrcMessage {
+            prefix: Some(prefix),
+            command: command.to_owned(),
+            params,
+        }
+    }
+
+    // Message tags are accepted but dropped, the gateway has no use for them
+    pub fn parse(line: &str) -> Result<IrcMessage, IrcError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ').map(|(_, rest)| rest).unwrap_or("");
+        }
+        rest = rest.trim_start_matches(' ');
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (source, remainder) = prefixed.split_once(' ').unwrap_or((prefixed, ""));
+            prefix = Some(source.to_owned());
+            rest = remainder;
+        }
+        rest = rest.trim_start_matches(' ');
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return Err(IrcError::EmptyMessage);
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_owned());
+                break;
+            }
+
+            let (param, remainder) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param.to_owned());
+            rest = remainder;
+        }
+
+        Ok(IrcMessage {
+            prefix,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+}
+
+impl Display for IrcMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(prefix) = &self.prefix {
+            write!(f, ":{} ", prefix)?;
+        }
+        write!(f, "{}", self.command)?;
+
+        if let Some((last, middle)) = self.params.split_last() {
+            for param in middle {
+                write!(f, " {}", param)?;
+            }
+
+            if last.is_empty() || last.contains(' ') || last.starts_with(':') {
+                write!(f, " :{}", last)?;
+            } else {
+                write!(f, " {}", last)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn irc_message_parses_prefix_params_and_trailing() {
+        let message =
+            IrcMessage::parse("@time=now :Alice!alice@host privmsg #rusty-chat :Hi there\r\n")
+                .unwrap_or_else(|err| panic!("Failed to parse IRC message: {}", err));
+
+        assert_eq!(message.prefix.as_deref(), Some("Alice!alice@host"));
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(
+            message.params,
+            vec![String::from("#rusty-chat"), String::from("Hi there")]
+        );
+
+        let message = IrcMessage::parse("USER bob 0 * :Bob")
+            .unwrap_or_else(|err| panic!("Failed to parse IRC message: {}", err));
+        assert_eq!(message.params.len(), 4);
+
+        assert!(matches!(
+            IrcMessage::parse("\r\n"),
+            Err(IrcError::EmptyMessage)
+        ));
+    }
+
+    #[test]
+    fn irc_message_formats_trailing_param_when_needed() {
+        let message = IrcMessage::with_prefix(
+            String::from("rusty-chat"),
+            "001",
+            vec![String::from("Bob"), String::from("Welcome, Bob")],
+        );
+        assert_eq!(message.to_string(), ":rusty-chat 001 Bob :Welcome, Bob");
+
+        let message = IrcMessage::new("PONG", vec![String::from("token")]);
+        assert_eq!(message.to_string(), "PONG token");
+
+        let message = IrcMessage::new("CAP", vec![String::from("*"), String::new()]);
+        assert_eq!(message.to_string(), "CAP * :");
+    }
+}
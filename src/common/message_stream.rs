@@ -1,53 +1,429 @@
+pub mod connection;
 pub mod error;
 
 use std::{
-    io::{Read, Write},
-    net::TcpStream,
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, Shutdown},
     ops::Deref,
 };
 
-use self::error::MessageStreamError;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
 
-use crate::common::protocol::{message::Message, serializable::Serializable};
+use self::{connection::Connection, error::MessageStreamError};
+
+use crate::common::protocol::{
+    codec::{BinaryCodec, Codec, Framing},
+    message::Message,
+    packet::Compression,
+};
+
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+// Smaller messages rarely shrink enough to be worth compressing
+pub const COMPRESSION_THRESHOLD: usize = 512;
+
+// Every frame starts with the length of the encoded message as a little-endian u32.
+// Lengths never come close to the top bit, so it marks compressed frames.
+const FRAME_HEADER_SIZE: usize = 4;
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+// How much is read at once while looking for the end of a line
+const LINE_CHUNK_SIZE: usize = 4096;
+
+// Anything that carries whole Messages, so handshakes and sessions work the same on every listener
+pub trait MessageTransport {
+    fn read_message(&mut self) -> Result<Message, MessageStreamError>;
+    fn send_message(&mut self, message: &Message) -> Result<(), MessageStreamError>;
+    fn peer_address(&self) -> Option<IpAddr>;
+
+    // Only local transports know who is on the other end
+    fn peer_uid(&self) -> Option<u32> {
+        None
+    }
+
+    // Transports that bring their own framing leave compression to it
+    fn supports_compression(&self) -> bool {
+        false
+    }
+
+    fn set_compression(&mut self, _compression: Compression) {}
+}
+
+// Transports a session can read on one thread while another thread sends
+pub trait SplitTransport: MessageTransport + Sized {
+    fn try_clone(&self) -> Result<Self, MessageStreamError>;
+
+    // Unblocks a reader on another handle, e.g. once the session was ended from elsewhere
+    fn shutdown(&self);
+}
 
 #[derive(Debug)]
-pub struct MessageStream {
-    tcp_stream: TcpStream,
+pub struct MessageStream<C: Codec = BinaryCodec> {
+    connection: Connection,
+    codec: C,
+    compression: Compression,
+    // Bytes read past the end of the last line, only used by newline-delimited codecs
+    line_buffer: Vec<u8>,
 }
 
 impl MessageStream {
-    pub fn new(tcp_stream: TcpStream) -> MessageStream {
-        MessageStream { tcp_stream }
+    pub fn new(connection: impl Into<Connection>) -> MessageStream {
+        MessageStream::with_codec(connection, BinaryCodec)
+    }
+}
+
+impl<C: Codec> MessageStream<C> {
+    pub fn with_codec(connection: impl Into<Connection>, codec: C) -> MessageStream<C> {
+        MessageStream {
+            connection: connection.into(),
+            codec,
+            compression: Compression::None,
+            line_buffer: Vec::new(),
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // Set by the handshake, both sides switch right after Authenticated
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
     }
 
     pub fn read_message(&mut self) -> Result<Message, MessageStreamError> {
-        let mut message_buffer = Vec::<u8>::new();
+        let message_buffer = match self.codec.framing() {
+            Framing::LengthPrefixed => self.read_frame()?,
+            Framing::NewlineDelimited => self.read_line()?,
+        };
+
+        self.codec
+            .decode(&message_buffer)
+            .map_err(MessageStreamError::CodecError)
+    }
 
-        self.tcp_stream
-            .read_to_end(&mut message_buffer)
+    fn read_frame(&mut self) -> Result<Vec<u8>, MessageStreamError> {
+        let mut header = [0; FRAME_HEADER_SIZE];
+
+        self.connection
+            .read_exact(&mut header)
+            .map_err(MessageStreamError::IoError)?;
+
+        let header = u32::from_le_bytes(header);
+        let compressed = header & COMPRESSED_FLAG != 0;
+        let message_length = (header & !COMPRESSED_FLAG) as usize;
+        if message_length > MAX_MESSAGE_SIZE {
+            return Err(MessageStreamError::MessageTooLarge(MAX_MESSAGE_SIZE));
+        }
+
+        let mut message_buffer = vec![0; message_length];
+
+        self.connection
+            .read_exact(&mut message_buffer)
             .map_err(MessageStreamError::IoError)?;
 
-        let message =
-            Message::from_bytes(&message_buffer).map_err(MessageStreamError::MessageParseError)?;
+        if compressed {
+            message_buffer = match self.compression {
+                Compression::Deflate => decompress(&message_buffer)?,
+                Compression::None => return Err(MessageStreamError::UnexpectedCompression),
+            };
+        }
+
+        Ok(message_buffer)
+    }
+
+    // Reads in chunks and keeps whatever follows the line for the next call
+    fn read_line(&mut self) -> Result<Vec<u8>, MessageStreamError> {
+        loop {
+            if let Some(end) = self.line_buffer.iter().position(|byte| *byte == b'\n') {
+                if end > MAX_MESSAGE_SIZE {
+                    return Err(MessageStreamError::MessageTooLarge(MAX_MESSAGE_SIZE));
+                }
+
+                return Ok(self.line_buffer.drain(..=end).collect());
+            }
 
-        Ok(message)
+            if self.line_buffer.len() > MAX_MESSAGE_SIZE {
+                return Err(MessageStreamError::MessageTooLarge(MAX_MESSAGE_SIZE));
+            }
+
+            let mut chunk = [0; LINE_CHUNK_SIZE];
+            match self.connection.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(MessageStreamError::IoError(io::Error::from(
+                        ErrorKind::UnexpectedEof,
+                    )))
+                }
+                Ok(read) => self.line_buffer.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(MessageStreamError::IoError(err)),
+            }
+        }
     }
 
     pub fn send_message(&mut self, message: &Message) -> Result<(), MessageStreamError> {
-        let message_bytes = message.as_bytes();
+        let message_bytes = self
+            .codec
+            .encode(message)
+            .map_err(MessageStreamError::CodecError)?;
+
+        if message_bytes.len() > MAX_MESSAGE_SIZE {
+            return Err(MessageStreamError::MessageTooLarge(MAX_MESSAGE_SIZE));
+        }
+
+        // The encoding already ends with its newline
+        if self.codec.framing() == Framing::NewlineDelimited {
+            return self
+                .connection
+                .write_all(&message_bytes)
+                .map_err(MessageStreamError::IoError);
+        }
 
-        self.tcp_stream
-            .write_all(&message_bytes)
+        let (header, message_bytes) = match self.compression {
+            Compression::Deflate if message_bytes.len() >= COMPRESSION_THRESHOLD => {
+                match compress(&message_bytes)? {
+                    compressed_bytes if compressed_bytes.len() < message_bytes.len() => (
+                        compressed_bytes.len() as u32 | COMPRESSED_FLAG,
+                        compressed_bytes,
+                    ),
+                    _ => (message_bytes.len() as u32, message_bytes),
+                }
+            }
+            _ => (message_bytes.len() as u32, message_bytes),
+        };
+
+        // Header and message go out in one write, so they can't be split by another writer
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + message_bytes.len());
+        frame.extend(header.to_le_bytes());
+        frame.extend(message_bytes);
+
+        self.connection
+            .write_all(&frame)
             .map_err(MessageStreamError::IoError)?;
 
         Ok(())
     }
 }
 
-impl Deref for MessageStream {
-    type Target = TcpStream;
+impl<C: Codec + Clone> MessageStream<C> {
+    // A second handle on the same connection, e.g. to read on one thread and write on another.
+    // Lines already buffered by this handle stay with it.
+    pub fn try_clone(&self) -> Result<MessageStream<C>, MessageStreamError> {
+        let connection = self
+            .connection
+            .try_clone()
+            .map_err(MessageStreamError::IoError)?;
+
+        let mut message_stream = MessageStream::with_codec(connection, self.codec.clone());
+        message_stream.set_compression(self.compression);
+
+        Ok(message_stream)
+    }
+}
+
+impl<C: Codec> MessageTransport for MessageStream<C> {
+    fn read_message(&mut self) -> Result<Message, MessageStreamError> {
+        MessageStream::read_message(self)
+    }
+
+    fn send_message(&mut self, message: &Message) -> Result<(), MessageStreamError> {
+        MessageStream::send_message(self, message)
+    }
+
+    fn peer_address(&self) -> Option<IpAddr> {
+        self.connection.peer_address()
+    }
+
+    fn peer_uid(&self) -> Option<u32> {
+        self.connection.peer_uid()
+    }
+
+    fn supports_compression(&self) -> bool {
+        self.codec.framing() == Framing::LengthPrefixed
+    }
+
+    fn set_compression(&mut self, compression: Compression) {
+        MessageStream::set_compression(self, compression)
+    }
+}
+
+fn compress(bytes: &[u8]) -> Result<Vec<u8>, MessageStreamError> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+
+    encoder
+        .write_all(bytes)
+        .and_then(|_| encoder.finish())
+        .map_err(MessageStreamError::IoError)
+}
+
+// Stops one byte past the limit, so a small frame can't inflate into gigabytes
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, MessageStreamError> {
+    let mut decompressed = Vec::new();
+
+    DeflateDecoder::new(bytes)
+        .take(MAX_MESSAGE_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(MessageStreamError::IoError)?;
+
+    if decompressed.len() > MAX_MESSAGE_SIZE {
+        return Err(MessageStreamError::DecompressedTooLarge(MAX_MESSAGE_SIZE));
+    }
+
+    Ok(decompressed)
+}
+
+impl<C: Codec + Clone> SplitTransport for MessageStream<C> {
+    fn try_clone(&self) -> Result<MessageStream<C>, MessageStreamError> {
+        MessageStream::try_clone(self)
+    }
+
+    fn shutdown(&self) {
+        let _ = self.connection.shutdown(Shutdown::Both);
+    }
+}
+
+impl<C: Codec> Deref for MessageStream<C> {
+    type Target = Connection;
 
     fn deref(&self) -> &Self::Target {
-        &self.tcp_stream
+        &self.connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{TcpListener, TcpStream};
+
+    use crate::common::protocol::packet::{server::Chat, Packet};
+
+    fn connect() -> (TcpStream, MessageStream) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        let tcp_stream =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let (accepted, _) = listener
+            .accept()
+            .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+
+        (tcp_stream, MessageStream::new(accepted))
+    }
+
+    #[test]
+    fn message_stream_reads_consecutive_frames_from_one_connection() {
+        let (tcp_stream, mut receiver) = connect();
+        let mut sender = MessageStream::new(tcp_stream);
+
+        let messages: Vec<Message> = ["Hi", "⚡", "Still here"]
+            .into_iter()
+            .map(|text| Chat::new(String::from("Kitt3120"), String::from(text)).to_message())
+            .collect();
+        for message in &messages {
+            sender
+                .send_message(message)
+                .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+        }
+
+        // Frames end where their length says, not when the connection closes
+        for message in &messages {
+            let received = receiver
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read message: {}", err));
+            assert_eq!(&received, message);
+        }
+
+        let mut header = [0; FRAME_HEADER_SIZE];
+        let Connection::Tcp(tcp_stream) = &receiver.connection else {
+            panic!("Receiver is not a TCP connection");
+        };
+        tcp_stream
+            .set_nonblocking(true)
+            .unwrap_or_else(|err| panic!("Failed to set nonblocking: {}", err));
+        assert!(tcp_stream.peek(&mut header).is_err());
+    }
+
+    #[test]
+    fn message_stream_compresses_large_frames() {
+        let (tcp_stream, mut sender) = connect();
+        let mut receiver = MessageStream::new(tcp_stream);
+        sender.set_compression(Compression::Deflate);
+        receiver.set_compression(Compression::Deflate);
+
+        let log = "ERROR worker panicked at src/main.rs:42\n".repeat(200);
+        let message = Chat::new(String::from("Kitt3120"), log.clone()).to_message();
+        sender
+            .send_message(&message)
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+
+        let Connection::Tcp(tcp_stream) = &receiver.connection else {
+            panic!("Receiver is not a TCP connection");
+        };
+        let mut header = [0; FRAME_HEADER_SIZE];
+        tcp_stream
+            .peek(&mut header)
+            .unwrap_or_else(|err| panic!("Failed to peek header: {}", err));
+        let header = u32::from_le_bytes(header);
+        assert_ne!(header & COMPRESSED_FLAG, 0);
+        assert!(((header & !COMPRESSED_FLAG) as usize) < log.len() / 10);
+
+        let received = receiver
+            .read_message()
+            .unwrap_or_else(|err| panic!("Failed to read message: {}", err));
+        assert_eq!(received, message);
+    }
+
+    #[test]
+    fn message_stream_rejects_decompression_bombs() {
+        let bomb = compress(&vec![0; MAX_MESSAGE_SIZE * 4])
+            .unwrap_or_else(|err| panic!("Failed to compress: {}", err));
+        let mut frame = (bomb.len() as u32 | COMPRESSED_FLAG).to_le_bytes().to_vec();
+        frame.extend(&bomb);
+
+        let (mut tcp_stream, mut receiver) = connect();
+        receiver.set_compression(Compression::Deflate);
+        tcp_stream
+            .write_all(&frame)
+            .unwrap_or_else(|err| panic!("Failed to send frame: {}", err));
+        assert!(matches!(
+            receiver.read_message(),
+            Err(MessageStreamError::DecompressedTooLarge(MAX_MESSAGE_SIZE))
+        ));
+
+        let (mut tcp_stream, mut receiver) = connect();
+        tcp_stream
+            .write_all(&frame)
+            .unwrap_or_else(|err| panic!("Failed to send frame: {}", err));
+        assert!(matches!(
+            receiver.read_message(),
+            Err(MessageStreamError::UnexpectedCompression)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn message_stream_works_over_unix_sockets() {
+        let (left, right) = std::os::unix::net::UnixStream::pair()
+            .unwrap_or_else(|err| panic!("Failed to create socket pair: {}", err));
+        let mut sender = MessageStream::new(left);
+        let mut receiver = MessageStream::new(right);
+
+        let message = Chat::new(String::from("deploy-bot"), String::from("✅")).to_message();
+        sender
+            .send_message(&message)
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+        let received = receiver
+            .read_message()
+            .unwrap_or_else(|err| panic!("Failed to read message: {}", err));
+        assert_eq!(received, message);
+
+        // SAFETY: getuid can't fail
+        let uid = unsafe { libc::getuid() };
+        assert_eq!(MessageTransport::peer_uid(&receiver), Some(uid));
+        assert_eq!(MessageTransport::peer_address(&receiver), None);
     }
 }
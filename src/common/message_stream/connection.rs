@@ -0,0 +1,157 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Shutdown, TcpStream},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+// Addresses with this prefix name a Unix socket path instead of a host and port
+pub const UNIX_ADDRESS_PREFIX: &str = "unix:";
+
+// The byte stream a MessageStream frames messages on
+#[derive(Debug)]
+pub enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    pub fn connect(address: &str) -> io::Result<Connection> {
+        #[cfg(unix)]
+        if let Some(path) = address.strip_prefix(UNIX_ADDRESS_PREFIX) {
+            return UnixStream::connect(path).map(Connection::Unix);
+        }
+
+        TcpStream::connect(address).map(Connection::Tcp)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.try_clone().map(Connection::Tcp),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.try_clone().map(Connection::Unix),
+        }
+    }
+
+    // Unix sockets have no IP address, they're identified by peer_uid instead
+    pub fn peer_address(&self) -> Option<IpAddr> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.peer_addr().ok().map(|address| address.ip()),
+            #[cfg(unix)]
+            Connection::Unix(_) => None,
+        }
+    }
+
+    // The user id of the process on the other end, as vouched for by the kernel
+    pub fn peer_uid(&self) -> Option<u32> {
+        match self {
+            Connection::Tcp(_) => None,
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => peer_uid(unix_stream),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.shutdown(how),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.shutdown(how),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.read(buf),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.write(buf),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Tcp(tcp_stream) => tcp_stream.flush(),
+            #[cfg(unix)]
+            Connection::Unix(unix_stream) => unix_stream.flush(),
+        }
+    }
+}
+
+impl From<TcpStream> for Connection {
+    fn from(value: TcpStream) -> Self {
+        Connection::Tcp(value)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for Connection {
+    fn from(value: UnixStream) -> Self {
+        Connection::Unix(value)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(unix_stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: credentials and length describe a valid ucred buffer for SO_PEERCRED
+    let result = unsafe {
+        libc::getsockopt(
+            unix_stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+
+    match result {
+        0 => Some(credentials.uid),
+        _ => None,
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn peer_uid(unix_stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+
+    // SAFETY: uid and gid are valid for writes
+    let result = unsafe { libc::getpeereid(unix_stream.as_raw_fd(), &mut uid, &mut gid) };
+
+    match result {
+        0 => Some(uid),
+        _ => None,
+    }
+}
@@ -1,20 +1,39 @@
 use std::{fmt::Display, io::Error};
 
-use crate::common::protocol::error::MessageParseError;
+use crate::common::protocol::error::CodecError;
 
 #[derive(Debug)]
 pub enum MessageStreamError {
     IoError(Error),
-    MessageParseError(MessageParseError),
+    CodecError(CodecError),
+    MessageTooLarge(usize),
+    DecompressedTooLarge(usize),
+    UnexpectedCompression,
+    WebSocketError(String),
 }
 
 impl Display for MessageStreamError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             MessageStreamError::IoError(e) => write!(f, "IoError while streaming message: {}", e),
-            MessageStreamError::MessageParseError(e) => {
-                write!(f, "Error while parsing message: {}", e)
+            MessageStreamError::CodecError(e) => {
+                write!(f, "Error while coding message: {}", e)
             }
+            MessageStreamError::MessageTooLarge(limit) => {
+                write!(f, "Message exceeded the maximum size of {} bytes", limit)
+            }
+            MessageStreamError::DecompressedTooLarge(limit) => write!(
+                f,
+                "Compressed message exceeded the maximum size of {} bytes",
+                limit
+            ),
+            MessageStreamError::UnexpectedCompression => {
+                write!(
+                    f,
+                    "Received a compressed frame without negotiating compression"
+                )
+            }
+            MessageStreamError::WebSocketError(e) => write!(f, "WebSocket error: {}", e),
         }
     }
 }
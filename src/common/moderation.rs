@@ -0,0 +1,314 @@
+pub mod error;
+
+use std::{
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use self::error::ModerationError;
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    packet::{
+        client::{Ban, Kick, Mute, Unban},
+        server::End,
+        BanTarget, EndReason,
+    },
+    serializable::Serializable,
+    username_policy::UsernamePolicy,
+    wire::{self, WireReader},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Moderation {
+    bans: Vec<Ban>,
+    mutes: Vec<Mute>,
+    ban_list_path: Option<PathBuf>,
+}
+
+impl Moderation {
+    pub fn new() -> Moderation {
+        Moderation {
+            bans: Vec::new(),
+            mutes: Vec::new(),
+            ban_list_path: None,
+        }
+    }
+
+    // Loads the ban list from disk if it exists and writes it back there on every change
+    pub fn load(ban_list_path: PathBuf) -> Result<Moderation, ModerationError> {
+        let bans = match fs::read(&ban_list_path) {
+            Ok(bytes) => parse_ban_list(&bytes).map_err(ModerationError::BanListParseError)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(ModerationError::IoError(err)),
+        };
+
+        Ok(Moderation {
+            bans,
+            mutes: Vec::new(),
+            ban_list_path: Some(ban_list_path),
+        })
+    }
+
+    pub fn bans(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    pub fn find_ban(
+        &self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        address: Option<IpAddr>,
+    ) -> Option<&Ban> {
+        let now = unix_time();
+
+        self.bans.iter().find(|ban| {
+            is_active(ban.expires_at, now)
+                && match &ban.target {
+                    BanTarget::Username(banned_username) => {
+                        username_policy.canonicalize(banned_username)
+                            == username_policy.canonicalize(username)
+                    }
+                    BanTarget::Address(banned_address) => Some(*banned_address) == address,
+                }
+        })
+    }
+
+    pub fn is_muted(&self, username_policy: &UsernamePolicy, username: &str) -> bool {
+        let now = unix_time();
+        let canonical_username = username_policy.canonicalize(username);
+
+        self.mutes.iter().any(|mute| {
+            is_active(mute.expires_at, now)
+                && username_policy.canonicalize(&mute.username) == canonical_username
+        })
+    }
+
+    // Returns the End packet the kicked user's session has to be closed with
+    pub fn kick(&self, kick: &Kick) -> End {
+        End::new(EndReason::Kicked, kick.reason.clone())
+    }
+
+    // Returns the End packet the sessions matching the ban have to be closed with
+    // A new ban of the same target, in any spelling of the username, replaces the old one
+    pub fn ban(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        ban: Ban,
+    ) -> Result<End, ModerationError> {
+        let end = End::new(EndReason::Banned, ban.reason.clone());
+
+        // Written out before it takes effect, so a failed save leaves the old list in force
+        let mut bans = self.bans.clone();
+        bans.retain(|existing| !same_target(username_policy, &existing.target, &ban.target));
+        bans.push(ban);
+        self.save(&bans)?;
+        self.bans = bans;
+
+        Ok(end)
+    }
+
+    pub fn unban(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        unban: &Unban,
+    ) -> Result<bool, ModerationError> {
+        let mut bans = self.bans.clone();
+        bans.retain(|ban| !same_target(username_policy, &ban.target, &unban.target));
+
+        let unbanned = bans.len() != self.bans.len();
+        if unbanned {
+            self.save(&bans)?;
+            self.bans = bans;
+        }
+
+        Ok(unbanned)
+    }
+
+    pub fn mute(&mut self, username_policy: &UsernamePolicy, mute: Mute) {
+        let canonical_username = username_policy.canonicalize(&mute.username);
+        self.mutes.retain(|existing| {
+            username_policy.canonicalize(&existing.username) != canonical_username
+        });
+        self.mutes.push(mute);
+    }
+
+    fn save(&self, bans: &[Ban]) -> Result<(), ModerationError> {
+        let ban_list_path = match &self.ban_list_path {
+            Some(ban_list_path) => ban_list_path,
+            None => return Ok(()),
+        };
+
+        let mut bytes = Vec::new();
+        for ban in bans {
+            wire::write_bytes(&mut bytes, &ban.as_bytes());
+        }
+
+        // Write next to the ban list first, so a crash never leaves a truncated file behind
+        let temporary_path = ban_list_path.with_extension("tmp");
+        fs::write(&temporary_path, bytes).map_err(ModerationError::IoError)?;
+        fs::rename(&temporary_path, ban_list_path).map_err(ModerationError::IoError)?;
+
+        Ok(())
+    }
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_ban_list(bytes: &[u8]) -> Result<Vec<Ban>, MessageParseError> {
+    let mut reader = WireReader::new(bytes);
+    let mut bans = Vec::new();
+
+    while !reader.is_empty() {
+        let ban_bytes = reader.read_prefixed_bytes("Ban")?;
+        bans.push(Ban::from_bytes(ban_bytes)?);
+    }
+
+    Ok(bans)
+}
+
+fn same_target(username_policy: &UsernamePolicy, a: &BanTarget, b: &BanTarget) -> bool {
+    match (a, b) {
+        (BanTarget::Username(a), BanTarget::Username(b)) => {
+            username_policy.canonicalize(a) == username_policy.canonicalize(b)
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn is_active(expires_at: Option<u64>, now: u64) -> bool {
+    match expires_at {
+        Some(expires_at) => expires_at > now,
+        None => true,
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ban_list_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty_chat_{}_{}.bans", name, std::process::id()))
+    }
+
+    #[test]
+    fn moderation_finds_active_bans_only() {
+        let username_policy = UsernamePolicy::new();
+        let mut moderation = Moderation::new();
+        let address: IpAddr = "192.0.2.1"
+            .parse()
+            .unwrap_or_else(|err| panic!("Failed to parse address: {}", err));
+
+        let bans = [
+            Ban::new(BanTarget::Username(String::from("Troll")), None, None),
+            Ban::new(BanTarget::Username(String::from("Expired")), Some(1), None),
+            Ban::new(BanTarget::Address(address), Some(u64::MAX), None),
+        ];
+        for ban in bans {
+            moderation
+                .ban(&username_policy, ban)
+                .unwrap_or_else(|err| panic!("Failed to ban: {}", err));
+        }
+
+        assert!(moderation
+            .find_ban(&username_policy, "TROLL", None)
+            .is_some());
+        assert!(moderation
+            .find_ban(&username_policy, "Expired", None)
+            .is_none());
+        assert!(moderation
+            .find_ban(&username_policy, "Innocent", Some(address))
+            .is_some());
+        assert!(moderation
+            .find_ban(&username_policy, "Innocent", None)
+            .is_none());
+    }
+
+    #[test]
+    fn moderation_mutes_until_expiry() {
+        let username_policy = UsernamePolicy::new();
+        let mut moderation = Moderation::new();
+
+        moderation.mute(&username_policy, Mute::new(String::from("Loud"), None));
+        moderation.mute(&username_policy, Mute::new(String::from("Quiet"), Some(1)));
+
+        assert!(moderation.is_muted(&username_policy, "loud"));
+        assert!(!moderation.is_muted(&username_policy, "Quiet"));
+    }
+
+    #[test]
+    fn moderation_persists_ban_list() {
+        let username_policy = UsernamePolicy::new();
+        let path = ban_list_path("persists");
+        let _ = fs::remove_file(&path);
+
+        let ban = Ban::new(
+            BanTarget::Username(String::from("Troll")),
+            None,
+            Some(String::from("Spam")),
+        );
+
+        let mut moderation = Moderation::load(path.clone())
+            .unwrap_or_else(|err| panic!("Failed to load moderation: {}", err));
+        moderation
+            .ban(&username_policy, ban.clone())
+            .unwrap_or_else(|err| panic!("Failed to ban: {}", err));
+
+        // Banning another spelling of the same user replaces the ban instead of adding one
+        let respelled = Ban::new(
+            BanTarget::Username(String::from("TROLL")),
+            Some(u64::MAX),
+            None,
+        );
+        moderation
+            .ban(&username_policy, respelled.clone())
+            .unwrap_or_else(|err| panic!("Failed to ban: {}", err));
+
+        let mut reloaded = Moderation::load(path.clone())
+            .unwrap_or_else(|err| panic!("Failed to reload moderation: {}", err));
+        assert_eq!(reloaded.bans(), &[respelled]);
+
+        let unbanned = reloaded
+            .unban(
+                &username_policy,
+                &Unban::new(BanTarget::Username(String::from("troll"))),
+            )
+            .unwrap_or_else(|err| panic!("Failed to unban: {}", err));
+        assert!(unbanned);
+
+        let reloaded = Moderation::load(path.clone())
+            .unwrap_or_else(|err| panic!("Failed to reload moderation: {}", err));
+        assert!(reloaded.bans().is_empty());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn moderation_keeps_bans_unchanged_when_saving_fails() {
+        let path = std::env::temp_dir()
+            .join(format!("rusty_chat_missing_{}", std::process::id()))
+            .join("bans");
+        let mut moderation = Moderation::load(path)
+            .unwrap_or_else(|err| panic!("Failed to load moderation: {}", err));
+
+        let ban = Ban::new(BanTarget::Username(String::from("Troll")), None, None);
+        assert!(matches!(
+            moderation.ban(&UsernamePolicy::new(), ban),
+            Err(ModerationError::IoError(_))
+        ));
+        assert!(moderation.bans().is_empty());
+    }
+}
@@ -0,0 +1,20 @@
+use std::{fmt::Display, io::Error};
+
+use crate::common::protocol::error::MessageParseError;
+
+#[derive(Debug)]
+pub enum ModerationError {
+    IoError(Error),
+    BanListParseError(MessageParseError),
+}
+
+impl Display for ModerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ModerationError::IoError(e) => write!(f, "IoError while persisting ban list: {}", e),
+            ModerationError::BanListParseError(e) => {
+                write!(f, "Error while parsing ban list: {}", e)
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::common::protocol::{packet::server::EncryptedMessage, username_policy::UsernamePolicy};
+
+pub const DEFAULT_MAX_PER_USER: usize = 100;
+
+// Direct messages held for registered users while they are offline, by canonical username.
+// A full queue turns new messages away instead of dropping old ones, so the sender finds out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OfflineQueue {
+    max_per_user: usize,
+    queues: HashMap<String, VecDeque<EncryptedMessage>>,
+}
+
+impl OfflineQueue {
+    pub fn new(max_per_user: usize) -> OfflineQueue {
+        OfflineQueue {
+            max_per_user,
+            queues: HashMap::new(),
+        }
+    }
+
+    pub fn max_per_user(&self) -> usize {
+        self.max_per_user
+    }
+
+    pub fn set_max_per_user(&mut self, max_per_user: usize) {
+        self.max_per_user = max_per_user;
+    }
+
+    pub fn len_of(&self, username_policy: &UsernamePolicy, username: &str) -> usize {
+        self.queues
+            .get(&username_policy.canonicalize(username))
+            .map_or(0, VecDeque::len)
+    }
+
+    // Returns whether the message was queued
+    pub fn push(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        recipient: &str,
+        encrypted_message: EncryptedMessage,
+    ) -> bool {
+        let queue = self
+            .queues
+            .entry(username_policy.canonicalize(recipient))
+            .or_default();
+        if queue.len() >= self.max_per_user {
+            return false;
+        }
+
+        queue.push_back(encrypted_message);
+        true
+    }
+
+    // Empties the user's queue, oldest message first
+    pub fn take(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+    ) -> Vec<EncryptedMessage> {
+        self.queues
+            .remove(&username_policy.canonicalize(username))
+            .map(Vec::from)
+            .unwrap_or_default()
+    }
+}
+
+impl Default for OfflineQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PER_USER)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::common::protocol::username_policy::UsernamePolicy;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalUser {
+    pub uid: u32,
+    pub username: String,
+}
+
+impl LocalUser {
+    pub fn new(uid: u32, username: String) -> LocalUser {
+        LocalUser { uid, username }
+    }
+}
+
+// Usernames reserved for local processes, matched against the user id the kernel reports for a
+// Unix socket peer. Such a username is admitted from its uid without a key, and from nowhere else.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerCredentials {
+    users: Vec<LocalUser>,
+}
+
+impl PeerCredentials {
+    pub fn new() -> PeerCredentials {
+        PeerCredentials { users: Vec::new() }
+    }
+
+    pub fn users(&self) -> &[LocalUser] {
+        &self.users
+    }
+
+    pub fn allow(&mut self, user: LocalUser) {
+        if !self.users.contains(&user) {
+            self.users.push(user);
+        }
+    }
+
+    // Returns whether the username was reserved for any uid
+    pub fn revoke(&mut self, username_policy: &UsernamePolicy, username: &str) -> bool {
+        let canonical_username = username_policy.canonicalize(username);
+        let user_count = self.users.len();
+
+        self.users
+            .retain(|user| username_policy.canonicalize(&user.username) != canonical_username);
+
+        self.users.len() != user_count
+    }
+
+    pub fn requires_uid(&self, username_policy: &UsernamePolicy, username: &str) -> bool {
+        let canonical_username = username_policy.canonicalize(username);
+
+        self.users
+            .iter()
+            .any(|user| username_policy.canonicalize(&user.username) == canonical_username)
+    }
+
+    pub fn is_allowed(&self, username_policy: &UsernamePolicy, uid: u32, username: &str) -> bool {
+        let canonical_username = username_policy.canonicalize(username);
+
+        self.users.iter().any(|user| {
+            user.uid == uid && username_policy.canonicalize(&user.username) == canonical_username
+        })
+    }
+}
+
+impl Default for PeerCredentials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
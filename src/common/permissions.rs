@@ -0,0 +1,325 @@
+pub mod error;
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use self::error::PermissionError;
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::client,
+    packet::{
+        client::{AssignRole, RevokeRole},
+        Permission, PermissionSet, Role,
+    },
+    username_policy::UsernamePolicy,
+    wire::{self, WireReader},
+};
+
+// The permission a client message requires, checked in one place before any handler runs.
+// Commands declare their permission when they are registered, so the command registry checks those.
+pub fn required_permission(message: &client::Message) -> Option<Permission> {
+    match message {
+        client::Message::Authenticate(_)
+        | client::Message::ChallengeResponse(_)
+        | client::Message::Chat(_)
+        | client::Message::End(_)
+        | client::Message::Command(_)
+        | client::Message::PublishKey(_)
+        | client::Message::RequestKey(_)
+        | client::Message::EncryptedMessage(_)
+        | client::Message::OfferFile(_)
+        | client::Message::FileChunk(_)
+        | client::Message::RequestChunk(_)
+        | client::Message::ReadReceipt(_) => None,
+        client::Message::Kick(_) => Some(Permission::Kick),
+        client::Message::Ban(_) | client::Message::Unban(_) => Some(Permission::Ban),
+        client::Message::Mute(_) => Some(Permission::Mute),
+        client::Message::AssignRole(_) | client::Message::RevokeRole(_) => {
+            Some(Permission::ManageRoles)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Permissions {
+    roles: Vec<Role>,
+    // Keyed by canonical username, so assignments follow the username policy
+    assignments: HashMap<String, Vec<String>>,
+    assignments_path: Option<PathBuf>,
+}
+
+impl Permissions {
+    pub fn new(roles: Vec<Role>) -> Permissions {
+        Permissions {
+            roles,
+            assignments: HashMap::new(),
+            assignments_path: None,
+        }
+    }
+
+    // Loads the role assignments from disk if they exist and writes them back there on every change
+    pub fn load(
+        roles: Vec<Role>,
+        assignments_path: PathBuf,
+    ) -> Result<Permissions, PermissionError> {
+        let assignments = match fs::read(&assignments_path) {
+            Ok(bytes) => {
+                parse_assignments(&bytes).map_err(PermissionError::AssignmentsParseError)?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(PermissionError::IoError(err)),
+        };
+
+        Ok(Permissions {
+            roles,
+            assignments,
+            assignments_path: Some(assignments_path),
+        })
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    pub fn roles_of(&self, username_policy: &UsernamePolicy, username: &str) -> Vec<Role> {
+        let role_names = match self
+            .assignments
+            .get(&username_policy.canonicalize(username))
+        {
+            Some(role_names) => role_names,
+            None => return Vec::new(),
+        };
+
+        self.roles
+            .iter()
+            .filter(|role| role_names.contains(&role.name))
+            .cloned()
+            .collect()
+    }
+
+    pub fn permissions_of(
+        &self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+    ) -> PermissionSet {
+        self.roles_of(username_policy, username)
+            .iter()
+            .fold(PermissionSet::default(), |permissions, role| {
+                permissions.union(role.permissions)
+            })
+    }
+
+    pub fn check(
+        &self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        permission: Permission,
+    ) -> Result<(), PermissionError> {
+        match self
+            .permissions_of(username_policy, username)
+            .contains(permission)
+        {
+            true => Ok(()),
+            false => Err(PermissionError::NotPermitted(
+                String::from(username),
+                permission,
+            )),
+        }
+    }
+
+    pub fn authorize(
+        &self,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        message: &client::Message,
+    ) -> Result<(), PermissionError> {
+        match required_permission(message) {
+            Some(permission) => self.check(username_policy, username, permission),
+            None => Ok(()),
+        }
+    }
+
+    pub fn assign_role(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        assign_role: &AssignRole,
+    ) -> Result<(), PermissionError> {
+        if !self.roles.iter().any(|role| role.name == assign_role.role) {
+            return Err(PermissionError::UnknownRole(assign_role.role.clone()));
+        }
+
+        let role_names = self
+            .assignments
+            .entry(username_policy.canonicalize(&assign_role.username))
+            .or_default();
+
+        if !role_names.contains(&assign_role.role) {
+            role_names.push(assign_role.role.clone());
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn revoke_role(
+        &mut self,
+        username_policy: &UsernamePolicy,
+        revoke_role: &RevokeRole,
+    ) -> Result<bool, PermissionError> {
+        let canonical_username = username_policy.canonicalize(&revoke_role.username);
+
+        let role_names = match self.assignments.get_mut(&canonical_username) {
+            Some(role_names) => role_names,
+            None => return Ok(false),
+        };
+
+        let role_count = role_names.len();
+        role_names.retain(|role_name| *role_name != revoke_role.role);
+        let revoked = role_names.len() != role_count;
+
+        if role_names.is_empty() {
+            self.assignments.remove(&canonical_username);
+        }
+
+        if revoked {
+            self.save()?;
+        }
+
+        Ok(revoked)
+    }
+
+    fn save(&self) -> Result<(), PermissionError> {
+        let assignments_path = match &self.assignments_path {
+            Some(assignments_path) => assignments_path,
+            None => return Ok(()),
+        };
+
+        let mut bytes = Vec::new();
+        for (username, role_names) in &self.assignments {
+            for role_name in role_names {
+                wire::write_str(&mut bytes, username);
+                wire::write_str(&mut bytes, role_name);
+            }
+        }
+
+        // Write next to the assignments first, so a crash never leaves a truncated file behind
+        let temporary_path = assignments_path.with_extension("tmp");
+        fs::write(&temporary_path, bytes).map_err(PermissionError::IoError)?;
+        fs::rename(&temporary_path, assignments_path).map_err(PermissionError::IoError)?;
+
+        Ok(())
+    }
+}
+
+fn parse_assignments(bytes: &[u8]) -> Result<HashMap<String, Vec<String>>, MessageParseError> {
+    let mut reader = WireReader::new(bytes);
+    let mut assignments: HashMap<String, Vec<String>> = HashMap::new();
+
+    while !reader.is_empty() {
+        let username = reader.read_str("Username")?.to_owned();
+        let role_name = reader.read_str("Role")?.to_owned();
+
+        assignments.entry(username).or_default().push(role_name);
+    }
+
+    Ok(assignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::protocol::packet::client::{Chat, Kick};
+
+    fn roles() -> Vec<Role> {
+        vec![
+            Role::new(
+                String::from("moderator"),
+                PermissionSet::new(&[Permission::Kick, Permission::Mute]),
+            ),
+            Role::new(String::from("admin"), PermissionSet::new(&Permission::ALL)),
+        ]
+    }
+
+    #[test]
+    fn permissions_authorize_by_assigned_roles() {
+        let username_policy = UsernamePolicy::new();
+        let mut permissions = Permissions::new(roles());
+
+        permissions
+            .assign_role(
+                &username_policy,
+                &AssignRole::new(String::from("Kitt3120"), String::from("moderator")),
+            )
+            .unwrap_or_else(|err| panic!("Failed to assign role: {}", err));
+
+        let kick = client::Message::Kick(Kick::new(String::from("Troll"), None));
+        let chat = client::Message::Chat(Chat::new(None, String::from("⚡")));
+
+        assert!(permissions
+            .authorize(&username_policy, "kitt3120", &kick)
+            .is_ok());
+        assert!(permissions
+            .authorize(&username_policy, "Troll", &chat)
+            .is_ok());
+
+        match permissions.authorize(&username_policy, "Troll", &kick) {
+            Err(PermissionError::NotPermitted(username, Permission::Kick)) => {
+                assert_eq!(username, "Troll")
+            }
+            other => panic!("Kick by an unprivileged user was not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn permissions_reject_unknown_roles() {
+        let username_policy = UsernamePolicy::new();
+        let mut permissions = Permissions::new(roles());
+
+        let assignment = permissions.assign_role(
+            &username_policy,
+            &AssignRole::new(String::from("Kitt3120"), String::from("owner")),
+        );
+
+        assert!(matches!(assignment, Err(PermissionError::UnknownRole(_))));
+    }
+
+    #[test]
+    fn permissions_persist_assignments() {
+        let username_policy = UsernamePolicy::new();
+        let path = std::env::temp_dir().join(format!(
+            "rusty_chat_assignments_{}.roles",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let mut permissions = Permissions::load(roles(), path.clone())
+            .unwrap_or_else(|err| panic!("Failed to load permissions: {}", err));
+        permissions
+            .assign_role(
+                &username_policy,
+                &AssignRole::new(String::from("Kitt3120"), String::from("admin")),
+            )
+            .unwrap_or_else(|err| panic!("Failed to assign role: {}", err));
+
+        let mut reloaded = Permissions::load(roles(), path.clone())
+            .unwrap_or_else(|err| panic!("Failed to reload permissions: {}", err));
+        assert_eq!(
+            reloaded.permissions_of(&username_policy, "Kitt3120"),
+            PermissionSet::new(&Permission::ALL)
+        );
+
+        let revoked = reloaded
+            .revoke_role(
+                &username_policy,
+                &RevokeRole::new(String::from("Kitt3120"), String::from("admin")),
+            )
+            .unwrap_or_else(|err| panic!("Failed to revoke role: {}", err));
+        assert!(revoked);
+
+        let reloaded = Permissions::load(roles(), path.clone())
+            .unwrap_or_else(|err| panic!("Failed to reload permissions: {}", err));
+        assert!(reloaded.roles_of(&username_policy, "Kitt3120").is_empty());
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,28 @@
+use std::{fmt::Display, io::Error};
+
+use crate::common::protocol::{error::MessageParseError, packet::Permission};
+
+#[derive(Debug)]
+pub enum PermissionError {
+    NotPermitted(String, Permission),
+    UnknownRole(String),
+    IoError(Error),
+    AssignmentsParseError(MessageParseError),
+}
+
+impl Display for PermissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PermissionError::NotPermitted(username, permission) => {
+                write!(f, "{} lacks the permission {}", username, permission)
+            }
+            PermissionError::UnknownRole(role) => write!(f, "Unknown role: {}", role),
+            PermissionError::IoError(e) => {
+                write!(f, "IoError while persisting role assignments: {}", e)
+            }
+            PermissionError::AssignmentsParseError(e) => {
+                write!(f, "Error while parsing role assignments: {}", e)
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+pub mod error;
+
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use self::error::PluginError;
+
+use crate::common::protocol::{
+    message::Message,
+    packet::{server::Chat, Packet},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recipient {
+    Everyone,
+    User(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outgoing {
+    pub recipient: Recipient,
+    pub message: Message,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatVerdict {
+    Relay,
+    Drop,
+}
+
+// Collects the messages a plugin wants to send while one of its hooks runs
+#[derive(Debug, Default)]
+pub struct PluginContext {
+    outgoing: Vec<Outgoing>,
+}
+
+impl PluginContext {
+    pub fn send(&mut self, recipient: Recipient, message: Message) {
+        self.outgoing.push(Outgoing { recipient, message });
+    }
+
+    pub fn say(&mut self, recipient: Recipient, username: &str, text: &str) {
+        let chat = Chat::new(String::from(username), String::from(text));
+        self.send(recipient, chat.to_message());
+    }
+}
+
+// How long a single hook may run before its plugin is disabled
+pub const DEFAULT_HOOK_BUDGET: Duration = Duration::from_millis(100);
+
+// Hooks run while the whole server state is locked, so they must never block.
+// Slow work like network calls belongs on a thread of the plugin's own.
+#[allow(unused_variables)]
+pub trait Plugin: Send {
+    fn name(&self) -> &str;
+
+    fn on_handshake_completed(&mut self, context: &mut PluginContext, username: &str) {}
+
+    // The chat may be modified in place before it is relayed
+    fn on_chat(&mut self, context: &mut PluginContext, chat: &mut Chat) -> ChatVerdict {
+        ChatVerdict::Relay
+    }
+
+    fn on_user_left(&mut self, context: &mut PluginContext, username: &str) {}
+
+    fn on_tick(&mut self, context: &mut PluginContext, now: Instant) {}
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ChatOutcome {
+    pub chat: Option<Chat>,
+    pub outgoing: Vec<Outgoing>,
+}
+
+struct PluginSlot {
+    plugin: Box<dyn Plugin>,
+    disabled: bool,
+}
+
+pub struct PluginHost {
+    plugins: Vec<PluginSlot>,
+    errors: Vec<PluginError>,
+    hook_budget: Duration,
+}
+
+impl Default for PluginHost {
+    fn default() -> PluginHost {
+        PluginHost {
+            plugins: Vec::new(),
+            errors: Vec::new(),
+            hook_budget: DEFAULT_HOOK_BUDGET,
+        }
+    }
+}
+
+impl PluginHost {
+    pub fn new() -> PluginHost {
+        PluginHost::default()
+    }
+
+    pub fn set_hook_budget(&mut self, hook_budget: Duration) {
+        self.hook_budget = hook_budget;
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(PluginSlot {
+            plugin,
+            disabled: false,
+        });
+    }
+
+    pub fn plugin_names(&self) -> Vec<&str> {
+        self.plugins.iter().map(|slot| slot.plugin.name()).collect()
+    }
+
+    // Plugins that panicked or blocked, they are no longer called
+    pub fn errors(&self) -> &[PluginError] {
+        &self.errors
+    }
+
+    pub fn handshake_completed(&mut self, username: &str) -> Vec<Outgoing> {
+        let mut context = PluginContext::default();
+        self.each(&mut context, |plugin, context| {
+            plugin.on_handshake_completed(context, username)
+        });
+
+        context.outgoing
+    }
+
+    pub fn chat(&mut self, chat: Chat) -> ChatOutcome {
+        let mut context = PluginContext::default();
+        let mut chat = Some(chat);
+
+        self.each(&mut context, |plugin, context| {
+            // Once a plugin dropped the chat, the remaining ones don't get to see it
+            if let Some(current) = &mut chat {
+                // Work on a copy, so a plugin that panics halfway can't leave a half-modified chat behind
+                let mut candidate = current.clone();
+                match plugin.on_chat(context, &mut candidate) {
+                    ChatVerdict::Relay => *current = candidate,
+                    ChatVerdict::Drop => chat = None,
+                }
+            }
+        });
+
+        ChatOutcome {
+            chat,
+            outgoing: context.outgoing,
+        }
+    }
+
+    pub fn user_left(&mut self, username: &str) -> Vec<Outgoing> {
+        let mut context = PluginContext::default();
+        self.each(&mut context, |plugin, context| {
+            plugin.on_user_left(context, username)
+        });
+
+        context.outgoing
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Vec<Outgoing> {
+        let mut context = PluginContext::default();
+        self.each(&mut context, |plugin, context| plugin.on_tick(context, now));
+
+        context.outgoing
+    }
+
+    fn each(
+        &mut self,
+        context: &mut PluginContext,
+        mut hook: impl FnMut(&mut dyn Plugin, &mut PluginContext),
+    ) {
+        for slot in self.plugins.iter_mut().filter(|slot| !slot.disabled) {
+            // Messages of a plugin that panics are discarded along with the plugin
+            let mut plugin_context = PluginContext::default();
+
+            let started = Instant::now();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                hook(slot.plugin.as_mut(), &mut plugin_context)
+            }));
+            let elapsed = started.elapsed();
+
+            match result {
+                // The hook already finished, only the next ones are skipped
+                Ok(()) if elapsed > self.hook_budget => {
+                    context.outgoing.append(&mut plugin_context.outgoing);
+                    slot.disabled = true;
+                    self.errors.push(PluginError::TooSlow(
+                        String::from(slot.plugin.name()),
+                        elapsed,
+                    ));
+                }
+                Ok(()) => context.outgoing.append(&mut plugin_context.outgoing),
+                Err(payload) => {
+                    slot.disabled = true;
+                    self.errors.push(PluginError::Panicked(
+                        String::from(slot.plugin.name()),
+                        panic_reason(payload.as_ref()),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for PluginHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginHost")
+            .field("plugins", &self.plugin_names())
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+fn panic_reason(payload: &(dyn Any + Send)) -> String {
+    if let Some(reason) = payload.downcast_ref::<&str>() {
+        return String::from(*reason);
+    }
+
+    if let Some(reason) = payload.downcast_ref::<String>() {
+        return reason.clone();
+    }
+
+    String::from("Unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    struct Shouter;
+
+    impl Plugin for Shouter {
+        fn name(&self) -> &str {
+            "shouter"
+        }
+
+        fn on_chat(&mut self, _context: &mut PluginContext, chat: &mut Chat) -> ChatVerdict {
+            chat.message = chat.message.to_uppercase();
+            ChatVerdict::Relay
+        }
+    }
+
+    struct Censor;
+
+    impl Plugin for Censor {
+        fn name(&self) -> &str {
+            "censor"
+        }
+
+        fn on_chat(&mut self, context: &mut PluginContext, chat: &mut Chat) -> ChatVerdict {
+            if !chat.message.contains("SPAM") {
+                return ChatVerdict::Relay;
+            }
+
+            context.say(
+                Recipient::User(chat.username.clone()),
+                "censor",
+                "Your message was removed",
+            );
+            ChatVerdict::Drop
+        }
+    }
+
+    struct Greeter;
+
+    impl Plugin for Greeter {
+        fn name(&self) -> &str {
+            "greeter"
+        }
+
+        fn on_handshake_completed(&mut self, context: &mut PluginContext, username: &str) {
+            context.say(
+                Recipient::Everyone,
+                "greeter",
+                &format!("Welcome {}", username),
+            );
+        }
+    }
+
+    struct Faulty;
+
+    impl Plugin for Faulty {
+        fn name(&self) -> &str {
+            "faulty"
+        }
+
+        fn on_handshake_completed(&mut self, context: &mut PluginContext, _username: &str) {
+            context.say(Recipient::Everyone, "faulty", "Never delivered");
+            panic!("Faulty plugin");
+        }
+    }
+
+    struct Sleeper;
+
+    impl Plugin for Sleeper {
+        fn name(&self) -> &str {
+            "sleeper"
+        }
+
+        fn on_user_left(&mut self, context: &mut PluginContext, username: &str) {
+            thread::sleep(Duration::from_millis(50));
+            context.say(Recipient::Everyone, "sleeper", &format!("Bye {}", username));
+        }
+    }
+
+    #[test]
+    fn host_runs_chat_hooks_in_order() {
+        let mut host = PluginHost::new();
+        host.register(Box::new(Shouter));
+        host.register(Box::new(Censor));
+
+        let outcome = host.chat(Chat::new(String::from("Kitt3120"), String::from("hi")));
+        assert_eq!(
+            outcome.chat,
+            Some(Chat::new(String::from("Kitt3120"), String::from("HI")))
+        );
+        assert!(outcome.outgoing.is_empty());
+
+        let outcome = host.chat(Chat::new(String::from("Troll"), String::from("spam")));
+        assert_eq!(outcome.chat, None);
+        assert_eq!(
+            outcome.outgoing,
+            vec![Outgoing {
+                recipient: Recipient::User(String::from("Troll")),
+                message: Chat::new(
+                    String::from("censor"),
+                    String::from("Your message was removed")
+                )
+                .to_message(),
+            }]
+        );
+    }
+
+    #[test]
+    fn host_disables_panicking_plugins() {
+        let mut host = PluginHost::new();
+        host.register(Box::new(Faulty));
+        host.register(Box::new(Greeter));
+
+        let outgoing = host.handshake_completed("Kitt3120");
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(
+            host.errors(),
+            &[PluginError::Panicked(
+                String::from("faulty"),
+                String::from("Faulty plugin")
+            )]
+        );
+
+        let outgoing = host.handshake_completed("Kitt3120");
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(host.errors().len(), 1);
+    }
+
+    #[test]
+    fn host_disables_plugins_that_block() {
+        let mut host = PluginHost::new();
+        host.set_hook_budget(Duration::from_millis(10));
+        host.register(Box::new(Sleeper));
+        host.register(Box::new(Greeter));
+
+        assert_eq!(host.user_left("Kitt3120").len(), 1);
+        assert!(matches!(
+            host.errors(),
+            [PluginError::TooSlow(plugin, elapsed)]
+                if plugin == "sleeper" && *elapsed >= Duration::from_millis(50)
+        ));
+
+        assert!(host.user_left("Kitt3120").is_empty());
+        assert_eq!(host.handshake_completed("Kitt3120").len(), 1);
+        assert_eq!(host.errors().len(), 1);
+    }
+}
@@ -0,0 +1,22 @@
+use std::{fmt::Display, time::Duration};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PluginError {
+    Panicked(String, String),
+    TooSlow(String, Duration),
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginError::Panicked(plugin, reason) => {
+                write!(f, "Plugin {} panicked and was disabled: {}", plugin, reason)
+            }
+            PluginError::TooSlow(plugin, elapsed) => write!(
+                f,
+                "Plugin {} blocked for {:?} and was disabled",
+                plugin, elapsed
+            ),
+        }
+    }
+}
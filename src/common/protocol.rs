@@ -1,5 +1,11 @@
+pub mod codec;
 pub mod error;
 pub mod handshake;
 pub mod message;
 pub mod packet;
 pub mod serializable;
+pub mod username_policy;
+pub mod wire;
+
+// Bumped whenever old and new peers can no longer understand each other
+pub const PROTOCOL_VERSION: u16 = 2;
@@ -0,0 +1,48 @@
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "messagepack")]
+pub mod message_pack;
+
+#[cfg(feature = "cbor")]
+pub use cbor::CborCodec;
+#[cfg(feature = "json")]
+pub use json::{JsonCodec, JsonLinesCodec};
+#[cfg(feature = "messagepack")]
+pub use message_pack::MessagePackCodec;
+
+use crate::common::protocol::{error::CodecError, message::Message, serializable::Serializable};
+
+// How a MessageStream tells where one encoded message ends and the next one begins
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Framing {
+    // A little-endian u32 length in front of every message, which also carries the compression flag
+    #[default]
+    LengthPrefixed,
+    // One message per line, for codecs whose encoding has no newline but the one it ends with
+    NewlineDelimited,
+}
+
+pub trait Codec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError>;
+
+    fn framing(&self) -> Framing {
+        Framing::LengthPrefixed
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BinaryCodec;
+
+impl Codec for BinaryCodec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError> {
+        Ok(message.as_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        Message::from_bytes(bytes).map_err(CodecError::MessageParse)
+    }
+}
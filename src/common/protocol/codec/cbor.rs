@@ -0,0 +1,40 @@
+use crate::common::protocol::{codec::Codec, error::CodecError, message::Message};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+
+        ciborium::into_writer(message, &mut bytes)
+            .map_err(|err| CodecError::Encode(err.to_string()))?;
+
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        ciborium::from_reader(bytes).map_err(|err| CodecError::Decode(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::protocol::packet::{server::Chat, Packet};
+
+    #[test]
+    fn cbor_codec_converts_correctly() {
+        let message = Chat::new(String::from("Kitt3120"), String::from("⚡")).to_message();
+
+        let bytes = CborCodec
+            .encode(&message)
+            .unwrap_or_else(|err| panic!("Failed to encode message: {}", err));
+
+        let parsed_message = CborCodec
+            .decode(&bytes)
+            .unwrap_or_else(|err| panic!("Failed to decode message: {}", err));
+
+        assert_eq!(message, parsed_message);
+    }
+}
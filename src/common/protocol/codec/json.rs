@@ -0,0 +1,132 @@
+use crate::common::protocol::{
+    codec::{Codec, Framing},
+    error::CodecError,
+    message::Message,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError> {
+        let mut bytes =
+            serde_json::to_vec(message).map_err(|err| CodecError::Encode(err.to_string()))?;
+        bytes.push(b'\n');
+
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        serde_json::from_slice(bytes.trim_ascii())
+            .map_err(|err| CodecError::Decode(err.to_string()))
+    }
+}
+
+// The same JSON lines without the binary length prefix, so a script only has to read and write lines.
+// There is no room for the compression flag, so these streams stay uncompressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JsonLinesCodec;
+
+impl Codec for JsonLinesCodec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError> {
+        JsonCodec.encode(message)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        JsonCodec.decode(bytes)
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::NewlineDelimited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::common::{
+        message_stream::MessageStream,
+        protocol::packet::{server::Chat, Packet},
+    };
+
+    #[test]
+    fn json_codec_converts_correctly() {
+        let message = Chat::new(String::from("Kitt3120"), String::from("⚡")).to_message();
+
+        let bytes = JsonCodec
+            .encode(&message)
+            .unwrap_or_else(|err| panic!("Failed to encode message: {}", err));
+
+        assert_eq!(bytes.last(), Some(&b'\n'));
+        assert!(!bytes[..bytes.len() - 1].contains(&b'\n'));
+
+        let parsed_message = JsonCodec
+            .decode(&bytes)
+            .unwrap_or_else(|err| panic!("Failed to decode message: {}", err));
+
+        assert_eq!(message, parsed_message);
+    }
+
+    #[test]
+    fn json_lines_codec_needs_no_length_prefix() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+        let mut script =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let (accepted, _) = listener
+            .accept()
+            .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+        let mut message_stream = MessageStream::with_codec(accepted, JsonLinesCodec);
+
+        let messages: Vec<Message> = ["Hi", "⚡", "Still here"]
+            .into_iter()
+            .map(|text| Chat::new(String::from("Kitt3120"), String::from(text)).to_message())
+            .collect();
+        let mut lines = Vec::new();
+        for message in &messages {
+            lines.extend(
+                JsonCodec
+                    .encode(message)
+                    .unwrap_or_else(|err| panic!("Failed to encode message: {}", err)),
+            );
+        }
+
+        // Two lines in one write and one split across two, as a script might send them
+        let split = lines.len() - 5;
+        for part in [&lines[..split], &lines[split..]] {
+            script
+                .write_all(part)
+                .unwrap_or_else(|err| panic!("Failed to write: {}", err));
+        }
+        for message in &messages {
+            let received = message_stream
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read message: {}", err));
+            assert_eq!(&received, message);
+        }
+
+        message_stream
+            .send_message(&messages[0])
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+        let mut line = String::new();
+        BufReader::new(script)
+            .read_line(&mut line)
+            .unwrap_or_else(|err| panic!("Failed to read line: {}", err));
+        assert!(line.starts_with('{'));
+        assert_eq!(
+            JsonLinesCodec
+                .decode(line.as_bytes())
+                .unwrap_or_else(|err| panic!("Failed to decode message: {}", err)),
+            messages[0]
+        );
+    }
+}
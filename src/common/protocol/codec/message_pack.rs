@@ -0,0 +1,35 @@
+use crate::common::protocol::{codec::Codec, error::CodecError, message::Message};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, CodecError> {
+        rmp_serde::to_vec(message).map_err(|err| CodecError::Encode(err.to_string()))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        rmp_serde::from_slice(bytes).map_err(|err| CodecError::Decode(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::protocol::packet::{server::Chat, Packet};
+
+    #[test]
+    fn message_pack_codec_converts_correctly() {
+        let message = Chat::new(String::from("Kitt3120"), String::from("⚡")).to_message();
+
+        let bytes = MessagePackCodec
+            .encode(&message)
+            .unwrap_or_else(|err| panic!("Failed to encode message: {}", err));
+
+        let parsed_message = MessagePackCodec
+            .decode(&bytes)
+            .unwrap_or_else(|err| panic!("Failed to decode message: {}", err));
+
+        assert_eq!(message, parsed_message);
+    }
+}
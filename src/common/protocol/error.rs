@@ -1,5 +1,9 @@
+pub mod codec;
 pub mod handshake;
 pub mod message_parse;
+pub mod username;
 
+pub use codec::CodecError;
 pub use handshake::HandshakeError;
 pub use message_parse::MessageParseError;
+pub use username::UsernameError;
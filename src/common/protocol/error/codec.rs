@@ -0,0 +1,20 @@
+use std::fmt::Display;
+
+use crate::common::protocol::error::MessageParseError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodecError {
+    MessageParse(MessageParseError),
+    Encode(String),
+    Decode(String),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::MessageParse(err) => write!(f, "Error while parsing message: {}", err),
+            CodecError::Encode(reason) => write!(f, "Unable to encode message: {}", reason),
+            CodecError::Decode(reason) => write!(f, "Unable to decode message: {}", reason),
+        }
+    }
+}
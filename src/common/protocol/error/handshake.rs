@@ -1,11 +1,24 @@
-use crate::common::{message_stream::error::MessageStreamError, protocol::message::Message};
+use crate::common::{
+    message_stream::error::MessageStreamError,
+    protocol::{
+        error::UsernameError,
+        message::Message,
+        packet::{client::Ban, EndReason},
+    },
+};
 use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum HandshakeError {
     MessageStreamError(MessageStreamError),
     UnexpectedMessage(Message),
-    AuthenticationFailed(String),
+    AuthenticationFailed(EndReason, Option<String>),
+    UsernameRejected(UsernameError),
+    Banned(Ban),
+    KeyRequired(String),
+    KeyNotAuthorized(String),
+    InvalidSignature(String),
+    PeerNotAuthorized(String),
 }
 
 impl Display for HandshakeError {
@@ -17,8 +30,25 @@ impl Display for HandshakeError {
             HandshakeError::UnexpectedMessage(message) => {
                 write!(f, "Unexpected message: {}", message)
             }
-            HandshakeError::AuthenticationFailed(reason) => {
-                write!(f, "Authentication failed: {}", reason)
+            HandshakeError::AuthenticationFailed(reason, text) => match text {
+                Some(text) => write!(f, "Authentication failed: {}: {}", reason, text),
+                None => write!(f, "Authentication failed: {}", reason),
+            },
+            HandshakeError::UsernameRejected(err) => {
+                write!(f, "Rejected username: {}", err)
+            }
+            HandshakeError::Banned(ban) => write!(f, "Banned: {}", ban),
+            HandshakeError::KeyRequired(username) => {
+                write!(f, "{} has to authenticate with a key", username)
+            }
+            HandshakeError::KeyNotAuthorized(username) => {
+                write!(f, "Key is not authorized for {}", username)
+            }
+            HandshakeError::InvalidSignature(username) => {
+                write!(f, "Invalid challenge signature for {}", username)
+            }
+            HandshakeError::PeerNotAuthorized(username) => {
+                write!(f, "{} is reserved for another local user", username)
             }
         }
     }
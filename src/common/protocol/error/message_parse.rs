@@ -1,11 +1,11 @@
-use std::{fmt::Display, string::FromUtf8Error};
+use std::{fmt::Display, str::Utf8Error};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum MessageParseError {
     MessageEmpty,
     UnexcpetedEndOfMessage,
     UnknownKind(u8),
-    StringParse(String, FromUtf8Error),
+    StringParse(String, Utf8Error),
     ByteParse(String),
 }
 
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+
+use crate::common::protocol::packet::EndReason;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UsernameError {
+    TooShort(usize),
+    TooLong(usize),
+    DisallowedCharacter(char),
+    Reserved(String),
+    Taken(String),
+}
+
+impl UsernameError {
+    pub fn end_reason(&self) -> EndReason {
+        match self {
+            UsernameError::TooShort(_)
+            | UsernameError::TooLong(_)
+            | UsernameError::DisallowedCharacter(_) => EndReason::UsernameInvalid,
+            UsernameError::Reserved(_) => EndReason::UsernameReserved,
+            UsernameError::Taken(_) => EndReason::UsernameTaken,
+        }
+    }
+}
+
+impl Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameError::TooShort(min_length) => write!(
+                f,
+                "Username must be at least {} characters long",
+                min_length
+            ),
+            UsernameError::TooLong(max_length) => {
+                write!(f, "Username must be at most {} characters long", max_length)
+            }
+            UsernameError::DisallowedCharacter(character) => {
+                write!(f, "Username contains disallowed character {:?}", character)
+            }
+            UsernameError::Reserved(username) => write!(f, "Username {} is reserved", username),
+            UsernameError::Taken(username) => write!(f, "Username {} is already taken", username),
+        }
+    }
+}
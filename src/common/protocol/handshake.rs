@@ -1,2 +1,16 @@
 pub mod client;
 pub mod server;
+
+use crate::common::protocol::wire;
+
+const CHALLENGE_CONTEXT: &[u8] = b"rusty_chat authentication v1";
+
+// What the client signs for a server::Challenge. The context and username keep a signature
+// from being replayed for another username or passed off as a signature for something else.
+pub fn challenge_payload(username: &str, nonce: &[u8; 32]) -> Vec<u8> {
+    let mut payload = CHALLENGE_CONTEXT.to_vec();
+    wire::write_str(&mut payload, username);
+    payload.extend_from_slice(nonce);
+
+    payload
+}
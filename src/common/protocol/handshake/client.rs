@@ -1,80 +1,173 @@
+use ed25519_dalek::{Signer, SigningKey};
+
 use crate::common::{
-    message_stream::MessageStream,
+    message_stream::MessageTransport,
     protocol::{
         error::HandshakeError,
+        handshake::challenge_payload,
         message::{server, Message},
-        packet::{client::Authenticate, server::Authenticated, Packet},
+        packet::{
+            client::{Authenticate, ChallengeResponse},
+            server::{Authenticated, Challenge},
+            Compression, Packet, Role,
+        },
     },
 };
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct HandshakeArguments {
     username: String,
+    signing_key: Option<SigningKey>,
+    compression: Compression,
+    device: Option<String>,
 }
 
 impl HandshakeArguments {
-    pub fn new(username: String) -> HandshakeArguments {
-        HandshakeArguments { username }
+    // With a signing key the client authenticates like an SSH login, the server must have its public key registered.
+    // The compression is only an offer, the server may turn it down.
+    pub fn new(
+        username: String,
+        signing_key: Option<SigningKey>,
+        compression: Compression,
+        device: Option<String>,
+    ) -> HandshakeArguments {
+        HandshakeArguments {
+            username,
+            signing_key,
+            compression,
+            device,
+        }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Handshake {
     username: String,
+    roles: Vec<Role>,
+    compression: Compression,
 }
 
 impl Handshake {
-    fn new(username: String) -> Handshake {
-        Handshake { username }
+    fn new(username: String, roles: Vec<Role>, compression: Compression) -> Handshake {
+        Handshake {
+            username,
+            roles,
+            compression,
+        }
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    // Lets the client hide actions the server would reject anyway
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
     }
 
-    pub fn perform(
-        message_stream: &mut MessageStream,
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn perform<T: MessageTransport>(
+        transport: &mut T,
         arguments: HandshakeArguments,
     ) -> Result<Handshake, HandshakeError> {
-        send_authentication(message_stream, arguments.username.clone())?;
-        let _authenticated = receive_authentication_result(message_stream)?;
-        // Currently, the server's authenticated packet does not contain any data, so we don't need anything from it.
-        // However, it's already implemented here so we don't have to adapt here again when finally adding data to the packet.
+        let compression = match transport.supports_compression() {
+            true => arguments.compression,
+            false => Compression::None,
+        };
+
+        send_authentication(transport, &arguments, compression)?;
+        let authenticated = receive_authentication_result(transport, &arguments)?;
 
-        let handshake = Handshake::new(arguments.username);
+        if authenticated.compression != Compression::None
+            && authenticated.compression != compression
+        {
+            return Err(HandshakeError::UnexpectedMessage(
+                authenticated.to_message(),
+            ));
+        }
+        transport.set_compression(authenticated.compression);
+
+        let handshake = Handshake::new(
+            arguments.username,
+            authenticated.roles,
+            authenticated.compression,
+        );
         Ok(handshake)
     }
 }
 
-fn send_authentication(
-    message_stream: &mut MessageStream,
-    username: String,
+fn send_authentication<T: MessageTransport>(
+    transport: &mut T,
+    arguments: &HandshakeArguments,
+    compression: Compression,
 ) -> Result<(), HandshakeError> {
-    let authenticate_packet = Authenticate::new(username);
+    let public_key = arguments
+        .signing_key
+        .as_ref()
+        .map(|signing_key| signing_key.verifying_key().to_bytes());
+    let authenticate_packet = Authenticate::new(
+        arguments.username.clone(),
+        public_key,
+        compression,
+        arguments.device.clone(),
+    );
     let message = authenticate_packet.to_message();
 
-    message_stream
+    transport
         .send_message(&message)
         .map_err(HandshakeError::MessageStreamError)?;
 
     Ok(())
 }
 
-fn receive_authentication_result(
-    message_stream: &mut MessageStream,
+fn answer_challenge<T: MessageTransport>(
+    transport: &mut T,
+    arguments: &HandshakeArguments,
+    challenge: Challenge,
+) -> Result<(), HandshakeError> {
+    // The server only challenges clients that sent a public key
+    let signing_key = match &arguments.signing_key {
+        Some(signing_key) => signing_key,
+        None => return Err(HandshakeError::UnexpectedMessage(challenge.to_message())),
+    };
+
+    let signature = signing_key.sign(&challenge_payload(&arguments.username, &challenge.nonce));
+    let challenge_response_packet = ChallengeResponse::new(signature.to_bytes().to_vec());
+
+    transport
+        .send_message(&challenge_response_packet.to_message())
+        .map_err(HandshakeError::MessageStreamError)
+}
+
+fn receive_authentication_result<T: MessageTransport>(
+    transport: &mut T,
+    arguments: &HandshakeArguments,
 ) -> Result<Authenticated, HandshakeError> {
-    let message = message_stream
-        .read_message()
-        .map_err(HandshakeError::MessageStreamError)?;
+    let mut challenged = false;
 
-    let authenticated_packet = match message {
-        Message::Server(message) => match message {
-            server::Message::Authenticated(authenticated) => authenticated,
-            server::Message::End(end) => {
-                return Err(HandshakeError::AuthenticationFailed(end.reason))
-            }
-            _ => return Err(HandshakeError::UnexpectedMessage(Message::Server(message))),
-        },
-        _ => return Err(HandshakeError::UnexpectedMessage(message)),
-    };
+    loop {
+        let message = transport
+            .read_message()
+            .map_err(HandshakeError::MessageStreamError)?;
 
-    Ok(authenticated_packet)
+        match message {
+            Message::Server(message) => match message {
+                server::Message::Authenticated(authenticated) => return Ok(authenticated),
+                server::Message::End(end) => {
+                    return Err(HandshakeError::AuthenticationFailed(end.reason, end.text))
+                }
+                server::Message::Challenge(challenge) if !challenged => {
+                    answer_challenge(transport, arguments, challenge)?;
+                    challenged = true;
+                }
+                _ => return Err(HandshakeError::UnexpectedMessage(Message::Server(message))),
+            },
+            _ => return Err(HandshakeError::UnexpectedMessage(message)),
+        }
+    }
 }
 
 //TODO: Test
@@ -1,57 +1,126 @@
+use std::net::IpAddr;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+
 use crate::common::{
-    message_stream::MessageStream,
+    authorized_keys::AuthorizedKeys,
+    message_stream::MessageTransport,
+    moderation::Moderation,
+    peer_credentials::PeerCredentials,
+    permissions::Permissions,
     protocol::{
         error::HandshakeError,
+        handshake::challenge_payload,
         message::{client, Message},
         packet::{
-            client::Authenticate,
-            server::{Authenticated, End},
-            Packet,
+            client::{Authenticate, ChallengeResponse},
+            server::{Authenticated, Challenge, End},
+            Compression, EndReason, Packet,
         },
+        username_policy::UsernamePolicy,
     },
 };
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct HandshakeArguments<'a> {
     taken_usernames: &'a [String],
+    username_policy: &'a UsernamePolicy,
+    moderation: &'a Moderation,
+    permissions: &'a Permissions,
+    authorized_keys: &'a AuthorizedKeys,
+    peer_credentials: &'a PeerCredentials,
 }
 
 impl<'a> HandshakeArguments<'a> {
-    pub fn new(taken_usernames: &'a [String]) -> HandshakeArguments<'a> {
-        HandshakeArguments { taken_usernames }
+    pub fn new(
+        taken_usernames: &'a [String],
+        username_policy: &'a UsernamePolicy,
+        moderation: &'a Moderation,
+        permissions: &'a Permissions,
+        authorized_keys: &'a AuthorizedKeys,
+        peer_credentials: &'a PeerCredentials,
+    ) -> HandshakeArguments<'a> {
+        HandshakeArguments {
+            taken_usernames,
+            username_policy,
+            moderation,
+            permissions,
+            authorized_keys,
+            peer_credentials,
+        }
+    }
+
+    // Registered accounts prove who they are, so they may be logged in from several devices at once
+    fn is_registered(&self, username: &str) -> bool {
+        self.authorized_keys
+            .requires_key(self.username_policy, username)
+            || self
+                .peer_credentials
+                .requires_uid(self.username_policy, username)
     }
 }
 
+// Device names are only ever displayed, longer ones are cut short
+pub const MAX_DEVICE_NAME_LENGTH: usize = 32;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Handshake {
     username: String,
+    device: Option<String>,
+    compression: Compression,
 }
 
 impl Handshake {
-    fn new(username: String) -> Handshake {
-        Handshake { username }
+    fn new(username: String, device: Option<String>, compression: Compression) -> Handshake {
+        Handshake {
+            username,
+            device,
+            compression,
+        }
     }
 
-    pub fn perform(
-        message_stream: &mut MessageStream,
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn perform<T: MessageTransport>(
+        transport: &mut T,
         arguments: HandshakeArguments,
     ) -> Result<Handshake, HandshakeError> {
-        let authenticate_packet = receive_authentication(message_stream)?;
-        send_authentication_result(
-            message_stream,
-            arguments.taken_usernames,
-            authenticate_packet.username.clone(),
-        )?;
-
-        let handshake = Handshake::new(authenticate_packet.username);
+        let authenticate_packet = receive_authentication(transport)?;
+
+        let address = transport.peer_address();
+        let admission = match admit(&arguments, &authenticate_packet.username, address) {
+            Ok(username) => authenticate(transport, &arguments, &authenticate_packet, username),
+            Err(err) => Err(err),
+        };
+        // The client only offers what it can decode
+        let compression = match transport.supports_compression() {
+            true => authenticate_packet.compression,
+            false => Compression::None,
+        };
+        let username = send_authentication_result(transport, &arguments, admission, compression)?;
+        transport.set_compression(compression);
+
+        let device = authenticate_packet.device.as_deref().and_then(device_name);
+        let handshake = Handshake::new(username, device, compression);
         Ok(handshake)
     }
 }
 
-fn receive_authentication(
-    message_stream: &mut MessageStream,
+fn receive_authentication<T: MessageTransport>(
+    transport: &mut T,
 ) -> Result<Authenticate, HandshakeError> {
-    let message = message_stream
+    let message = transport
         .read_message()
         .map_err(HandshakeError::MessageStreamError)?;
 
@@ -66,27 +135,464 @@ fn receive_authentication(
     Ok(authenticate_packet)
 }
 
-fn send_authentication_result(
-    message_stream: &mut MessageStream,
-    taken_usernames: &[String],
+// A local peer whose uid the username is reserved for needs nothing else, a reserved username
+// can't be claimed from anywhere else. Otherwise clients without a key are only admitted if the
+// username has none registered. With one, the client has to sign a fresh challenge to prove it
+// holds the private key.
+fn authenticate<T: MessageTransport>(
+    transport: &mut T,
+    arguments: &HandshakeArguments,
+    authenticate_packet: &Authenticate,
     username: String,
-) -> Result<(), HandshakeError> {
-    let username_taken = taken_usernames.contains(&username);
-    let message = match username_taken {
-        true => {
-            let end_packet = End::new(String::from("Username already taken"));
-            end_packet.to_message()
+) -> Result<String, HandshakeError> {
+    let peer_allowed = transport.peer_uid().is_some_and(|uid| {
+        arguments
+            .peer_credentials
+            .is_allowed(arguments.username_policy, uid, &username)
+    });
+    if peer_allowed {
+        return Ok(username);
+    }
+    if arguments
+        .peer_credentials
+        .requires_uid(arguments.username_policy, &username)
+    {
+        return Err(HandshakeError::PeerNotAuthorized(username));
+    }
+
+    let public_key = match authenticate_packet.public_key {
+        Some(public_key) => public_key,
+        None if arguments
+            .authorized_keys
+            .requires_key(arguments.username_policy, &username) =>
+        {
+            return Err(HandshakeError::KeyRequired(username))
         }
-        false => {
-            let authenticated_packet = Authenticated::new();
+        None => return Ok(username),
+    };
+
+    if !arguments
+        .authorized_keys
+        .is_authorized(arguments.username_policy, &username, &public_key)
+    {
+        return Err(HandshakeError::KeyNotAuthorized(username));
+    }
+    let verifying_key = VerifyingKey::from_bytes(&public_key)
+        .map_err(|_| HandshakeError::KeyNotAuthorized(username.clone()))?;
+
+    let mut nonce = [0; 32];
+    OsRng.fill_bytes(&mut nonce);
+
+    transport
+        .send_message(&Challenge::new(nonce).to_message())
+        .map_err(HandshakeError::MessageStreamError)?;
+    let challenge_response = receive_challenge_response(transport)?;
+
+    let signature = Signature::from_slice(&challenge_response.signature)
+        .map_err(|_| HandshakeError::InvalidSignature(username.clone()))?;
+    verifying_key
+        .verify_strict(
+            &challenge_payload(&authenticate_packet.username, &nonce),
+            &signature,
+        )
+        .map_err(|_| HandshakeError::InvalidSignature(username.clone()))?;
+
+    Ok(username)
+}
+
+fn receive_challenge_response<T: MessageTransport>(
+    transport: &mut T,
+) -> Result<ChallengeResponse, HandshakeError> {
+    let message = transport
+        .read_message()
+        .map_err(HandshakeError::MessageStreamError)?;
+
+    match message {
+        Message::Client(client::Message::ChallengeResponse(challenge_response)) => {
+            Ok(challenge_response)
+        }
+        _ => Err(HandshakeError::UnexpectedMessage(message)),
+    }
+}
+
+fn send_authentication_result<T: MessageTransport>(
+    transport: &mut T,
+    arguments: &HandshakeArguments,
+    admission: Result<String, HandshakeError>,
+    compression: Compression,
+) -> Result<String, HandshakeError> {
+    // Nothing can be sent over a broken stream
+    if let Err(HandshakeError::MessageStreamError(_)) = admission {
+        return admission;
+    }
+
+    let message = match &admission {
+        Ok(username) => {
+            let roles = arguments
+                .permissions
+                .roles_of(arguments.username_policy, username);
+            let authenticated_packet = Authenticated::new(roles, compression);
             authenticated_packet.to_message()
         }
+        Err(err) => {
+            let end_packet = rejection_end(err);
+            end_packet.to_message()
+        }
     };
 
-    message_stream
+    transport
         .send_message(&message)
         .map_err(HandshakeError::MessageStreamError)?;
 
-    Ok(())
+    admission
+}
+
+// Only usernames in use by unregistered sessions are taken, a registered one is checked by
+// authenticate like any other login
+fn admit(
+    arguments: &HandshakeArguments,
+    username: &str,
+    address: Option<IpAddr>,
+) -> Result<String, HandshakeError> {
+    let taken_usernames: Vec<String> = arguments
+        .taken_usernames
+        .iter()
+        .filter(|taken_username| !arguments.is_registered(taken_username))
+        .cloned()
+        .collect();
+    let username = arguments
+        .username_policy
+        .validate(username, &taken_usernames)
+        .map_err(HandshakeError::UsernameRejected)?;
+
+    if let Some(ban) = arguments
+        .moderation
+        .find_ban(arguments.username_policy, &username, address)
+    {
+        return Err(HandshakeError::Banned(ban.clone()));
+    }
+
+    Ok(username)
+}
+
+fn device_name(device: &str) -> Option<String> {
+    let device: String = device
+        .chars()
+        .filter(|character| !character.is_control())
+        .take(MAX_DEVICE_NAME_LENGTH)
+        .collect();
+
+    match device.trim() {
+        "" => None,
+        device => Some(device.to_owned()),
+    }
+}
+
+fn rejection_end(err: &HandshakeError) -> End {
+    match err {
+        HandshakeError::UsernameRejected(err) => End::new(err.end_reason(), Some(err.to_string())),
+        HandshakeError::Banned(ban) => End::new(EndReason::Banned, ban.reason.clone()),
+        HandshakeError::KeyRequired(_)
+        | HandshakeError::KeyNotAuthorized(_)
+        | HandshakeError::InvalidSignature(_)
+        | HandshakeError::PeerNotAuthorized(_) => {
+            End::new(EndReason::Unauthorized, Some(err.to_string()))
+        }
+        HandshakeError::UnexpectedMessage(_) => End::new(EndReason::ProtocolMismatch, None),
+        _ => End::new(EndReason::Unspecified, None),
+    }
+}
+
+// Owns everything HandshakeArguments borrows, so a test sets up a server handshake in one line
+#[cfg(test)]
+pub(crate) struct HandshakeFixture {
+    pub taken_usernames: Vec<String>,
+    pub username_policy: UsernamePolicy,
+    pub moderation: Moderation,
+    pub permissions: Permissions,
+    pub authorized_keys: AuthorizedKeys,
+    pub peer_credentials: PeerCredentials,
+}
+
+#[cfg(test)]
+impl HandshakeFixture {
+    pub fn new() -> HandshakeFixture {
+        HandshakeFixture {
+            taken_usernames: Vec::new(),
+            username_policy: UsernamePolicy::new(),
+            moderation: Moderation::new(),
+            permissions: Permissions::new(Vec::new()),
+            authorized_keys: AuthorizedKeys::new(),
+            peer_credentials: PeerCredentials::new(),
+        }
+    }
+
+    pub fn arguments(&self) -> HandshakeArguments<'_> {
+        HandshakeArguments::new(
+            &self.taken_usernames,
+            &self.username_policy,
+            &self.moderation,
+            &self.permissions,
+            &self.authorized_keys,
+            &self.peer_credentials,
+        )
+    }
+
+    pub fn perform<T: MessageTransport>(
+        &self,
+        transport: &mut T,
+    ) -> Result<Handshake, HandshakeError> {
+        Handshake::perform(transport, self.arguments())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use ed25519_dalek::SigningKey;
+
+    use crate::common::{
+        authorized_keys::AuthorizedKey,
+        message_stream::MessageStream,
+        peer_credentials::LocalUser,
+        protocol::{
+            error::UsernameError,
+            handshake::client::{self as client_handshake},
+            packet::{client::Ban, BanTarget},
+        },
+    };
+
+    type Results = (
+        Result<Handshake, HandshakeError>,
+        Result<client_handshake::Handshake, HandshakeError>,
+    );
+
+    // Runs both sides on the given streams, returning the server's result and the client's result
+    fn run(
+        mut server_stream: MessageStream,
+        mut client_stream: MessageStream,
+        username: &str,
+        fixture: &HandshakeFixture,
+        signing_key: Option<SigningKey>,
+    ) -> Results {
+        let username = username.to_owned();
+        let client = thread::spawn(move || {
+            let arguments = client_handshake::HandshakeArguments::new(
+                username,
+                signing_key,
+                Compression::None,
+                None,
+            );
+            client_handshake::Handshake::perform(&mut client_stream, arguments)
+        });
+
+        let server_result = fixture.perform(&mut server_stream);
+        let client_result = client
+            .join()
+            .unwrap_or_else(|_| panic!("Client thread panicked"));
+
+        (server_result, client_result)
+    }
+
+    // Runs both sides over loopback TCP
+    fn perform(
+        username: &str,
+        fixture: &HandshakeFixture,
+        signing_key: Option<SigningKey>,
+    ) -> Results {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        let client_stream =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let (server_stream, _) = listener
+            .accept()
+            .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+
+        run(
+            MessageStream::new(server_stream),
+            MessageStream::new(client_stream),
+            username,
+            fixture,
+            signing_key,
+        )
+    }
+
+    #[test]
+    fn handshake_verifies_key_challenges() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mut fixture = HandshakeFixture::new();
+        fixture
+            .authorized_keys
+            .add(AuthorizedKey::new(
+                String::from("ops"),
+                signing_key.verifying_key().to_bytes(),
+                None,
+            ))
+            .unwrap_or_else(|err| panic!("Failed to add key: {}", err));
+
+        let (server_result, client_result) = perform("Ops", &fixture, Some(signing_key));
+        assert!(server_result.is_ok_and(|handshake| handshake.username() == "Ops"));
+        assert!(client_result.is_ok());
+
+        let (server_result, client_result) = perform("Ops", &fixture, None);
+        assert!(matches!(server_result, Err(HandshakeError::KeyRequired(_))));
+        assert!(matches!(
+            client_result,
+            Err(HandshakeError::AuthenticationFailed(
+                EndReason::Unauthorized,
+                _
+            ))
+        ));
+
+        let (server_result, _) = perform("Ops", &fixture, Some(SigningKey::from_bytes(&[8; 32])));
+        assert!(matches!(
+            server_result,
+            Err(HandshakeError::KeyNotAuthorized(_))
+        ));
+    }
+
+    #[test]
+    fn handshake_ends_banned_users_before_authenticating() {
+        let mut fixture = HandshakeFixture::new();
+        let bans = [
+            Ban::new(
+                BanTarget::Username(String::from("Troll")),
+                None,
+                Some(String::from("Spam")),
+            ),
+            Ban::new(BanTarget::Address(IpAddr::from([127, 0, 0, 1])), None, None),
+        ];
+        let username_policy = fixture.username_policy.clone();
+        for ban in bans {
+            fixture
+                .moderation
+                .ban(&username_policy, ban)
+                .unwrap_or_else(|err| panic!("Failed to ban: {}", err));
+        }
+
+        // The client stops at the first End or Authenticated, so getting End proves it came first
+        let (server_result, client_result) = perform("TROLL", &fixture, None);
+        assert!(matches!(server_result, Err(HandshakeError::Banned(_))));
+        assert!(matches!(
+            client_result,
+            Err(HandshakeError::AuthenticationFailed(EndReason::Banned, Some(reason)))
+                if reason == "Spam"
+        ));
+
+        let (_, client_result) = perform("Innocent", &fixture, None);
+        assert!(matches!(
+            client_result,
+            Err(HandshakeError::AuthenticationFailed(
+                EndReason::Banned,
+                None
+            ))
+        ));
+    }
+
+    #[test]
+    fn handshake_admits_registered_usernames_that_are_in_use() {
+        let mut fixture = HandshakeFixture::new();
+        fixture
+            .authorized_keys
+            .add(AuthorizedKey::new(
+                String::from("ops"),
+                SigningKey::from_bytes(&[7; 32]).verifying_key().to_bytes(),
+                None,
+            ))
+            .unwrap_or_else(|err| panic!("Failed to add key: {}", err));
+        fixture.taken_usernames = vec![String::from("Ops"), String::from("Alice")];
+        let arguments = fixture.arguments();
+
+        assert!(admit(&arguments, "OPS", None).is_ok());
+        assert!(matches!(
+            admit(&arguments, "alice", None),
+            Err(HandshakeError::UsernameRejected(UsernameError::Taken(_)))
+        ));
+
+        assert_eq!(device_name("  Laptop\n"), Some(String::from("Laptop")));
+        assert_eq!(device_name("\t"), None);
+        assert_eq!(
+            device_name(&"x".repeat(100)).map(|device| device.len()),
+            Some(MAX_DEVICE_NAME_LENGTH)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handshake_admits_local_users_by_uid() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        use crate::common::{
+            message_stream::connection::Connection, unix_socket::UnixSocketListener,
+        };
+
+        let directory =
+            std::env::temp_dir().join(format!("rusty_chat_unix_socket_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory)
+            .unwrap_or_else(|err| panic!("Failed to create directory: {}", err));
+        let path = directory.join("chat.sock");
+
+        let listener = UnixSocketListener::bind(&path, 0o600)
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let mode = fs::metadata(&path)
+            .unwrap_or_else(|err| panic!("Failed to read socket metadata: {}", err))
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // SAFETY: getuid can't fail
+        let uid = unsafe { libc::getuid() };
+        let mut fixture = HandshakeFixture::new();
+        fixture
+            .peer_credentials
+            .allow(LocalUser::new(uid, String::from("deploy-bot")));
+
+        let connect = || {
+            let connection = Connection::connect(&format!("unix:{}", path.display()))
+                .unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+            let server_stream = listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            (server_stream, MessageStream::new(connection))
+        };
+
+        let (server_stream, client_stream) = connect();
+        let (server_result, client_result) =
+            run(server_stream, client_stream, "Deploy-Bot", &fixture, None);
+        assert!(server_result.is_ok_and(|handshake| handshake.username() == "Deploy-Bot"));
+        assert!(client_result.is_ok());
+
+        let (server_result, _) = perform("deploy-bot", &fixture, None);
+        assert!(matches!(
+            server_result,
+            Err(HandshakeError::PeerNotAuthorized(_))
+        ));
+
+        let mut fixture = HandshakeFixture::new();
+        fixture.peer_credentials.allow(LocalUser::new(
+            uid.wrapping_add(1),
+            String::from("deploy-bot"),
+        ));
+        let (server_stream, client_stream) = connect();
+        let (server_result, _) = run(server_stream, client_stream, "deploy-bot", &fixture, None);
+        assert!(matches!(
+            server_result,
+            Err(HandshakeError::PeerNotAuthorized(_))
+        ));
+
+        drop(listener);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&directory);
+    }
 }
-//TODO: Test
@@ -6,6 +6,7 @@ use std::fmt::{Debug, Display};
 use crate::common::protocol::{error::MessageParseError, serializable::Serializable};
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Message {
     Client(client::Message),
     Server(server::Message),
@@ -48,17 +49,576 @@ impl Serializable for Message {
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Message, MessageParseError> {
+        MessageRef::from_bytes(bytes).map(MessageRef::into_owned)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageRef<'a> {
+    Client(client::MessageRef<'a>),
+    Server(server::MessageRef<'a>),
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<MessageRef<'a>, MessageParseError> {
         if bytes.is_empty() {
             return Err(MessageParseError::MessageEmpty);
         }
 
         let message_kind = bytes[0];
         match message_kind {
-            0 => Ok(Message::Client(client::Message::from_bytes(&bytes[1..])?)),
-            1 => Ok(Message::Server(server::Message::from_bytes(&bytes[1..])?)),
+            0 => Ok(MessageRef::Client(client::MessageRef::from_bytes(
+                &bytes[1..],
+            )?)),
+            1 => Ok(MessageRef::Server(server::MessageRef::from_bytes(
+                &bytes[1..],
+            )?)),
             _ => Err(MessageParseError::UnknownKind(message_kind)),
         }
     }
+
+    pub fn into_owned(self) -> Message {
+        match self {
+            MessageRef::Client(message) => Message::Client(message.into_owned()),
+            MessageRef::Server(message) => Message::Server(message.into_owned()),
+        }
+    }
 }
 
-//TODO: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::protocol::packet::{
+        client as client_packet, server as server_packet, BanTarget, Compression, Delivery,
+        EndReason, FileInfo, PermissionSet, Rejection, Role,
+    };
+    use proptest::prelude::*;
+
+    fn any_end_reason() -> impl Strategy<Value = EndReason> {
+        prop_oneof![
+            any::<u8>().prop_map(EndReason::from_id),
+            any::<u8>().prop_map(EndReason::Unknown)
+        ]
+    }
+
+    fn any_compression() -> impl Strategy<Value = Compression> {
+        prop_oneof![Just(Compression::None), Just(Compression::Deflate)]
+    }
+
+    fn any_role() -> impl Strategy<Value = Role> {
+        (".*", any::<u64>())
+            .prop_map(|(name, bits)| Role::new(name, PermissionSet::from_bits(bits)))
+    }
+
+    fn any_client_assign_role() -> impl Strategy<Value = client_packet::AssignRole> {
+        (".*", ".*").prop_map(|(username, role)| client_packet::AssignRole::new(username, role))
+    }
+
+    fn any_client_revoke_role() -> impl Strategy<Value = client_packet::RevokeRole> {
+        (".*", ".*").prop_map(|(username, role)| client_packet::RevokeRole::new(username, role))
+    }
+
+    fn any_client_authenticate() -> impl Strategy<Value = client_packet::Authenticate> {
+        (
+            ".+",
+            proptest::option::of(any::<[u8; 32]>()),
+            any_compression(),
+            proptest::option::of(".*"),
+        )
+            .prop_map(|(username, public_key, compression, device)| {
+                client_packet::Authenticate::new(username, public_key, compression, device)
+            })
+    }
+
+    fn any_client_chat() -> impl Strategy<Value = client_packet::Chat> {
+        (proptest::option::of(any::<u64>()), ".+")
+            .prop_map(|(nonce, message)| client_packet::Chat::new(nonce, message))
+    }
+
+    fn any_client_end() -> impl Strategy<Value = client_packet::End> {
+        (any_end_reason(), proptest::option::of(".*"))
+            .prop_map(|(reason, text)| client_packet::End::new(reason, text))
+    }
+
+    fn any_server_authenticated() -> impl Strategy<Value = server_packet::Authenticated> {
+        (
+            proptest::collection::vec(any_role(), 0..4),
+            any_compression(),
+        )
+            .prop_map(|(roles, compression)| server_packet::Authenticated::new(roles, compression))
+    }
+
+    fn any_server_chat() -> impl Strategy<Value = server_packet::Chat> {
+        (".*", ".+").prop_map(|(username, message)| server_packet::Chat::new(username, message))
+    }
+
+    fn any_server_end() -> impl Strategy<Value = server_packet::End> {
+        (any_end_reason(), proptest::option::of(".*"))
+            .prop_map(|(reason, text)| server_packet::End::new(reason, text))
+    }
+
+    fn any_ban_target() -> impl Strategy<Value = BanTarget> {
+        prop_oneof![
+            ".*".prop_map(BanTarget::Username),
+            any::<std::net::IpAddr>().prop_map(BanTarget::Address),
+        ]
+    }
+
+    fn any_client_kick() -> impl Strategy<Value = client_packet::Kick> {
+        (".*", proptest::option::of(".*"))
+            .prop_map(|(username, reason)| client_packet::Kick::new(username, reason))
+    }
+
+    fn any_client_ban() -> impl Strategy<Value = client_packet::Ban> {
+        (
+            any_ban_target(),
+            proptest::option::of(any::<u64>()),
+            proptest::option::of(".*"),
+        )
+            .prop_map(|(target, expires_at, reason)| {
+                client_packet::Ban::new(target, expires_at, reason)
+            })
+    }
+
+    fn any_client_mute() -> impl Strategy<Value = client_packet::Mute> {
+        (".*", proptest::option::of(any::<u64>()))
+            .prop_map(|(username, expires_at)| client_packet::Mute::new(username, expires_at))
+    }
+
+    fn any_client_unban() -> impl Strategy<Value = client_packet::Unban> {
+        any_ban_target().prop_map(client_packet::Unban::new)
+    }
+
+    fn any_server_warning() -> impl Strategy<Value = server_packet::Warning> {
+        ".+".prop_map(server_packet::Warning::new)
+    }
+
+    fn any_server_user_joined() -> impl Strategy<Value = server_packet::UserJoined> {
+        ".+".prop_map(server_packet::UserJoined::new)
+    }
+
+    fn any_server_user_left() -> impl Strategy<Value = server_packet::UserLeft> {
+        ".+".prop_map(server_packet::UserLeft::new)
+    }
+
+    fn any_client_command() -> impl Strategy<Value = client_packet::Command> {
+        (".*", proptest::collection::vec(".*", 0..4))
+            .prop_map(|(name, arguments)| client_packet::Command::new(name, arguments))
+    }
+
+    fn any_server_command_result() -> impl Strategy<Value = server_packet::CommandResult> {
+        (any::<bool>(), ".*")
+            .prop_map(|(success, output)| server_packet::CommandResult::new(success, output))
+    }
+
+    fn any_client_publish_key() -> impl Strategy<Value = client_packet::PublishKey> {
+        any::<[u8; 32]>().prop_map(client_packet::PublishKey::new)
+    }
+
+    fn any_client_request_key() -> impl Strategy<Value = client_packet::RequestKey> {
+        ".+".prop_map(client_packet::RequestKey::new)
+    }
+
+    fn any_client_encrypted_message() -> impl Strategy<Value = client_packet::EncryptedMessage> {
+        (
+            ".*",
+            proptest::option::of(any::<u64>()),
+            proptest::collection::vec(any::<u8>(), 1..128),
+        )
+            .prop_map(|(recipient, receipt_id, payload)| {
+                client_packet::EncryptedMessage::new(recipient, receipt_id, payload)
+            })
+    }
+
+    fn any_server_public_key() -> impl Strategy<Value = server_packet::PublicKey> {
+        (".*", proptest::option::of(any::<[u8; 32]>()))
+            .prop_map(|(username, public_key)| server_packet::PublicKey::new(username, public_key))
+    }
+
+    fn any_server_encrypted_message() -> impl Strategy<Value = server_packet::EncryptedMessage> {
+        (
+            ".*",
+            proptest::option::of(any::<u64>()),
+            proptest::option::of(any::<u64>()),
+            proptest::collection::vec(any::<u8>(), 1..128),
+        )
+            .prop_map(|(sender, queued_at, receipt_id, payload)| {
+                server_packet::EncryptedMessage::new(sender, queued_at, receipt_id, payload)
+            })
+    }
+
+    fn any_server_delivery_status() -> impl Strategy<Value = server_packet::DeliveryStatus> {
+        (
+            ".*",
+            prop_oneof![
+                Just(Delivery::Live),
+                Just(Delivery::Queued),
+                Just(Delivery::Dropped)
+            ],
+        )
+            .prop_map(|(recipient, delivery)| {
+                server_packet::DeliveryStatus::new(recipient, delivery)
+            })
+    }
+
+    fn any_server_ack() -> impl Strategy<Value = server_packet::Ack> {
+        (
+            any::<u64>(),
+            proptest::option::of(prop_oneof![
+                Just(Rejection::RateLimited),
+                Just(Rejection::Muted),
+                Just(Rejection::NotPermitted),
+                Just(Rejection::Filtered)
+            ]),
+        )
+            .prop_map(|(nonce, rejection)| server_packet::Ack::new(nonce, rejection))
+    }
+
+    fn any_client_read_receipt() -> impl Strategy<Value = client_packet::ReadReceipt> {
+        (".*", any::<u64>())
+            .prop_map(|(sender, receipt_id)| client_packet::ReadReceipt::new(sender, receipt_id))
+    }
+
+    fn any_server_read_receipt() -> impl Strategy<Value = server_packet::ReadReceipt> {
+        (".*", any::<u64>())
+            .prop_map(|(reader, receipt_id)| server_packet::ReadReceipt::new(reader, receipt_id))
+    }
+
+    fn any_server_private_message() -> impl Strategy<Value = server_packet::PrivateMessage> {
+        (".*", ".*")
+            .prop_map(|(sender, message)| server_packet::PrivateMessage::new(sender, message))
+    }
+
+    fn any_client_challenge_response() -> impl Strategy<Value = client_packet::ChallengeResponse> {
+        proptest::collection::vec(any::<u8>(), 64).prop_map(client_packet::ChallengeResponse::new)
+    }
+
+    fn any_server_challenge() -> impl Strategy<Value = server_packet::Challenge> {
+        any::<[u8; 32]>().prop_map(server_packet::Challenge::new)
+    }
+
+    fn any_file_info() -> impl Strategy<Value = FileInfo> {
+        (".*", any::<u64>(), ".*", any::<[u8; 32]>())
+            .prop_map(|(name, size, mime_type, hash)| FileInfo::new(name, size, mime_type, hash))
+    }
+
+    fn any_client_offer_file() -> impl Strategy<Value = client_packet::OfferFile> {
+        any_file_info().prop_map(client_packet::OfferFile::new)
+    }
+
+    fn any_client_file_chunk() -> impl Strategy<Value = client_packet::FileChunk> {
+        (
+            any::<u64>(),
+            any::<u64>(),
+            proptest::collection::vec(any::<u8>(), 0..128),
+        )
+            .prop_map(|(file_id, offset, data)| {
+                client_packet::FileChunk::new(file_id, offset, data)
+            })
+    }
+
+    fn any_client_request_chunk() -> impl Strategy<Value = client_packet::RequestChunk> {
+        (any::<u64>(), any::<u64>(), any::<u64>()).prop_map(|(file_id, offset, length)| {
+            client_packet::RequestChunk::new(file_id, offset, length)
+        })
+    }
+
+    fn any_server_upload_ready() -> impl Strategy<Value = server_packet::UploadReady> {
+        (any::<u64>(), any::<u64>())
+            .prop_map(|(file_id, offset)| server_packet::UploadReady::new(file_id, offset))
+    }
+
+    fn any_server_file_chunk() -> impl Strategy<Value = server_packet::FileChunk> {
+        (
+            any::<u64>(),
+            any::<u64>(),
+            proptest::collection::vec(any::<u8>(), 0..128),
+        )
+            .prop_map(|(file_id, offset, data)| {
+                server_packet::FileChunk::new(file_id, offset, data)
+            })
+    }
+
+    fn any_server_attachment() -> impl Strategy<Value = server_packet::Attachment> {
+        (".*", any::<u64>(), any_file_info()).prop_map(|(username, file_id, file)| {
+            server_packet::Attachment::new(username, file_id, file)
+        })
+    }
+
+    fn any_client_message() -> impl Strategy<Value = client::Message> {
+        prop_oneof![
+            any_client_authenticate().prop_map(client::Message::Authenticate),
+            any_client_chat().prop_map(client::Message::Chat),
+            any_client_end().prop_map(client::Message::End),
+            any_client_kick().prop_map(client::Message::Kick),
+            any_client_ban().prop_map(client::Message::Ban),
+            any_client_mute().prop_map(client::Message::Mute),
+            any_client_unban().prop_map(client::Message::Unban),
+            any_client_assign_role().prop_map(client::Message::AssignRole),
+            any_client_revoke_role().prop_map(client::Message::RevokeRole),
+            any_client_command().prop_map(client::Message::Command),
+            any_client_publish_key().prop_map(client::Message::PublishKey),
+            any_client_request_key().prop_map(client::Message::RequestKey),
+            any_client_encrypted_message().prop_map(client::Message::EncryptedMessage),
+            any_client_challenge_response().prop_map(client::Message::ChallengeResponse),
+            any_client_offer_file().prop_map(client::Message::OfferFile),
+            any_client_file_chunk().prop_map(client::Message::FileChunk),
+            any_client_request_chunk().prop_map(client::Message::RequestChunk),
+            any_client_read_receipt().prop_map(client::Message::ReadReceipt),
+        ]
+    }
+
+    fn any_server_message() -> impl Strategy<Value = server::Message> {
+        prop_oneof![
+            any_server_authenticated().prop_map(server::Message::Authenticated),
+            any_server_chat().prop_map(server::Message::Chat),
+            any_server_end().prop_map(server::Message::End),
+            any_server_warning().prop_map(server::Message::Warning),
+            any_server_command_result().prop_map(server::Message::CommandResult),
+            any_server_user_joined().prop_map(server::Message::UserJoined),
+            any_server_user_left().prop_map(server::Message::UserLeft),
+            any_server_public_key().prop_map(server::Message::PublicKey),
+            any_server_encrypted_message().prop_map(server::Message::EncryptedMessage),
+            any_server_challenge().prop_map(server::Message::Challenge),
+            any_server_upload_ready().prop_map(server::Message::UploadReady),
+            any_server_file_chunk().prop_map(server::Message::FileChunk),
+            any_server_attachment().prop_map(server::Message::Attachment),
+            any_server_delivery_status().prop_map(server::Message::DeliveryStatus),
+            any_server_ack().prop_map(server::Message::Ack),
+            any_server_read_receipt().prop_map(server::Message::ReadReceipt),
+            any_server_private_message().prop_map(server::Message::PrivateMessage),
+        ]
+    }
+
+    fn any_message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            any_client_message().prop_map(Message::Client),
+            any_server_message().prop_map(Message::Server),
+        ]
+    }
+
+    fn assert_round_trip<T: Serializable + Debug + PartialEq>(value: T) {
+        let parsed_value = match T::from_bytes(&value.as_bytes()) {
+            Ok(value) => value,
+            Err(err) => panic!("Failed to parse {:?}: {}", value, err),
+        };
+
+        assert_eq!(value, parsed_value);
+    }
+
+    proptest! {
+        #[test]
+        fn client_authenticate_round_trips(packet in any_client_authenticate()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_chat_round_trips(packet in any_client_chat()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_end_round_trips(packet in any_client_end()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_kick_round_trips(packet in any_client_kick()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_ban_round_trips(packet in any_client_ban()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_mute_round_trips(packet in any_client_mute()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_unban_round_trips(packet in any_client_unban()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_assign_role_round_trips(packet in any_client_assign_role()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_revoke_role_round_trips(packet in any_client_revoke_role()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_command_round_trips(packet in any_client_command()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_command_result_round_trips(packet in any_server_command_result()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_authenticated_round_trips(packet in any_server_authenticated()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_chat_round_trips(packet in any_server_chat()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_end_round_trips(packet in any_server_end()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_warning_round_trips(packet in any_server_warning()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_user_joined_round_trips(packet in any_server_user_joined()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_user_left_round_trips(packet in any_server_user_left()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_publish_key_round_trips(packet in any_client_publish_key()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_request_key_round_trips(packet in any_client_request_key()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_encrypted_message_round_trips(packet in any_client_encrypted_message()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_public_key_round_trips(packet in any_server_public_key()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_encrypted_message_round_trips(packet in any_server_encrypted_message()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_delivery_status_round_trips(packet in any_server_delivery_status()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_ack_round_trips(packet in any_server_ack()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_read_receipt_round_trips(packet in any_client_read_receipt()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_read_receipt_round_trips(packet in any_server_read_receipt()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_private_message_round_trips(packet in any_server_private_message()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_challenge_response_round_trips(packet in any_client_challenge_response()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_challenge_round_trips(packet in any_server_challenge()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_offer_file_round_trips(packet in any_client_offer_file()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_file_chunk_round_trips(packet in any_client_file_chunk()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_request_chunk_round_trips(packet in any_client_request_chunk()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_upload_ready_round_trips(packet in any_server_upload_ready()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_file_chunk_round_trips(packet in any_server_file_chunk()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn server_attachment_round_trips(packet in any_server_attachment()) {
+            assert_round_trip(packet);
+        }
+
+        #[test]
+        fn client_message_round_trips(message in any_client_message()) {
+            assert_round_trip(message);
+        }
+
+        #[test]
+        fn server_message_round_trips(message in any_server_message()) {
+            assert_round_trip(message);
+        }
+
+        #[test]
+        fn message_round_trips(message in any_message()) {
+            assert_round_trip(message);
+        }
+
+        #[test]
+        fn message_ref_agrees_with_message(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let owned = Message::from_bytes(&bytes);
+            let borrowed = MessageRef::from_bytes(&bytes).map(MessageRef::into_owned);
+
+            prop_assert_eq!(owned, borrowed);
+        }
+
+        #[test]
+        fn message_from_bytes_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = Message::from_bytes(&bytes);
+        }
+
+        #[test]
+        fn server_chat_from_bytes_never_panics(
+            username_length in any::<usize>(),
+            rest in proptest::collection::vec(any::<u8>(), 0..64)
+        ) {
+            let mut bytes = username_length.to_le_bytes().to_vec();
+            bytes.extend(rest);
+
+            let _ = server_packet::Chat::from_bytes(&bytes);
+        }
+    }
+}
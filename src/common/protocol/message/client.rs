@@ -2,15 +2,38 @@ use std::fmt::{Debug, Display};
 
 use crate::common::protocol::{
     error::MessageParseError,
-    packet::client::{Authenticate, Chat, End},
+    packet::{
+        client::{
+            AssignRole, Authenticate, AuthenticateRef, Ban, ChallengeResponse, Chat, ChatRef,
+            Command, EncryptedMessage, End, EndRef, FileChunk, Kick, Mute, OfferFile, PublishKey,
+            ReadReceipt, RequestChunk, RequestKey, RevokeRole, Unban,
+        },
+        PacketRef,
+    },
     serializable::Serializable,
 };
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Message {
     Authenticate(Authenticate),
     Chat(Chat),
     End(End),
+    Kick(Kick),
+    Ban(Ban),
+    Mute(Mute),
+    Unban(Unban),
+    AssignRole(AssignRole),
+    RevokeRole(RevokeRole),
+    Command(Command),
+    PublishKey(PublishKey),
+    RequestKey(RequestKey),
+    EncryptedMessage(EncryptedMessage),
+    ChallengeResponse(ChallengeResponse),
+    OfferFile(OfferFile),
+    FileChunk(FileChunk),
+    RequestChunk(RequestChunk),
+    ReadReceipt(ReadReceipt),
 }
 
 impl Message {
@@ -19,6 +42,21 @@ impl Message {
             Message::Authenticate(_) => 0,
             Message::Chat(_) => 1,
             Message::End(_) => 2,
+            Message::Kick(_) => 3,
+            Message::Ban(_) => 4,
+            Message::Mute(_) => 5,
+            Message::Unban(_) => 6,
+            Message::AssignRole(_) => 7,
+            Message::RevokeRole(_) => 8,
+            Message::Command(_) => 9,
+            Message::PublishKey(_) => 10,
+            Message::RequestKey(_) => 11,
+            Message::EncryptedMessage(_) => 12,
+            Message::ChallengeResponse(_) => 13,
+            Message::OfferFile(_) => 14,
+            Message::FileChunk(_) => 15,
+            Message::RequestChunk(_) => 16,
+            Message::ReadReceipt(_) => 17,
         }
     }
 }
@@ -29,6 +67,25 @@ impl Display for Message {
             Message::Authenticate(username) => write!(f, "Authenticate ({})", username),
             Message::Chat(message) => write!(f, "Chat({})", message),
             Message::End(reason) => write!(f, "End({})", reason),
+            Message::Kick(kick) => write!(f, "Kick({})", kick),
+            Message::Ban(ban) => write!(f, "Ban({})", ban),
+            Message::Mute(mute) => write!(f, "Mute({})", mute),
+            Message::Unban(unban) => write!(f, "Unban({})", unban),
+            Message::AssignRole(assign_role) => write!(f, "AssignRole({})", assign_role),
+            Message::RevokeRole(revoke_role) => write!(f, "RevokeRole({})", revoke_role),
+            Message::Command(command) => write!(f, "Command({})", command),
+            Message::PublishKey(publish_key) => write!(f, "PublishKey({})", publish_key),
+            Message::RequestKey(request_key) => write!(f, "RequestKey({})", request_key),
+            Message::EncryptedMessage(encrypted_message) => {
+                write!(f, "EncryptedMessage({})", encrypted_message)
+            }
+            Message::ChallengeResponse(challenge_response) => {
+                write!(f, "ChallengeResponse({})", challenge_response)
+            }
+            Message::OfferFile(offer_file) => write!(f, "OfferFile({})", offer_file),
+            Message::FileChunk(file_chunk) => write!(f, "FileChunk({})", file_chunk),
+            Message::RequestChunk(request_chunk) => write!(f, "RequestChunk({})", request_chunk),
+            Message::ReadReceipt(read_receipt) => write!(f, "ReadReceipt({})", read_receipt),
         }
     }
 }
@@ -40,31 +97,27 @@ impl Serializable for Message {
             Message::Authenticate(username) => username.as_bytes(),
             Message::Chat(message) => message.as_bytes(),
             Message::End(reason) => reason.as_bytes(),
+            Message::Kick(kick) => kick.as_bytes(),
+            Message::Ban(ban) => ban.as_bytes(),
+            Message::Mute(mute) => mute.as_bytes(),
+            Message::Unban(unban) => unban.as_bytes(),
+            Message::AssignRole(assign_role) => assign_role.as_bytes(),
+            Message::RevokeRole(revoke_role) => revoke_role.as_bytes(),
+            Message::Command(command) => command.as_bytes(),
+            Message::PublishKey(publish_key) => publish_key.as_bytes(),
+            Message::RequestKey(request_key) => request_key.as_bytes(),
+            Message::EncryptedMessage(encrypted_message) => encrypted_message.as_bytes(),
+            Message::ChallengeResponse(challenge_response) => challenge_response.as_bytes(),
+            Message::OfferFile(offer_file) => offer_file.as_bytes(),
+            Message::FileChunk(file_chunk) => file_chunk.as_bytes(),
+            Message::RequestChunk(request_chunk) => request_chunk.as_bytes(),
+            Message::ReadReceipt(read_receipt) => read_receipt.as_bytes(),
         });
         bytes
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Message, MessageParseError> {
-        if bytes.is_empty() {
-            return Err(MessageParseError::MessageEmpty);
-        }
-
-        let message_kind = bytes[0];
-        match message_kind {
-            0 => {
-                let authenticate = Authenticate::from_bytes(&bytes[1..])?;
-                Ok(Message::Authenticate(authenticate))
-            }
-            1 => {
-                let chat = Chat::from_bytes(&bytes[1..])?;
-                Ok(Message::Chat(chat))
-            }
-            2 => {
-                let end = End::from_bytes(&bytes[1..])?;
-                Ok(Message::End(end))
-            }
-            kind => Err(MessageParseError::UnknownKind(kind)),
-        }
+        MessageRef::from_bytes(bytes).map(MessageRef::into_owned)
     }
 }
 
@@ -86,15 +139,157 @@ impl From<&mut Message> for Vec<u8> {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageRef<'a> {
+    Authenticate(AuthenticateRef<'a>),
+    Chat(ChatRef<'a>),
+    End(EndRef<'a>),
+    Kick(Kick),
+    Ban(Ban),
+    Mute(Mute),
+    Unban(Unban),
+    AssignRole(AssignRole),
+    RevokeRole(RevokeRole),
+    Command(Command),
+    PublishKey(PublishKey),
+    RequestKey(RequestKey),
+    EncryptedMessage(EncryptedMessage),
+    ChallengeResponse(ChallengeResponse),
+    OfferFile(OfferFile),
+    FileChunk(FileChunk),
+    RequestChunk(RequestChunk),
+    ReadReceipt(ReadReceipt),
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<MessageRef<'a>, MessageParseError> {
+        if bytes.is_empty() {
+            return Err(MessageParseError::MessageEmpty);
+        }
+
+        let message_kind = bytes[0];
+        match message_kind {
+            0 => {
+                let authenticate = AuthenticateRef::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Authenticate(authenticate))
+            }
+            1 => {
+                let chat = ChatRef::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Chat(chat))
+            }
+            2 => {
+                let end = EndRef::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::End(end))
+            }
+            3 => {
+                let kick = Kick::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Kick(kick))
+            }
+            4 => {
+                let ban = Ban::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Ban(ban))
+            }
+            5 => {
+                let mute = Mute::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Mute(mute))
+            }
+            6 => {
+                let unban = Unban::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Unban(unban))
+            }
+            7 => {
+                let assign_role = AssignRole::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::AssignRole(assign_role))
+            }
+            8 => {
+                let revoke_role = RevokeRole::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::RevokeRole(revoke_role))
+            }
+            9 => {
+                let command = Command::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Command(command))
+            }
+            10 => {
+                let publish_key = PublishKey::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::PublishKey(publish_key))
+            }
+            11 => {
+                let request_key = RequestKey::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::RequestKey(request_key))
+            }
+            12 => {
+                let encrypted_message = EncryptedMessage::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::EncryptedMessage(encrypted_message))
+            }
+            13 => {
+                let challenge_response = ChallengeResponse::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::ChallengeResponse(challenge_response))
+            }
+            14 => {
+                let offer_file = OfferFile::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::OfferFile(offer_file))
+            }
+            15 => {
+                let file_chunk = FileChunk::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::FileChunk(file_chunk))
+            }
+            16 => {
+                let request_chunk = RequestChunk::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::RequestChunk(request_chunk))
+            }
+            17 => {
+                let read_receipt = ReadReceipt::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::ReadReceipt(read_receipt))
+            }
+            kind => Err(MessageParseError::UnknownKind(kind)),
+        }
+    }
+
+    pub fn into_owned(self) -> Message {
+        match self {
+            MessageRef::Authenticate(authenticate) => {
+                Message::Authenticate(authenticate.into_owned())
+            }
+            MessageRef::Chat(chat) => Message::Chat(chat.into_owned()),
+            MessageRef::End(end) => Message::End(end.into_owned()),
+            MessageRef::Kick(kick) => Message::Kick(kick),
+            MessageRef::Ban(ban) => Message::Ban(ban),
+            MessageRef::Mute(mute) => Message::Mute(mute),
+            MessageRef::Unban(unban) => Message::Unban(unban),
+            MessageRef::AssignRole(assign_role) => Message::AssignRole(assign_role),
+            MessageRef::RevokeRole(revoke_role) => Message::RevokeRole(revoke_role),
+            MessageRef::Command(command) => Message::Command(command),
+            MessageRef::PublishKey(publish_key) => Message::PublishKey(publish_key),
+            MessageRef::RequestKey(request_key) => Message::RequestKey(request_key),
+            MessageRef::EncryptedMessage(encrypted_message) => {
+                Message::EncryptedMessage(encrypted_message)
+            }
+            MessageRef::ChallengeResponse(challenge_response) => {
+                Message::ChallengeResponse(challenge_response)
+            }
+            MessageRef::OfferFile(offer_file) => Message::OfferFile(offer_file),
+            MessageRef::FileChunk(file_chunk) => Message::FileChunk(file_chunk),
+            MessageRef::RequestChunk(request_chunk) => Message::RequestChunk(request_chunk),
+            MessageRef::ReadReceipt(read_receipt) => Message::ReadReceipt(read_receipt),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::protocol::packet::{Compression, EndReason};
 
     #[test]
     fn message_authenticate_converts_correctly() {
         let username = String::from("Kitt3120");
 
-        let authenticate = Authenticate::new(username);
+        let authenticate = Authenticate::new(
+            username,
+            Some([7; 32]),
+            Compression::Deflate,
+            Some(String::from("laptop")),
+        );
         let authenticate_comparison_clone = authenticate.clone();
 
         let message = Message::Authenticate(authenticate);
@@ -117,7 +312,7 @@ mod tests {
     fn message_chat_converts_correctly() {
         let message_content = String::from("⚡");
 
-        let chat = Chat::new(message_content);
+        let chat = Chat::new(Some(42), message_content);
         let chat_comparison_clone = chat.clone();
 
         let message = Message::Chat(chat);
@@ -138,9 +333,9 @@ mod tests {
 
     #[test]
     fn message_end_converts_correctly() {
-        let reason = String::from("❌");
+        let text = String::from("❌");
 
-        let end = End::new(reason);
+        let end = End::new(EndReason::Quit, Some(text));
         let end_comparison_clone = end.clone();
 
         let message = Message::End(end);
@@ -158,4 +353,24 @@ mod tests {
             panic!("Parsed message is not of type Message::End");
         }
     }
+
+    #[test]
+    fn message_chat_ref_borrows_from_bytes() {
+        let chat = Chat::new(None, String::from("⚡"));
+        let bytes = Message::Chat(chat.clone()).as_bytes();
+
+        let parsed_message = match MessageRef::from_bytes(&bytes) {
+            Ok(message) => message,
+            Err(err) => panic!("Failed to parse message: {}", err),
+        };
+
+        if let MessageRef::Chat(chat_ref) = &parsed_message {
+            assert_eq!(chat_ref.message, chat.message);
+            assert!(bytes.as_ptr_range().contains(&chat_ref.message.as_ptr()));
+        } else {
+            panic!("Parsed message is not of type MessageRef::Chat");
+        }
+
+        assert_eq!(parsed_message.into_owned(), Message::Chat(chat));
+    }
 }
@@ -1,16 +1,38 @@
 use crate::common::protocol::{
     error::MessageParseError,
-    packet::server::{Authenticated, Chat, End},
+    packet::{
+        server::{
+            Ack, Attachment, Authenticated, Challenge, Chat, ChatRef, CommandResult,
+            DeliveryStatus, EncryptedMessage, End, EndRef, FileChunk, PrivateMessage, PublicKey,
+            ReadReceipt, UploadReady, UserJoined, UserLeft, Warning,
+        },
+        PacketRef,
+    },
     serializable::Serializable,
 };
 
 use std::fmt::{Debug, Display};
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Message {
     Authenticated(Authenticated),
     Chat(Chat),
     End(End),
+    Warning(Warning),
+    CommandResult(CommandResult),
+    UserJoined(UserJoined),
+    UserLeft(UserLeft),
+    PublicKey(PublicKey),
+    EncryptedMessage(EncryptedMessage),
+    Challenge(Challenge),
+    UploadReady(UploadReady),
+    FileChunk(FileChunk),
+    Attachment(Attachment),
+    DeliveryStatus(DeliveryStatus),
+    Ack(Ack),
+    ReadReceipt(ReadReceipt),
+    PrivateMessage(PrivateMessage),
 }
 
 impl Message {
@@ -19,6 +41,20 @@ impl Message {
             Message::Authenticated(_) => 0,
             Message::Chat(_) => 1,
             Message::End(_) => 2,
+            Message::Warning(_) => 3,
+            Message::CommandResult(_) => 4,
+            Message::UserJoined(_) => 5,
+            Message::UserLeft(_) => 6,
+            Message::PublicKey(_) => 7,
+            Message::EncryptedMessage(_) => 8,
+            Message::Challenge(_) => 9,
+            Message::UploadReady(_) => 10,
+            Message::FileChunk(_) => 11,
+            Message::Attachment(_) => 12,
+            Message::DeliveryStatus(_) => 13,
+            Message::Ack(_) => 14,
+            Message::ReadReceipt(_) => 15,
+            Message::PrivateMessage(_) => 16,
         }
     }
 }
@@ -29,6 +65,28 @@ impl Display for Message {
             Message::Authenticated(authenticated) => write!(f, "Authenticated({})", authenticated),
             Message::Chat(chat) => write!(f, "Chat({})", chat),
             Message::End(end) => write!(f, "End({})", end),
+            Message::Warning(warning) => write!(f, "Warning({})", warning),
+            Message::CommandResult(command_result) => {
+                write!(f, "CommandResult({})", command_result)
+            }
+            Message::UserJoined(user_joined) => write!(f, "UserJoined({})", user_joined),
+            Message::UserLeft(user_left) => write!(f, "UserLeft({})", user_left),
+            Message::PublicKey(public_key) => write!(f, "PublicKey({})", public_key),
+            Message::EncryptedMessage(encrypted_message) => {
+                write!(f, "EncryptedMessage({})", encrypted_message)
+            }
+            Message::Challenge(challenge) => write!(f, "Challenge({})", challenge),
+            Message::UploadReady(upload_ready) => write!(f, "UploadReady({})", upload_ready),
+            Message::FileChunk(file_chunk) => write!(f, "FileChunk({})", file_chunk),
+            Message::Attachment(attachment) => write!(f, "Attachment({})", attachment),
+            Message::DeliveryStatus(delivery_status) => {
+                write!(f, "DeliveryStatus({})", delivery_status)
+            }
+            Message::Ack(ack) => write!(f, "Ack({})", ack),
+            Message::ReadReceipt(read_receipt) => write!(f, "ReadReceipt({})", read_receipt),
+            Message::PrivateMessage(private_message) => {
+                write!(f, "PrivateMessage({})", private_message)
+            }
         }
     }
 }
@@ -40,31 +98,26 @@ impl Serializable for Message {
             Message::Authenticated(authenticated) => authenticated.as_bytes(),
             Message::Chat(chat) => chat.as_bytes(),
             Message::End(end) => end.as_bytes(),
+            Message::Warning(warning) => warning.as_bytes(),
+            Message::CommandResult(command_result) => command_result.as_bytes(),
+            Message::UserJoined(user_joined) => user_joined.as_bytes(),
+            Message::UserLeft(user_left) => user_left.as_bytes(),
+            Message::PublicKey(public_key) => public_key.as_bytes(),
+            Message::EncryptedMessage(encrypted_message) => encrypted_message.as_bytes(),
+            Message::Challenge(challenge) => challenge.as_bytes(),
+            Message::UploadReady(upload_ready) => upload_ready.as_bytes(),
+            Message::FileChunk(file_chunk) => file_chunk.as_bytes(),
+            Message::Attachment(attachment) => attachment.as_bytes(),
+            Message::DeliveryStatus(delivery_status) => delivery_status.as_bytes(),
+            Message::Ack(ack) => ack.as_bytes(),
+            Message::ReadReceipt(read_receipt) => read_receipt.as_bytes(),
+            Message::PrivateMessage(private_message) => private_message.as_bytes(),
         });
         bytes
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Message, MessageParseError> {
-        if bytes.is_empty() {
-            return Err(MessageParseError::MessageEmpty);
-        }
-
-        let message_kind = bytes[0];
-        match message_kind {
-            0 => {
-                let authenticated = Authenticated::from_bytes(&bytes[1..])?;
-                Ok(Message::Authenticated(authenticated))
-            }
-            1 => {
-                let chat = Chat::from_bytes(&bytes[1..])?;
-                Ok(Message::Chat(chat))
-            }
-            2 => {
-                let end = End::from_bytes(&bytes[1..])?;
-                Ok(Message::End(end))
-            }
-            kind => Err(MessageParseError::UnknownKind(kind)),
-        }
+        MessageRef::from_bytes(bytes).map(MessageRef::into_owned)
     }
 }
 
@@ -86,13 +139,146 @@ impl From<&mut Message> for Vec<u8> {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageRef<'a> {
+    Authenticated(Authenticated),
+    Chat(ChatRef<'a>),
+    End(EndRef<'a>),
+    Warning(Warning),
+    CommandResult(CommandResult),
+    UserJoined(UserJoined),
+    UserLeft(UserLeft),
+    PublicKey(PublicKey),
+    EncryptedMessage(EncryptedMessage),
+    Challenge(Challenge),
+    UploadReady(UploadReady),
+    FileChunk(FileChunk),
+    Attachment(Attachment),
+    DeliveryStatus(DeliveryStatus),
+    Ack(Ack),
+    ReadReceipt(ReadReceipt),
+    PrivateMessage(PrivateMessage),
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<MessageRef<'a>, MessageParseError> {
+        if bytes.is_empty() {
+            return Err(MessageParseError::MessageEmpty);
+        }
+
+        let message_kind = bytes[0];
+        match message_kind {
+            0 => {
+                let authenticated = Authenticated::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Authenticated(authenticated))
+            }
+            1 => {
+                let chat = ChatRef::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Chat(chat))
+            }
+            2 => {
+                let end = EndRef::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::End(end))
+            }
+            3 => {
+                let warning = Warning::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Warning(warning))
+            }
+            4 => {
+                let command_result = CommandResult::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::CommandResult(command_result))
+            }
+            5 => {
+                let user_joined = UserJoined::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::UserJoined(user_joined))
+            }
+            6 => {
+                let user_left = UserLeft::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::UserLeft(user_left))
+            }
+            7 => {
+                let public_key = PublicKey::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::PublicKey(public_key))
+            }
+            8 => {
+                let encrypted_message = EncryptedMessage::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::EncryptedMessage(encrypted_message))
+            }
+            9 => {
+                let challenge = Challenge::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Challenge(challenge))
+            }
+            10 => {
+                let upload_ready = UploadReady::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::UploadReady(upload_ready))
+            }
+            11 => {
+                let file_chunk = FileChunk::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::FileChunk(file_chunk))
+            }
+            12 => {
+                let attachment = Attachment::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Attachment(attachment))
+            }
+            13 => {
+                let delivery_status = DeliveryStatus::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::DeliveryStatus(delivery_status))
+            }
+            14 => {
+                let ack = Ack::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::Ack(ack))
+            }
+            15 => {
+                let read_receipt = ReadReceipt::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::ReadReceipt(read_receipt))
+            }
+            16 => {
+                let private_message = PrivateMessage::from_bytes(&bytes[1..])?;
+                Ok(MessageRef::PrivateMessage(private_message))
+            }
+            kind => Err(MessageParseError::UnknownKind(kind)),
+        }
+    }
+
+    pub fn into_owned(self) -> Message {
+        match self {
+            MessageRef::Authenticated(authenticated) => Message::Authenticated(authenticated),
+            MessageRef::Chat(chat) => Message::Chat(chat.into_owned()),
+            MessageRef::End(end) => Message::End(end.into_owned()),
+            MessageRef::Warning(warning) => Message::Warning(warning),
+            MessageRef::CommandResult(command_result) => Message::CommandResult(command_result),
+            MessageRef::UserJoined(user_joined) => Message::UserJoined(user_joined),
+            MessageRef::UserLeft(user_left) => Message::UserLeft(user_left),
+            MessageRef::PublicKey(public_key) => Message::PublicKey(public_key),
+            MessageRef::EncryptedMessage(encrypted_message) => {
+                Message::EncryptedMessage(encrypted_message)
+            }
+            MessageRef::Challenge(challenge) => Message::Challenge(challenge),
+            MessageRef::UploadReady(upload_ready) => Message::UploadReady(upload_ready),
+            MessageRef::FileChunk(file_chunk) => Message::FileChunk(file_chunk),
+            MessageRef::Attachment(attachment) => Message::Attachment(attachment),
+            MessageRef::DeliveryStatus(delivery_status) => Message::DeliveryStatus(delivery_status),
+            MessageRef::Ack(ack) => Message::Ack(ack),
+            MessageRef::ReadReceipt(read_receipt) => Message::ReadReceipt(read_receipt),
+            MessageRef::PrivateMessage(private_message) => Message::PrivateMessage(private_message),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::protocol::packet::Compression;
+    use crate::common::protocol::packet::{EndReason, Permission, PermissionSet, Role};
 
     #[test]
     fn message_authenticated_converts_correctly() {
-        let authenticated = Authenticated::new();
+        let role = Role::new(
+            String::from("moderator"),
+            PermissionSet::new(&[Permission::Kick, Permission::Mute]),
+        );
+
+        let authenticated = Authenticated::new(vec![role], Compression::Deflate);
         let authenticated_comparison_clone = authenticated.clone();
 
         let message = Message::Authenticated(authenticated);
@@ -137,9 +323,9 @@ mod tests {
 
     #[test]
     fn message_end_converts_correctly() {
-        let message = String::from("❌");
+        let text = String::from("❌");
 
-        let end = End::new(message);
+        let end = End::new(EndReason::ServerShutdown, Some(text));
         let end_comparison_clone = end.clone();
 
         let message = Message::End(end);
@@ -157,4 +343,25 @@ mod tests {
             panic!("Parsed message is not of type Message::End");
         }
     }
+
+    #[test]
+    fn message_chat_ref_borrows_from_bytes() {
+        let chat = Chat::new(String::from("Kitt3120"), String::from("⚡"));
+        let bytes = Message::Chat(chat.clone()).as_bytes();
+
+        let parsed_message = match MessageRef::from_bytes(&bytes) {
+            Ok(message) => message,
+            Err(err) => panic!("Failed to parse message: {}", err),
+        };
+
+        if let MessageRef::Chat(chat_ref) = &parsed_message {
+            assert_eq!(chat_ref.username, chat.username);
+            assert_eq!(chat_ref.message, chat.message);
+            assert!(bytes.as_ptr_range().contains(&chat_ref.message.as_ptr()));
+        } else {
+            panic!("Parsed message is not of type MessageRef::Chat");
+        }
+
+        assert_eq!(parsed_message.into_owned(), Message::Chat(chat));
+    }
 }
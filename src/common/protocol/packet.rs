@@ -1,8 +1,33 @@
+pub mod ban_target;
 pub mod client;
+pub mod compression;
+pub mod delivery;
+pub mod end_reason;
+pub mod file_info;
+pub mod rejection;
+pub mod role;
 pub mod server;
 
-use crate::common::protocol::{message::Message, serializable::Serializable};
+pub use ban_target::BanTarget;
+pub use compression::Compression;
+pub use delivery::Delivery;
+pub use end_reason::EndReason;
+pub use file_info::{FileInfo, MAX_CHUNK_SIZE};
+pub use rejection::Rejection;
+pub use role::{Permission, PermissionSet, Role};
+
+use crate::common::protocol::{
+    error::MessageParseError, message::Message, serializable::Serializable,
+};
 
 pub trait Packet: Serializable {
     fn to_message(self) -> Message;
 }
+
+pub trait PacketRef<'a>: Sized {
+    type Owned: Packet;
+
+    fn from_bytes(bytes: &'a [u8]) -> Result<Self, MessageParseError>;
+
+    fn into_owned(self) -> Self::Owned;
+}
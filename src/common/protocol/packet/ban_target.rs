@@ -0,0 +1,51 @@
+use std::{fmt::Display, net::IpAddr};
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    wire::{self, WireReader},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BanTarget {
+    Username(String),
+    Address(IpAddr),
+}
+
+impl BanTarget {
+    fn id(&self) -> u8 {
+        match self {
+            BanTarget::Username(_) => 0,
+            BanTarget::Address(_) => 1,
+        }
+    }
+
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.id());
+
+        match self {
+            BanTarget::Username(username) => wire::write_str(bytes, username),
+            BanTarget::Address(address) => wire::write_str(bytes, &address.to_string()),
+        }
+    }
+
+    pub fn read_from(reader: &mut WireReader) -> Result<BanTarget, MessageParseError> {
+        match reader.read_u8()? {
+            0 => Ok(BanTarget::Username(reader.read_str("Username")?.to_owned())),
+            1 => match reader.read_str("Address")?.parse() {
+                Ok(address) => Ok(BanTarget::Address(address)),
+                Err(_) => Err(MessageParseError::ByteParse(String::from("Address"))),
+            },
+            kind => Err(MessageParseError::UnknownKind(kind)),
+        }
+    }
+}
+
+impl Display for BanTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BanTarget::Username(username) => write!(f, "{}", username),
+            BanTarget::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
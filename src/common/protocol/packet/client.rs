@@ -1,7 +1,37 @@
+pub mod assign_role;
 pub mod authenticate;
+pub mod ban;
+pub mod challenge_response;
 pub mod chat;
+pub mod command;
+pub mod encrypted_message;
 pub mod end;
+pub mod file_chunk;
+pub mod kick;
+pub mod mute;
+pub mod offer_file;
+pub mod publish_key;
+pub mod read_receipt;
+pub mod request_chunk;
+pub mod request_key;
+pub mod revoke_role;
+pub mod unban;
 
-pub use authenticate::Authenticate;
-pub use chat::Chat;
-pub use end::End;
+pub use assign_role::AssignRole;
+pub use authenticate::{Authenticate, AuthenticateRef};
+pub use ban::Ban;
+pub use challenge_response::ChallengeResponse;
+pub use chat::{Chat, ChatRef};
+pub use command::Command;
+pub use encrypted_message::EncryptedMessage;
+pub use end::{End, EndRef};
+pub use file_chunk::FileChunk;
+pub use kick::Kick;
+pub use mute::Mute;
+pub use offer_file::OfferFile;
+pub use publish_key::PublishKey;
+pub use read_receipt::ReadReceipt;
+pub use request_chunk::RequestChunk;
+pub use request_key::RequestKey;
+pub use revoke_role::RevokeRole;
+pub use unban::Unban;
@@ -0,0 +1,53 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssignRole {
+    pub username: String,
+    pub role: String,
+}
+
+impl AssignRole {
+    pub fn new(username: String, role: String) -> AssignRole {
+        AssignRole { username, role }
+    }
+}
+
+impl Display for AssignRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.username, self.role)
+    }
+}
+
+impl Serializable for AssignRole {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        wire::write_str(&mut bytes, &self.role);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<AssignRole, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let role = reader.read_str("Role")?.to_owned();
+
+        Ok(AssignRole::new(username, role))
+    }
+}
+
+impl Packet for AssignRole {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::AssignRole(self))
+    }
+}
@@ -3,52 +3,67 @@ use std::fmt::Display;
 use crate::common::protocol::{
     error::MessageParseError,
     message::{client, Message},
-    packet::Packet,
+    packet::{Compression, Packet, PacketRef},
     serializable::Serializable,
+    wire::{self, WireReader},
 };
 
+// With a public key the server answers with a server::Challenge instead of admitting right away.
+// The device name tells a registered user's sessions apart when they list them.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Authenticate {
     pub username: String,
+    pub public_key: Option<[u8; 32]>,
+    pub compression: Compression,
+    pub device: Option<String>,
 }
 
 impl Authenticate {
-    pub fn new(username: String) -> Authenticate {
-        Authenticate { username }
+    pub fn new(
+        username: String,
+        public_key: Option<[u8; 32]>,
+        compression: Compression,
+        device: Option<String>,
+    ) -> Authenticate {
+        Authenticate {
+            username,
+            public_key,
+            compression,
+            device,
+        }
     }
 }
 
 impl Display for Authenticate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.username)
+        AuthenticateRef::new(
+            &self.username,
+            self.public_key,
+            self.compression,
+            self.device.as_deref(),
+        )
+        .fmt(f)
     }
 }
 
 impl Serializable for Authenticate {
     fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = vec![self.compression.id()];
 
+        wire::write_option(&mut bytes, &self.public_key, |bytes, public_key| {
+            bytes.extend_from_slice(public_key)
+        });
+        wire::write_option(&mut bytes, &self.device, |bytes, device| {
+            wire::write_str(bytes, device)
+        });
         bytes.extend_from_slice(self.username.as_bytes());
 
         bytes
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Authenticate, MessageParseError> {
-        if bytes.is_empty() {
-            return Err(MessageParseError::UnexcpetedEndOfMessage);
-        }
-
-        let username = match String::from_utf8(bytes.to_vec()) {
-            Ok(username) => username,
-            Err(err) => {
-                return Err(MessageParseError::StringParse(
-                    String::from("Username"),
-                    err,
-                ))
-            }
-        };
-
-        Ok(Authenticate::new(username))
+        AuthenticateRef::from_bytes(bytes).map(AuthenticateRef::into_owned)
     }
 }
 
@@ -57,3 +72,80 @@ impl Packet for Authenticate {
         Message::Client(client::Message::Authenticate(self))
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuthenticateRef<'a> {
+    pub username: &'a str,
+    pub public_key: Option<[u8; 32]>,
+    pub compression: Compression,
+    pub device: Option<&'a str>,
+}
+
+impl<'a> AuthenticateRef<'a> {
+    pub fn new(
+        username: &'a str,
+        public_key: Option<[u8; 32]>,
+        compression: Compression,
+        device: Option<&'a str>,
+    ) -> AuthenticateRef<'a> {
+        AuthenticateRef {
+            username,
+            public_key,
+            compression,
+            device,
+        }
+    }
+}
+
+impl Display for AuthenticateRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.username)?;
+
+        if let Some(public_key) = &self.public_key {
+            write!(f, ", ")?;
+            public_key
+                .iter()
+                .try_for_each(|byte| write!(f, "{:02x}", byte))?;
+        }
+
+        if let Some(device) = self.device {
+            write!(f, ", on {}", device)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> PacketRef<'a> for AuthenticateRef<'a> {
+    type Owned = Authenticate;
+
+    fn from_bytes(bytes: &'a [u8]) -> Result<AuthenticateRef<'a>, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let compression = Compression::from_id(reader.read_u8()?);
+        let public_key =
+            reader.read_option("Public Key", |reader| reader.read_array("Public Key"))?;
+        let device = reader.read_option("Device", |reader| reader.read_str("Device"))?;
+        let username = reader.read_remaining_str("Username")?;
+
+        if username.is_empty() {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+
+        Ok(AuthenticateRef::new(
+            username,
+            public_key,
+            compression,
+            device,
+        ))
+    }
+
+    fn into_owned(self) -> Authenticate {
+        Authenticate::new(
+            self.username.to_owned(),
+            self.public_key,
+            self.compression,
+            self.device.map(str::to_owned),
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::{BanTarget, Packet},
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ban {
+    pub target: BanTarget,
+    // Seconds since the Unix epoch, None bans permanently
+    pub expires_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+impl Ban {
+    pub fn new(target: BanTarget, expires_at: Option<u64>, reason: Option<String>) -> Ban {
+        Ban {
+            target,
+            expires_at,
+            reason,
+        }
+    }
+}
+
+impl Display for Ban {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target)?;
+
+        if let Some(expires_at) = self.expires_at {
+            write!(f, " until {}", expires_at)?;
+        }
+
+        if let Some(reason) = &self.reason {
+            write!(f, ", {}", reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serializable for Ban {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        self.target.write_to(&mut bytes);
+        wire::write_option(&mut bytes, &self.expires_at, |bytes, expires_at| {
+            wire::write_u64(bytes, *expires_at)
+        });
+        // Flagged explicitly, so an empty reason stays apart from no reason
+        wire::write_option(&mut bytes, &self.reason, |bytes, reason| {
+            bytes.extend_from_slice(reason.as_bytes())
+        });
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Ban, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let target = BanTarget::read_from(&mut reader)?;
+        let expires_at = reader.read_option("Expiry", |reader| reader.read_u64("Expiry"))?;
+        let reason = reader.read_option("Reason", |reader| {
+            reader
+                .read_remaining_str("Reason")
+                .map(|reason| reason.to_owned())
+        })?;
+
+        Ok(Ban::new(target, expires_at, reason))
+    }
+}
+
+impl Packet for Ban {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::Ban(self))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+pub const SIGNATURE_SIZE: usize = 64;
+
+// Ed25519 signature over the server::Challenge, see handshake::challenge_payload
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChallengeResponse {
+    pub signature: Vec<u8>,
+}
+
+impl ChallengeResponse {
+    pub fn new(signature: Vec<u8>) -> ChallengeResponse {
+        ChallengeResponse { signature }
+    }
+}
+
+impl Display for ChallengeResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.signature
+            .iter()
+            .try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl Serializable for ChallengeResponse {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.signature.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ChallengeResponse, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let signature = reader.read_bytes(SIGNATURE_SIZE)?;
+
+        if !reader.is_empty() {
+            return Err(MessageParseError::ByteParse(String::from("Signature")));
+        }
+
+        Ok(ChallengeResponse::new(signature.to_vec()))
+    }
+}
+
+impl Packet for ChallengeResponse {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::ChallengeResponse(self))
+    }
+}
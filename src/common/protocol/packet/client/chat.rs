@@ -1,19 +1,24 @@
 use crate::common::protocol::{
     error::MessageParseError,
     message::{client, Message},
-    packet::Packet,
+    packet::{Packet, PacketRef},
     serializable::Serializable,
+    wire::{self, WireReader},
 };
 use std::fmt::Display;
 
+// With a nonce the server answers with a server::Ack carrying it, so the sender learns
+// whether the message was accepted
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chat {
+    pub nonce: Option<u64>,
     pub message: String,
 }
 
 impl Chat {
-    pub fn new(message: String) -> Chat {
-        Chat { message }
+    pub fn new(nonce: Option<u64>, message: String) -> Chat {
+        Chat { nonce, message }
     }
 }
 
@@ -27,22 +32,16 @@ impl Serializable for Chat {
     fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
+        wire::write_option(&mut bytes, &self.nonce, |bytes, nonce| {
+            wire::write_u64(bytes, *nonce)
+        });
         bytes.extend_from_slice(self.message.as_bytes());
 
         bytes
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Chat, MessageParseError> {
-        if bytes.is_empty() {
-            return Err(MessageParseError::UnexcpetedEndOfMessage);
-        }
-
-        let message = match String::from_utf8(bytes.to_vec()) {
-            Ok(message) => message,
-            Err(err) => return Err(MessageParseError::StringParse(String::from("Message"), err)),
-        };
-
-        Ok(Chat::new(message))
+        ChatRef::from_bytes(bytes).map(ChatRef::into_owned)
     }
 }
 
@@ -51,3 +50,41 @@ impl Packet for Chat {
         Message::Client(client::Message::Chat(self))
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChatRef<'a> {
+    pub nonce: Option<u64>,
+    pub message: &'a str,
+}
+
+impl<'a> ChatRef<'a> {
+    pub fn new(nonce: Option<u64>, message: &'a str) -> ChatRef<'a> {
+        ChatRef { nonce, message }
+    }
+}
+
+impl Display for ChatRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl<'a> PacketRef<'a> for ChatRef<'a> {
+    type Owned = Chat;
+
+    fn from_bytes(bytes: &'a [u8]) -> Result<ChatRef<'a>, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let nonce = reader.read_option("Nonce", |reader| reader.read_u64("Nonce"))?;
+        if reader.is_empty() {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+        let message = reader.read_remaining_str("Message")?;
+
+        Ok(ChatRef::new(nonce, message))
+    }
+
+    fn into_owned(self) -> Chat {
+        Chat::new(self.nonce, self.message.to_owned())
+    }
+}
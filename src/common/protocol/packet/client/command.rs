@@ -0,0 +1,64 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Command {
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+impl Command {
+    pub fn new(name: String, arguments: Vec<String>) -> Command {
+        Command { name, arguments }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{}", self.name)?;
+
+        for argument in &self.arguments {
+            write!(f, " {:?}", argument)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serializable for Command {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.name);
+        for argument in &self.arguments {
+            wire::write_str(&mut bytes, argument);
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Command, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let name = reader.read_str("Name")?.to_owned();
+        let mut arguments = Vec::new();
+        while !reader.is_empty() {
+            arguments.push(reader.read_str("Argument")?.to_owned());
+        }
+
+        Ok(Command::new(name, arguments))
+    }
+}
+
+impl Packet for Command {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::Command(self))
+    }
+}
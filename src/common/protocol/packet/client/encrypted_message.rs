@@ -0,0 +1,68 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// The payload is sealed for the recipient, the server only sees who it's for.
+// A receipt id asks the recipient to send a ReadReceipt with it once the message was read.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncryptedMessage {
+    pub recipient: String,
+    pub receipt_id: Option<u64>,
+    pub payload: Vec<u8>,
+}
+
+impl EncryptedMessage {
+    pub fn new(recipient: String, receipt_id: Option<u64>, payload: Vec<u8>) -> EncryptedMessage {
+        EncryptedMessage {
+            recipient,
+            receipt_id,
+            payload,
+        }
+    }
+}
+
+impl Display for EncryptedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {} bytes", self.recipient, self.payload.len())
+    }
+}
+
+impl Serializable for EncryptedMessage {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.recipient);
+        wire::write_option(&mut bytes, &self.receipt_id, |bytes, receipt_id| {
+            wire::write_u64(bytes, *receipt_id)
+        });
+        bytes.extend_from_slice(&self.payload);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<EncryptedMessage, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let recipient = reader.read_str("Recipient")?.to_owned();
+        let receipt_id =
+            reader.read_option("Receipt id", |reader| reader.read_u64("Receipt id"))?;
+        let payload = match reader.read_remaining_bytes() {
+            [] => return Err(MessageParseError::UnexcpetedEndOfMessage),
+            payload => payload.to_vec(),
+        };
+
+        Ok(EncryptedMessage::new(recipient, receipt_id, payload))
+    }
+}
+
+impl Packet for EncryptedMessage {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::EncryptedMessage(self))
+    }
+}
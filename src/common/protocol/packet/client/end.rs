@@ -1,48 +1,48 @@
 use crate::common::protocol::{
     error::MessageParseError,
     message::{client, Message},
-    packet::Packet,
+    packet::{EndReason, Packet, PacketRef},
     serializable::Serializable,
+    wire::{self, WireReader},
 };
 use std::fmt::Display;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct End {
-    pub reason: String,
+    pub reason: EndReason,
+    pub text: Option<String>,
 }
 
 impl End {
-    pub fn new(reason: String) -> End {
-        End { reason }
+    pub fn new(reason: EndReason, text: Option<String>) -> End {
+        End { reason, text }
     }
 }
 
 impl Display for End {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.reason)
+        match &self.text {
+            Some(text) => write!(f, "{}: {}", self.reason, text),
+            None => write!(f, "{}", self.reason),
+        }
     }
 }
 
 impl Serializable for End {
     fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = vec![self.reason.id()];
 
-        bytes.extend_from_slice(self.reason.as_bytes());
+        // Flagged explicitly, so an empty text stays apart from no text
+        wire::write_option(&mut bytes, &self.text, |bytes, text| {
+            bytes.extend_from_slice(text.as_bytes())
+        });
 
         bytes
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<End, MessageParseError> {
-        if bytes.is_empty() {
-            return Err(MessageParseError::UnexcpetedEndOfMessage);
-        }
-
-        let reason = match String::from_utf8(bytes.to_vec()) {
-            Ok(reason) => reason,
-            Err(err) => return Err(MessageParseError::StringParse(String::from("Reason"), err)),
-        };
-
-        Ok(End::new(reason))
+        EndRef::from_bytes(bytes).map(EndRef::into_owned)
     }
 }
 
@@ -51,3 +51,41 @@ impl Packet for End {
         Message::Client(client::Message::End(self))
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EndRef<'a> {
+    pub reason: EndReason,
+    pub text: Option<&'a str>,
+}
+
+impl<'a> EndRef<'a> {
+    pub fn new(reason: EndReason, text: Option<&'a str>) -> EndRef<'a> {
+        EndRef { reason, text }
+    }
+}
+
+impl Display for EndRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.text {
+            Some(text) => write!(f, "{}: {}", self.reason, text),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl<'a> PacketRef<'a> for EndRef<'a> {
+    type Owned = End;
+
+    fn from_bytes(bytes: &'a [u8]) -> Result<EndRef<'a>, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let reason = EndReason::from_id(reader.read_u8()?);
+        let text = reader.read_option("Text", |reader| reader.read_remaining_str("Text"))?;
+
+        Ok(EndRef::new(reason, text))
+    }
+
+    fn into_owned(self) -> End {
+        End::new(self.reason, self.text.map(str::to_owned))
+    }
+}
@@ -0,0 +1,63 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Kick {
+    pub username: String,
+    pub reason: Option<String>,
+}
+
+impl Kick {
+    pub fn new(username: String, reason: Option<String>) -> Kick {
+        Kick { username, reason }
+    }
+}
+
+impl Display for Kick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{}, {}", self.username, reason),
+            None => write!(f, "{}", self.username),
+        }
+    }
+}
+
+impl Serializable for Kick {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        // Flagged explicitly, so an empty reason stays apart from no reason
+        wire::write_option(&mut bytes, &self.reason, |bytes, reason| {
+            bytes.extend_from_slice(reason.as_bytes())
+        });
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Kick, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let reason = reader.read_option("Reason", |reader| {
+            reader
+                .read_remaining_str("Reason")
+                .map(|reason| reason.to_owned())
+        })?;
+
+        Ok(Kick::new(username, reason))
+    }
+}
+
+impl Packet for Kick {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::Kick(self))
+    }
+}
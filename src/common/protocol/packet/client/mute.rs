@@ -0,0 +1,62 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mute {
+    pub username: String,
+    // Seconds since the Unix epoch, None mutes until the server restarts
+    pub expires_at: Option<u64>,
+}
+
+impl Mute {
+    pub fn new(username: String, expires_at: Option<u64>) -> Mute {
+        Mute {
+            username,
+            expires_at,
+        }
+    }
+}
+
+impl Display for Mute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.expires_at {
+            Some(expires_at) => write!(f, "{} until {}", self.username, expires_at),
+            None => write!(f, "{}", self.username),
+        }
+    }
+}
+
+impl Serializable for Mute {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        wire::write_option(&mut bytes, &self.expires_at, |bytes, expires_at| {
+            wire::write_u64(bytes, *expires_at)
+        });
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Mute, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let expires_at = reader.read_option("Expiry", |reader| reader.read_u64("Expiry"))?;
+
+        Ok(Mute::new(username, expires_at))
+    }
+}
+
+impl Packet for Mute {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::Mute(self))
+    }
+}
@@ -0,0 +1,51 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::{FileInfo, Packet},
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+// Starts or resumes an upload, the server answers with server::UploadReady
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OfferFile {
+    pub file: FileInfo,
+}
+
+impl OfferFile {
+    pub fn new(file: FileInfo) -> OfferFile {
+        OfferFile { file }
+    }
+}
+
+impl Display for OfferFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)
+    }
+}
+
+impl Serializable for OfferFile {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        self.file.write_to(&mut bytes);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<OfferFile, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let file = FileInfo::read_from(&mut reader)?;
+
+        Ok(OfferFile::new(file))
+    }
+}
+
+impl Packet for OfferFile {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::OfferFile(self))
+    }
+}
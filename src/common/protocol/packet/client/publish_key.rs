@@ -0,0 +1,49 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+// The X25519 identity key other clients encrypt direct messages to
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublishKey {
+    pub public_key: [u8; 32],
+}
+
+impl PublishKey {
+    pub fn new(public_key: [u8; 32]) -> PublishKey {
+        PublishKey { public_key }
+    }
+}
+
+impl Display for PublishKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.public_key
+            .iter()
+            .try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl Serializable for PublishKey {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.public_key.to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<PublishKey, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let public_key = reader.read_array("Public Key")?;
+
+        Ok(PublishKey::new(public_key))
+    }
+}
+
+impl Packet for PublishKey {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::PublishKey(self))
+    }
+}
@@ -0,0 +1,54 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Confirms that a client::EncryptedMessage with a receipt id was read, sent back to its sender
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReadReceipt {
+    pub sender: String,
+    pub receipt_id: u64,
+}
+
+impl ReadReceipt {
+    pub fn new(sender: String, receipt_id: u64) -> ReadReceipt {
+        ReadReceipt { sender, receipt_id }
+    }
+}
+
+impl Display for ReadReceipt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.sender, self.receipt_id)
+    }
+}
+
+impl Serializable for ReadReceipt {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.receipt_id);
+        bytes.extend_from_slice(self.sender.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ReadReceipt, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let receipt_id = reader.read_u64("Receipt id")?;
+        let sender = reader.read_remaining_str("Sender")?.to_owned();
+
+        Ok(ReadReceipt::new(sender, receipt_id))
+    }
+}
+
+impl Packet for ReadReceipt {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::ReadReceipt(self))
+    }
+}
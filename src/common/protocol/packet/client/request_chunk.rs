@@ -0,0 +1,65 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Downloads are pulled one chunk at a time, so the client decides how much bandwidth they take
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestChunk {
+    pub file_id: u64,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl RequestChunk {
+    pub fn new(file_id: u64, offset: u64, length: u64) -> RequestChunk {
+        RequestChunk {
+            file_id,
+            offset,
+            length,
+        }
+    }
+}
+
+impl Display for RequestChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:016x}, {}, {} bytes",
+            self.file_id, self.offset, self.length
+        )
+    }
+}
+
+impl Serializable for RequestChunk {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.file_id);
+        wire::write_u64(&mut bytes, self.offset);
+        wire::write_u64(&mut bytes, self.length);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<RequestChunk, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let file_id = reader.read_u64("File ID")?;
+        let offset = reader.read_u64("Offset")?;
+        let length = reader.read_u64("Length")?;
+
+        Ok(RequestChunk::new(file_id, offset, length))
+    }
+}
+
+impl Packet for RequestChunk {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::RequestChunk(self))
+    }
+}
@@ -0,0 +1,53 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RevokeRole {
+    pub username: String,
+    pub role: String,
+}
+
+impl RevokeRole {
+    pub fn new(username: String, role: String) -> RevokeRole {
+        RevokeRole { username, role }
+    }
+}
+
+impl Display for RevokeRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.username, self.role)
+    }
+}
+
+impl Serializable for RevokeRole {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        wire::write_str(&mut bytes, &self.role);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<RevokeRole, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let role = reader.read_str("Role")?.to_owned();
+
+        Ok(RevokeRole::new(username, role))
+    }
+}
+
+impl Packet for RevokeRole {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::RevokeRole(self))
+    }
+}
@@ -0,0 +1,50 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{client, Message},
+    packet::{BanTarget, Packet},
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unban {
+    pub target: BanTarget,
+}
+
+impl Unban {
+    pub fn new(target: BanTarget) -> Unban {
+        Unban { target }
+    }
+}
+
+impl Display for Unban {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target)
+    }
+}
+
+impl Serializable for Unban {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        self.target.write_to(&mut bytes);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Unban, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let target = BanTarget::read_from(&mut reader)?;
+
+        Ok(Unban::new(target))
+    }
+}
+
+impl Packet for Unban {
+    fn to_message(self) -> Message {
+        Message::Client(client::Message::Unban(self))
+    }
+}
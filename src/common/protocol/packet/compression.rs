@@ -0,0 +1,36 @@
+use std::fmt::Display;
+
+// Offered by the client in Authenticate and confirmed by the server in Authenticated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    pub fn id(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    // An algorithm added by a newer peer is simply not negotiated
+    pub fn from_id(id: u8) -> Compression {
+        match id {
+            1 => Compression::Deflate,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "None"),
+            Compression::Deflate => write!(f, "Deflate"),
+        }
+    }
+}
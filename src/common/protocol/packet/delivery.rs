@@ -0,0 +1,43 @@
+use std::fmt::Display;
+
+use crate::common::protocol::error::MessageParseError;
+
+// What became of a direct message, reported to its sender with server::DeliveryStatus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Delivery {
+    Live,
+    // Held until the recipient logs in again
+    Queued,
+    // The recipient is offline and either unregistered or has a full queue, or the sender is muted
+    Dropped,
+}
+
+impl Delivery {
+    pub fn id(&self) -> u8 {
+        match self {
+            Delivery::Live => 0,
+            Delivery::Queued => 1,
+            Delivery::Dropped => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Delivery, MessageParseError> {
+        match id {
+            0 => Ok(Delivery::Live),
+            1 => Ok(Delivery::Queued),
+            2 => Ok(Delivery::Dropped),
+            _ => Err(MessageParseError::ByteParse(String::from("Delivery"))),
+        }
+    }
+}
+
+impl Display for Delivery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Delivery::Live => write!(f, "Live"),
+            Delivery::Queued => write!(f, "Queued"),
+            Delivery::Dropped => write!(f, "Dropped"),
+        }
+    }
+}
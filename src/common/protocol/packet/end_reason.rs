@@ -0,0 +1,110 @@
+use std::fmt::Display;
+
+// Compared by wire id, so Unknown(3) equals the Banned it parses back to
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndReason {
+    Unspecified,
+    Quit,
+    UsernameTaken,
+    Banned,
+    ProtocolMismatch,
+    ServerShutdown,
+    IdleTimeout,
+    Kicked,
+    RateLimited,
+    UsernameInvalid,
+    UsernameReserved,
+    Unauthorized,
+    // Ended from another session of the same account
+    SessionRevoked,
+    // Codes introduced by newer peers are kept, so they can at least be displayed and passed on
+    Unknown(u8),
+}
+
+impl EndReason {
+    pub fn id(&self) -> u8 {
+        match self {
+            EndReason::Unspecified => 0,
+            EndReason::Quit => 1,
+            EndReason::UsernameTaken => 2,
+            EndReason::Banned => 3,
+            EndReason::ProtocolMismatch => 4,
+            EndReason::ServerShutdown => 5,
+            EndReason::IdleTimeout => 6,
+            EndReason::Kicked => 7,
+            EndReason::RateLimited => 8,
+            EndReason::UsernameInvalid => 9,
+            EndReason::UsernameReserved => 10,
+            EndReason::Unauthorized => 11,
+            EndReason::SessionRevoked => 12,
+            EndReason::Unknown(id) => *id,
+        }
+    }
+
+    pub fn from_id(id: u8) -> EndReason {
+        match id {
+            0 => EndReason::Unspecified,
+            1 => EndReason::Quit,
+            2 => EndReason::UsernameTaken,
+            3 => EndReason::Banned,
+            4 => EndReason::ProtocolMismatch,
+            5 => EndReason::ServerShutdown,
+            6 => EndReason::IdleTimeout,
+            7 => EndReason::Kicked,
+            8 => EndReason::RateLimited,
+            9 => EndReason::UsernameInvalid,
+            10 => EndReason::UsernameReserved,
+            11 => EndReason::Unauthorized,
+            12 => EndReason::SessionRevoked,
+            id => EndReason::Unknown(id),
+        }
+    }
+}
+
+impl PartialEq for EndReason {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for EndReason {}
+
+impl Display for EndReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndReason::Unspecified => write!(f, "Unspecified"),
+            EndReason::Quit => write!(f, "Quit"),
+            EndReason::UsernameTaken => write!(f, "Username taken"),
+            EndReason::Banned => write!(f, "Banned"),
+            EndReason::ProtocolMismatch => write!(f, "Protocol mismatch"),
+            EndReason::ServerShutdown => write!(f, "Server shutdown"),
+            EndReason::IdleTimeout => write!(f, "Idle timeout"),
+            EndReason::Kicked => write!(f, "Kicked"),
+            EndReason::RateLimited => write!(f, "Rate limited"),
+            EndReason::UsernameInvalid => write!(f, "Username invalid"),
+            EndReason::UsernameReserved => write!(f, "Username reserved"),
+            EndReason::Unauthorized => write!(f, "Unauthorized"),
+            EndReason::SessionRevoked => write!(f, "Session revoked"),
+            EndReason::Unknown(id) => write!(f, "Unknown ({})", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn end_reason_id_converts_correctly() {
+        for id in u8::MIN..=u8::MAX {
+            assert_eq!(EndReason::from_id(id).id(), id);
+            assert_eq!(
+                EndReason::from_id(EndReason::Unknown(id).id()),
+                EndReason::Unknown(id)
+            );
+        }
+        assert_eq!(EndReason::Unknown(3), EndReason::Banned);
+        assert_ne!(EndReason::Unknown(3), EndReason::Kicked);
+    }
+}
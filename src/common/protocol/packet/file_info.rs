@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    wire::{self, WireReader},
+};
+
+// Files move in chunks of at most this size, so chat messages on the same connection get through in between
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileInfo {
+    pub name: String,
+    pub size: u64,
+    pub mime_type: String,
+    // SHA-256 of the whole file
+    pub hash: [u8; 32],
+}
+
+impl FileInfo {
+    pub fn new(name: String, size: u64, mime_type: String, hash: [u8; 32]) -> FileInfo {
+        FileInfo {
+            name,
+            size,
+            mime_type,
+            hash,
+        }
+    }
+
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        wire::write_str(bytes, &self.name);
+        wire::write_u64(bytes, self.size);
+        wire::write_str(bytes, &self.mime_type);
+        bytes.extend_from_slice(&self.hash);
+    }
+
+    pub fn read_from(reader: &mut WireReader) -> Result<FileInfo, MessageParseError> {
+        let name = reader.read_str("Name")?.to_owned();
+        let size = reader.read_u64("Size")?;
+        let mime_type = reader.read_str("MIME Type")?.to_owned();
+        let hash = reader.read_array("Hash")?;
+
+        Ok(FileInfo::new(name, size, mime_type, hash))
+    }
+}
+
+impl Display for FileInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, {} bytes)", self.name, self.mime_type, self.size)
+    }
+}
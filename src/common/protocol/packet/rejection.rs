@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+use crate::common::protocol::error::MessageParseError;
+
+// Why the server refused a message, reported to its sender with server::Ack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rejection {
+    RateLimited,
+    Muted,
+    NotPermitted,
+    // A plugin or filter dropped the message
+    Filtered,
+}
+
+impl Rejection {
+    pub fn id(&self) -> u8 {
+        match self {
+            Rejection::RateLimited => 0,
+            Rejection::Muted => 1,
+            Rejection::NotPermitted => 2,
+            Rejection::Filtered => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Rejection, MessageParseError> {
+        match id {
+            0 => Ok(Rejection::RateLimited),
+            1 => Ok(Rejection::Muted),
+            2 => Ok(Rejection::NotPermitted),
+            3 => Ok(Rejection::Filtered),
+            _ => Err(MessageParseError::ByteParse(String::from("Rejection"))),
+        }
+    }
+
+    // Sending the same message again later can only help when it was turned away for its timing
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Rejection::RateLimited)
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::RateLimited => write!(f, "Rate limited"),
+            Rejection::Muted => write!(f, "Muted"),
+            Rejection::NotPermitted => write!(f, "Not permitted"),
+            Rejection::Filtered => write!(f, "Filtered"),
+        }
+    }
+}
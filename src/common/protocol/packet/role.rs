@@ -0,0 +1,119 @@
+use std::fmt::Display;
+
+use crate::common::protocol::{
+    error::MessageParseError,
+    wire::{self, WireReader},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Permission {
+    Kick,
+    Ban,
+    Mute,
+    SeeAddresses,
+    ManageRoles,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::Kick,
+        Permission::Ban,
+        Permission::Mute,
+        Permission::SeeAddresses,
+        Permission::ManageRoles,
+    ];
+
+    fn bit(&self) -> u64 {
+        // Bits 0 to 2 belonged to rooms, announcements and topics, which were never built
+        let index = match self {
+            Permission::Kick => 3,
+            Permission::Ban => 4,
+            Permission::Mute => 5,
+            Permission::SeeAddresses => 6,
+            Permission::ManageRoles => 7,
+        };
+
+        1 << index
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Kick => write!(f, "Kick"),
+            Permission::Ban => write!(f, "Ban"),
+            Permission::Mute => write!(f, "Mute"),
+            Permission::SeeAddresses => write!(f, "See addresses"),
+            Permission::ManageRoles => write!(f, "Manage roles"),
+        }
+    }
+}
+
+// Bits of permissions unknown to this version are kept, so they survive being passed on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PermissionSet(u64);
+
+impl PermissionSet {
+    pub fn new(permissions: &[Permission]) -> PermissionSet {
+        PermissionSet(
+            permissions
+                .iter()
+                .fold(0, |bits, permission| bits | permission.bit()),
+        )
+    }
+
+    pub fn from_bits(bits: u64) -> PermissionSet {
+        PermissionSet(bits)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0 & permission.bit() != 0
+    }
+
+    pub fn union(&self, other: PermissionSet) -> PermissionSet {
+        PermissionSet(self.0 | other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Permission> + '_ {
+        Permission::ALL
+            .into_iter()
+            .filter(|permission| self.contains(*permission))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Role {
+    pub name: String,
+    pub permissions: PermissionSet,
+}
+
+impl Role {
+    pub fn new(name: String, permissions: PermissionSet) -> Role {
+        Role { name, permissions }
+    }
+
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        wire::write_str(bytes, &self.name);
+        wire::write_u64(bytes, self.permissions.bits());
+    }
+
+    pub fn read_from(reader: &mut WireReader) -> Result<Role, MessageParseError> {
+        let name = reader.read_str("Role Name")?.to_owned();
+        let permissions = PermissionSet::from_bits(reader.read_u64("Permissions")?);
+
+        Ok(Role::new(name, permissions))
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
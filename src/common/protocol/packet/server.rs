@@ -1,7 +1,35 @@
+pub mod ack;
+pub mod attachment;
 pub mod authenticated;
+pub mod challenge;
 pub mod chat;
+pub mod command_result;
+pub mod delivery_status;
+pub mod encrypted_message;
 pub mod end;
+pub mod file_chunk;
+pub mod private_message;
+pub mod public_key;
+pub mod read_receipt;
+pub mod upload_ready;
+pub mod user_joined;
+pub mod user_left;
+pub mod warning;
 
+pub use ack::Ack;
+pub use attachment::Attachment;
 pub use authenticated::Authenticated;
-pub use chat::Chat;
-pub use end::End;
+pub use challenge::Challenge;
+pub use chat::{Chat, ChatRef};
+pub use command_result::CommandResult;
+pub use delivery_status::DeliveryStatus;
+pub use encrypted_message::EncryptedMessage;
+pub use end::{End, EndRef};
+pub use file_chunk::FileChunk;
+pub use private_message::PrivateMessage;
+pub use public_key::PublicKey;
+pub use read_receipt::ReadReceipt;
+pub use upload_ready::UploadReady;
+pub use user_joined::UserJoined;
+pub use user_left::UserLeft;
+pub use warning::Warning;
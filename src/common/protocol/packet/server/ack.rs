@@ -0,0 +1,64 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::{Packet, Rejection},
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Answers a client::Chat that carried a nonce, without a rejection the chat was relayed
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ack {
+    pub nonce: u64,
+    pub rejection: Option<Rejection>,
+}
+
+impl Ack {
+    pub fn new(nonce: u64, rejection: Option<Rejection>) -> Ack {
+        Ack { nonce, rejection }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+impl Display for Ack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rejection {
+            Some(rejection) => write!(f, "{}, rejected: {}", self.nonce, rejection),
+            None => write!(f, "{}, accepted", self.nonce),
+        }
+    }
+}
+
+impl Serializable for Ack {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.nonce);
+        wire::write_option(&mut bytes, &self.rejection, |bytes, rejection| {
+            bytes.push(rejection.id())
+        });
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Ack, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let nonce = reader.read_u64("Nonce")?;
+        let rejection =
+            reader.read_option("Rejection", |reader| Rejection::from_id(reader.read_u8()?))?;
+
+        Ok(Ack::new(nonce, rejection))
+    }
+}
+
+impl Packet for Ack {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::Ack(self))
+    }
+}
@@ -0,0 +1,61 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::{FileInfo, Packet},
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// The chat message for a finished upload, recipients download it with client::RequestChunk
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attachment {
+    pub username: String,
+    pub file_id: u64,
+    pub file: FileInfo,
+}
+
+impl Attachment {
+    pub fn new(username: String, file_id: u64, file: FileInfo) -> Attachment {
+        Attachment {
+            username,
+            file_id,
+            file,
+        }
+    }
+}
+
+impl Display for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.username, self.file)
+    }
+}
+
+impl Serializable for Attachment {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        wire::write_u64(&mut bytes, self.file_id);
+        self.file.write_to(&mut bytes);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Attachment, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let file_id = reader.read_u64("File ID")?;
+        let file = FileInfo::read_from(&mut reader)?;
+
+        Ok(Attachment::new(username, file_id, file))
+    }
+}
+
+impl Packet for Attachment {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::Attachment(self))
+    }
+}
@@ -1,39 +1,60 @@
 use crate::common::protocol::{
     error::MessageParseError,
     message::{server, Message},
-    packet::Packet,
+    packet::{Compression, Packet, Role},
     serializable::Serializable,
+    wire::WireReader,
 };
 use std::fmt::Display;
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct Authenticated {}
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Authenticated {
+    pub roles: Vec<Role>,
+    // Applies to every frame after this one
+    pub compression: Compression,
+}
 
 impl Authenticated {
-    pub fn new() -> Authenticated {
-        Authenticated {}
+    pub fn new(roles: Vec<Role>, compression: Compression) -> Authenticated {
+        Authenticated { roles, compression }
     }
 }
 
 impl Default for Authenticated {
     fn default() -> Self {
-        Self::new()
+        Self::new(Vec::new(), Compression::None)
     }
 }
 
 impl Display for Authenticated {
-    fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Ok(())
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let role_names: Vec<&str> = self.roles.iter().map(|role| role.name.as_str()).collect();
+        write!(f, "{}", role_names.join(", "))
     }
 }
 
 impl Serializable for Authenticated {
     fn as_bytes(&self) -> Vec<u8> {
-        Vec::new()
+        let mut bytes = vec![self.compression.id()];
+
+        for role in &self.roles {
+            role.write_to(&mut bytes);
+        }
+
+        bytes
     }
 
-    fn from_bytes(_bytes: &[u8]) -> Result<Authenticated, MessageParseError> {
-        Ok(Authenticated::new())
+    fn from_bytes(bytes: &[u8]) -> Result<Authenticated, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+        let compression = Compression::from_id(reader.read_u8()?);
+        let mut roles = Vec::new();
+
+        while !reader.is_empty() {
+            roles.push(Role::read_from(&mut reader)?);
+        }
+
+        Ok(Authenticated::new(roles, compression))
     }
 }
 
@@ -0,0 +1,49 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+// Sent during the handshake to a client that authenticates with a public key
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Challenge {
+    pub nonce: [u8; 32],
+}
+
+impl Challenge {
+    pub fn new(nonce: [u8; 32]) -> Challenge {
+        Challenge { nonce }
+    }
+}
+
+impl Display for Challenge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.nonce
+            .iter()
+            .try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl Serializable for Challenge {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.nonce.to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Challenge, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let nonce = reader.read_array("Nonce")?;
+
+        Ok(Challenge::new(nonce))
+    }
+}
+
+impl Packet for Challenge {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::Challenge(self))
+    }
+}
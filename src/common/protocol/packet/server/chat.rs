@@ -1,12 +1,13 @@
 use crate::common::protocol::{
     error::MessageParseError,
     message::{server, Message},
-    packet::Packet,
+    packet::{Packet, PacketRef},
     serializable::Serializable,
 };
 use std::fmt::Display;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chat {
     pub username: String,
     pub message: String,
@@ -37,6 +38,38 @@ impl Serializable for Chat {
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Chat, MessageParseError> {
+        ChatRef::from_bytes(bytes).map(ChatRef::into_owned)
+    }
+}
+
+impl Packet for Chat {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::Chat(self))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChatRef<'a> {
+    pub username: &'a str,
+    pub message: &'a str,
+}
+
+impl<'a> ChatRef<'a> {
+    pub fn new(username: &'a str, message: &'a str) -> ChatRef<'a> {
+        ChatRef { username, message }
+    }
+}
+
+impl Display for ChatRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.username, self.message)
+    }
+}
+
+impl<'a> PacketRef<'a> for ChatRef<'a> {
+    type Owned = Chat;
+
+    fn from_bytes(bytes: &'a [u8]) -> Result<ChatRef<'a>, MessageParseError> {
         let usize_bytes = usize::BITS as usize / 8; //
 
         if bytes.len() < usize_bytes {
@@ -55,32 +88,31 @@ impl Serializable for Chat {
         // usize username length
         // + username_length bytes
         // + at least 1 character for message
-        if bytes.len() < usize_bytes + username_length + 1 {
-            return Err(MessageParseError::UnexcpetedEndOfMessage);
-        }
+        // The length is untrusted, so the addition must not be allowed to overflow
+        let username_end = match usize_bytes.checked_add(username_length) {
+            Some(username_end) if username_end < bytes.len() => username_end,
+            _ => return Err(MessageParseError::UnexcpetedEndOfMessage),
+        };
 
-        let username =
-            match String::from_utf8(bytes[usize_bytes..usize_bytes + username_length].to_vec()) {
-                Ok(username) => username,
-                Err(err) => {
-                    return Err(MessageParseError::StringParse(
-                        String::from("Username"),
-                        err,
-                    ))
-                }
-            };
-
-        let message = match String::from_utf8(bytes[usize_bytes + username_length..].to_vec()) {
+        let username = match std::str::from_utf8(&bytes[usize_bytes..username_end]) {
+            Ok(username) => username,
+            Err(err) => {
+                return Err(MessageParseError::StringParse(
+                    String::from("Username"),
+                    err,
+                ))
+            }
+        };
+
+        let message = match std::str::from_utf8(&bytes[username_end..]) {
             Ok(message) => message,
             Err(err) => return Err(MessageParseError::StringParse(String::from("Message"), err)),
         };
 
-        Ok(Chat::new(username, message))
+        Ok(ChatRef::new(username, message))
     }
-}
 
-impl Packet for Chat {
-    fn to_message(self) -> Message {
-        Message::Server(server::Message::Chat(self))
+    fn into_owned(self) -> Chat {
+        Chat::new(self.username.to_owned(), self.message.to_owned())
     }
 }
@@ -0,0 +1,55 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandResult {
+    pub success: bool,
+    pub output: String,
+}
+
+impl CommandResult {
+    pub fn new(success: bool, output: String) -> CommandResult {
+        CommandResult { success, output }
+    }
+}
+
+impl Display for CommandResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.success {
+            true => write!(f, "{}", self.output),
+            false => write!(f, "Failed, {}", self.output),
+        }
+    }
+}
+
+impl Serializable for CommandResult {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.success as u8];
+
+        bytes.extend_from_slice(self.output.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<CommandResult, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let success = reader.read_bool("Success")?;
+        let output = reader.read_remaining_str("Output")?.to_owned();
+
+        Ok(CommandResult::new(success, output))
+    }
+}
+
+impl Packet for CommandResult {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::CommandResult(self))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::{Delivery, Packet},
+    serializable::Serializable,
+    wire::WireReader,
+};
+use std::fmt::Display;
+
+// Tells the sender of a client::EncryptedMessage whether it was delivered or queued
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeliveryStatus {
+    pub recipient: String,
+    pub delivery: Delivery,
+}
+
+impl DeliveryStatus {
+    pub fn new(recipient: String, delivery: Delivery) -> DeliveryStatus {
+        DeliveryStatus {
+            recipient,
+            delivery,
+        }
+    }
+}
+
+impl Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.recipient, self.delivery)
+    }
+}
+
+impl Serializable for DeliveryStatus {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.delivery.id()];
+        bytes.extend(self.recipient.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<DeliveryStatus, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let delivery = Delivery::from_id(reader.read_u8()?)?;
+        let recipient = reader.read_remaining_str("Recipient")?.to_owned();
+
+        Ok(DeliveryStatus::new(recipient, delivery))
+    }
+}
+
+impl Packet for DeliveryStatus {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::DeliveryStatus(self))
+    }
+}
@@ -0,0 +1,90 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Relayed from client::EncryptedMessage as is, with the recipient swapped for the sender.
+// Messages held for an offline recipient carry the Unix time they were queued at.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncryptedMessage {
+    pub sender: String,
+    pub queued_at: Option<u64>,
+    pub receipt_id: Option<u64>,
+    pub payload: Vec<u8>,
+}
+
+impl EncryptedMessage {
+    pub fn new(
+        sender: String,
+        queued_at: Option<u64>,
+        receipt_id: Option<u64>,
+        payload: Vec<u8>,
+    ) -> EncryptedMessage {
+        EncryptedMessage {
+            sender,
+            queued_at,
+            receipt_id,
+            payload,
+        }
+    }
+}
+
+impl Display for EncryptedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.queued_at {
+            Some(queued_at) => write!(
+                f,
+                "{}, {} bytes, queued at {}",
+                self.sender,
+                self.payload.len(),
+                queued_at
+            ),
+            None => write!(f, "{}, {} bytes", self.sender, self.payload.len()),
+        }
+    }
+}
+
+impl Serializable for EncryptedMessage {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.sender);
+        wire::write_option(&mut bytes, &self.queued_at, |bytes, queued_at| {
+            wire::write_u64(bytes, *queued_at)
+        });
+        wire::write_option(&mut bytes, &self.receipt_id, |bytes, receipt_id| {
+            wire::write_u64(bytes, *receipt_id)
+        });
+        bytes.extend_from_slice(&self.payload);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<EncryptedMessage, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let sender = reader.read_str("Sender")?.to_owned();
+        let queued_at = reader.read_option("Queued at", |reader| reader.read_u64("Queued at"))?;
+        let receipt_id =
+            reader.read_option("Receipt id", |reader| reader.read_u64("Receipt id"))?;
+        let payload = match reader.read_remaining_bytes() {
+            [] => return Err(MessageParseError::UnexcpetedEndOfMessage),
+            payload => payload.to_vec(),
+        };
+
+        Ok(EncryptedMessage::new(
+            sender, queued_at, receipt_id, payload,
+        ))
+    }
+}
+
+impl Packet for EncryptedMessage {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::EncryptedMessage(self))
+    }
+}
@@ -0,0 +1,67 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Answers client::RequestChunk, an empty chunk means the offset is past the end of the file
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileChunk {
+    pub file_id: u64,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl FileChunk {
+    pub fn new(file_id: u64, offset: u64, data: Vec<u8>) -> FileChunk {
+        FileChunk {
+            file_id,
+            offset,
+            data,
+        }
+    }
+}
+
+impl Display for FileChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:016x}, {}, {} bytes",
+            self.file_id,
+            self.offset,
+            self.data.len()
+        )
+    }
+}
+
+impl Serializable for FileChunk {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.file_id);
+        wire::write_u64(&mut bytes, self.offset);
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<FileChunk, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let file_id = reader.read_u64("File ID")?;
+        let offset = reader.read_u64("Offset")?;
+        let data = reader.read_remaining_bytes().to_vec();
+
+        Ok(FileChunk::new(file_id, offset, data))
+    }
+}
+
+impl Packet for FileChunk {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::FileChunk(self))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// A plain text message to one user, sent by the /msg command.
+// Unlike EncryptedMessage, the server can read it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrivateMessage {
+    pub sender: String,
+    pub message: String,
+}
+
+impl PrivateMessage {
+    pub fn new(sender: String, message: String) -> PrivateMessage {
+        PrivateMessage { sender, message }
+    }
+}
+
+impl Display for PrivateMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.sender, self.message)
+    }
+}
+
+impl Serializable for PrivateMessage {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.sender);
+        bytes.extend_from_slice(self.message.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<PrivateMessage, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let sender = reader.read_str("Sender")?.to_owned();
+        let message = reader.read_remaining_str("Message")?.to_owned();
+
+        Ok(PrivateMessage::new(sender, message))
+    }
+}
+
+impl Packet for PrivateMessage {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::PrivateMessage(self))
+    }
+}
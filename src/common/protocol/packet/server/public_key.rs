@@ -0,0 +1,67 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Answers client::RequestKey, None if the user never published a key
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublicKey {
+    pub username: String,
+    pub public_key: Option<[u8; 32]>,
+}
+
+impl PublicKey {
+    pub fn new(username: String, public_key: Option<[u8; 32]>) -> PublicKey {
+        PublicKey {
+            username,
+            public_key,
+        }
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, ", self.username)?;
+
+        match &self.public_key {
+            Some(public_key) => public_key
+                .iter()
+                .try_for_each(|byte| write!(f, "{:02x}", byte)),
+            None => write!(f, "None"),
+        }
+    }
+}
+
+impl Serializable for PublicKey {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_str(&mut bytes, &self.username);
+        wire::write_option(&mut bytes, &self.public_key, |bytes, public_key| {
+            bytes.extend_from_slice(public_key)
+        });
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<PublicKey, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let username = reader.read_str("Username")?.to_owned();
+        let public_key =
+            reader.read_option("Public Key", |reader| reader.read_array("Public Key"))?;
+
+        Ok(PublicKey::new(username, public_key))
+    }
+}
+
+impl Packet for PublicKey {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::PublicKey(self))
+    }
+}
@@ -0,0 +1,54 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Relayed from client::ReadReceipt to the sender of the message that was read
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReadReceipt {
+    pub reader: String,
+    pub receipt_id: u64,
+}
+
+impl ReadReceipt {
+    pub fn new(reader: String, receipt_id: u64) -> ReadReceipt {
+        ReadReceipt { reader, receipt_id }
+    }
+}
+
+impl Display for ReadReceipt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.reader, self.receipt_id)
+    }
+}
+
+impl Serializable for ReadReceipt {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.receipt_id);
+        bytes.extend_from_slice(self.reader.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ReadReceipt, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let receipt_id = reader.read_u64("Receipt id")?;
+        let reader = reader.read_remaining_str("Reader")?.to_owned();
+
+        Ok(ReadReceipt::new(reader, receipt_id))
+    }
+}
+
+impl Packet for ReadReceipt {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::ReadReceipt(self))
+    }
+}
@@ -0,0 +1,54 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+    wire::{self, WireReader},
+};
+use std::fmt::Display;
+
+// Answers client::OfferFile. The offset is where a resumed upload continues, 0 for a new one.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UploadReady {
+    pub file_id: u64,
+    pub offset: u64,
+}
+
+impl UploadReady {
+    pub fn new(file_id: u64, offset: u64) -> UploadReady {
+        UploadReady { file_id, offset }
+    }
+}
+
+impl Display for UploadReady {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}, {}", self.file_id, self.offset)
+    }
+}
+
+impl Serializable for UploadReady {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        wire::write_u64(&mut bytes, self.file_id);
+        wire::write_u64(&mut bytes, self.offset);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<UploadReady, MessageParseError> {
+        let mut reader = WireReader::new(bytes);
+
+        let file_id = reader.read_u64("File ID")?;
+        let offset = reader.read_u64("Offset")?;
+
+        Ok(UploadReady::new(file_id, offset))
+    }
+}
+
+impl Packet for UploadReady {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::UploadReady(self))
+    }
+}
@@ -0,0 +1,59 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserJoined {
+    pub username: String,
+}
+
+impl UserJoined {
+    pub fn new(username: String) -> UserJoined {
+        UserJoined { username }
+    }
+}
+
+impl Display for UserJoined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.username)
+    }
+}
+
+impl Serializable for UserJoined {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(self.username.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<UserJoined, MessageParseError> {
+        if bytes.is_empty() {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+
+        let username = match std::str::from_utf8(bytes) {
+            Ok(username) => username.to_owned(),
+            Err(err) => {
+                return Err(MessageParseError::StringParse(
+                    String::from("Username"),
+                    err,
+                ))
+            }
+        };
+
+        Ok(UserJoined::new(username))
+    }
+}
+
+impl Packet for UserJoined {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::UserJoined(self))
+    }
+}
@@ -0,0 +1,59 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserLeft {
+    pub username: String,
+}
+
+impl UserLeft {
+    pub fn new(username: String) -> UserLeft {
+        UserLeft { username }
+    }
+}
+
+impl Display for UserLeft {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.username)
+    }
+}
+
+impl Serializable for UserLeft {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(self.username.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<UserLeft, MessageParseError> {
+        if bytes.is_empty() {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+
+        let username = match std::str::from_utf8(bytes) {
+            Ok(username) => username.to_owned(),
+            Err(err) => {
+                return Err(MessageParseError::StringParse(
+                    String::from("Username"),
+                    err,
+                ))
+            }
+        };
+
+        Ok(UserLeft::new(username))
+    }
+}
+
+impl Packet for UserLeft {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::UserLeft(self))
+    }
+}
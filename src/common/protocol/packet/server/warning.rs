@@ -0,0 +1,54 @@
+use crate::common::protocol::{
+    error::MessageParseError,
+    message::{server, Message},
+    packet::Packet,
+    serializable::Serializable,
+};
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Warning {
+    pub text: String,
+}
+
+impl Warning {
+    pub fn new(text: String) -> Warning {
+        Warning { text }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Serializable for Warning {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(self.text.as_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Warning, MessageParseError> {
+        if bytes.is_empty() {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_owned(),
+            Err(err) => return Err(MessageParseError::StringParse(String::from("Text"), err)),
+        };
+
+        Ok(Warning::new(text))
+    }
+}
+
+impl Packet for Warning {
+    fn to_message(self) -> Message {
+        Message::Server(server::Message::Warning(self))
+    }
+}
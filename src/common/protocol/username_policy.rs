@@ -0,0 +1,205 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::common::protocol::error::UsernameError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CharacterClass {
+    Alphabetic,
+    Numeric,
+    // Only the plain space, tabs and other whitespace stay out of usernames
+    Space,
+    Characters(String),
+}
+
+impl CharacterClass {
+    pub fn contains(&self, character: char) -> bool {
+        match self {
+            CharacterClass::Alphabetic => character.is_alphabetic(),
+            CharacterClass::Numeric => character.is_numeric(),
+            CharacterClass::Space => character == ' ',
+            CharacterClass::Characters(characters) => characters.contains(character),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsernamePolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub allowed_characters: Vec<CharacterClass>,
+    pub normalize: bool,
+    pub case_insensitive: bool,
+    pub confusable_aware: bool,
+    pub reserved_usernames: Vec<String>,
+}
+
+impl UsernamePolicy {
+    pub fn new() -> UsernamePolicy {
+        UsernamePolicy {
+            min_length: 1,
+            max_length: 32,
+            allowed_characters: vec![
+                CharacterClass::Alphabetic,
+                CharacterClass::Numeric,
+                CharacterClass::Characters(String::from("_-.")),
+            ],
+            normalize: true,
+            case_insensitive: true,
+            confusable_aware: true,
+            reserved_usernames: Vec::new(),
+        }
+    }
+
+    // Returns the username as it should be stored and displayed from now on
+    pub fn normalize(&self, username: &str) -> String {
+        let username = username.trim();
+
+        match self.normalize {
+            true => username.nfkc().collect(),
+            false => username.to_owned(),
+        }
+    }
+
+    // Returns the key two usernames are compared by, so "Alice", "alice" and "Аlice" (Cyrillic А) collide
+    pub fn canonicalize(&self, username: &str) -> String {
+        let mut username = self.normalize(username);
+
+        if self.case_insensitive {
+            username = username.to_lowercase();
+        }
+
+        if self.confusable_aware {
+            username = unicode_security::skeleton(&username).collect();
+        }
+
+        username
+    }
+
+    pub fn validate(
+        &self,
+        username: &str,
+        taken_usernames: &[String],
+    ) -> Result<String, UsernameError> {
+        let username = self.normalize(username);
+        let length = username.chars().count();
+
+        if length < self.min_length {
+            return Err(UsernameError::TooShort(self.min_length));
+        }
+
+        if length > self.max_length {
+            return Err(UsernameError::TooLong(self.max_length));
+        }
+
+        if let Some(character) = username.chars().find(|character| {
+            !self
+                .allowed_characters
+                .iter()
+                .any(|class| class.contains(*character))
+        }) {
+            return Err(UsernameError::DisallowedCharacter(character));
+        }
+
+        let canonical_username = self.canonicalize(&username);
+
+        if self.matches_any(&canonical_username, &self.reserved_usernames) {
+            return Err(UsernameError::Reserved(username));
+        }
+
+        if self.matches_any(&canonical_username, taken_usernames) {
+            return Err(UsernameError::Taken(username));
+        }
+
+        Ok(username)
+    }
+
+    fn matches_any(&self, canonical_username: &str, usernames: &[String]) -> bool {
+        usernames
+            .iter()
+            .any(|username| self.canonicalize(username) == canonical_username)
+    }
+}
+
+impl Default for UsernamePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_rejects_case_and_whitespace_variants() {
+        let policy = UsernamePolicy::new();
+        let taken_usernames = vec![String::from("Alice")];
+
+        for username in ["Alice", "alice", "Alice ", " ALICE"] {
+            assert_eq!(
+                policy.validate(username, &taken_usernames),
+                Err(UsernameError::Taken(String::from(username.trim())))
+            );
+        }
+    }
+
+    #[test]
+    fn policy_rejects_confusable_variants() {
+        let policy = UsernamePolicy::new();
+        let taken_usernames = vec![String::from("Alice")];
+
+        // Cyrillic А and fullwidth ｌ
+        let username = "\u{0410}\u{ff4c}ice";
+
+        assert_eq!(
+            policy.validate(username, &taken_usernames),
+            Err(UsernameError::Taken(String::from("\u{0410}lice")))
+        );
+    }
+
+    #[test]
+    fn policy_rejects_empty_looking_usernames() {
+        let policy = UsernamePolicy::new();
+
+        assert_eq!(policy.validate("   ", &[]), Err(UsernameError::TooShort(1)));
+        assert_eq!(
+            policy.validate("a\u{200b}b", &[]),
+            Err(UsernameError::DisallowedCharacter('\u{200b}'))
+        );
+    }
+
+    #[test]
+    fn policy_space_class_only_allows_plain_spaces() {
+        let mut policy = UsernamePolicy::new();
+        policy.allowed_characters.push(CharacterClass::Space);
+
+        assert!(policy.validate("Jane Doe", &[]).is_ok());
+        assert_eq!(
+            policy.validate("Jane\tDoe", &[]),
+            Err(UsernameError::DisallowedCharacter('\t'))
+        );
+    }
+
+    #[test]
+    fn policy_rejects_reserved_usernames() {
+        let policy = UsernamePolicy {
+            reserved_usernames: vec![String::from("Server")],
+            ..UsernamePolicy::new()
+        };
+
+        assert_eq!(
+            policy.validate("server", &[]),
+            Err(UsernameError::Reserved(String::from("server")))
+        );
+    }
+
+    #[test]
+    fn policy_accepts_and_normalizes_valid_usernames() {
+        let policy = UsernamePolicy::new();
+
+        assert_eq!(
+            policy.validate(" Kitt3120 ", &[String::from("Alice")]),
+            Ok(String::from("Kitt3120"))
+        );
+    }
+}
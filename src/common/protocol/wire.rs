@@ -0,0 +1,165 @@
+use crate::common::protocol::error::MessageParseError;
+
+// Strings that are followed by other fields are prefixed with their length as a little-endian usize,
+// the same layout server::Chat uses for its username.
+pub fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_bytes(bytes, value.as_bytes());
+}
+
+pub fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend(value.len().to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+pub fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend(value.to_le_bytes());
+}
+
+pub fn write_option<T>(bytes: &mut Vec<u8>, value: &Option<T>, write: impl Fn(&mut Vec<u8>, &T)) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            write(bytes, value);
+        }
+        None => bytes.push(0),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WireReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> WireReader<'a> {
+        WireReader { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], MessageParseError> {
+        if self.bytes.len() < length {
+            return Err(MessageParseError::UnexcpetedEndOfMessage);
+        }
+
+        let (value, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+
+        Ok(value)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, MessageParseError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self, value: &str) -> Result<bool, MessageParseError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(MessageParseError::ByteParse(String::from(value))),
+        }
+    }
+
+    pub fn read_u64(&mut self, value: &str) -> Result<u64, MessageParseError> {
+        match self.read_bytes(8)?.try_into() {
+            Ok(bytes) => Ok(u64::from_le_bytes(bytes)),
+            Err(_) => Err(MessageParseError::ByteParse(String::from(value))),
+        }
+    }
+
+    pub fn read_usize(&mut self, value: &str) -> Result<usize, MessageParseError> {
+        let usize_bytes = usize::BITS as usize / 8;
+
+        match self.read_bytes(usize_bytes)?.try_into() {
+            Ok(bytes) => Ok(usize::from_le_bytes(bytes)),
+            Err(_) => Err(MessageParseError::ByteParse(String::from(value))),
+        }
+    }
+
+    pub fn read_array<const N: usize>(
+        &mut self,
+        value: &str,
+    ) -> Result<[u8; N], MessageParseError> {
+        match self.read_bytes(N)?.try_into() {
+            Ok(bytes) => Ok(bytes),
+            Err(_) => Err(MessageParseError::ByteParse(String::from(value))),
+        }
+    }
+
+    pub fn read_prefixed_bytes(&mut self, value: &str) -> Result<&'a [u8], MessageParseError> {
+        let length = self.read_usize(value)?;
+        self.read_bytes(length)
+    }
+
+    pub fn read_str(&mut self, value: &str) -> Result<&'a str, MessageParseError> {
+        let bytes = self.read_prefixed_bytes(value)?;
+
+        std::str::from_utf8(bytes)
+            .map_err(|err| MessageParseError::StringParse(String::from(value), err))
+    }
+
+    pub fn read_remaining_bytes(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    pub fn read_remaining_str(&mut self, value: &str) -> Result<&'a str, MessageParseError> {
+        let bytes = self.read_bytes(self.bytes.len())?;
+
+        std::str::from_utf8(bytes)
+            .map_err(|err| MessageParseError::StringParse(String::from(value), err))
+    }
+
+    pub fn read_option<T>(
+        &mut self,
+        value: &str,
+        read: impl FnOnce(&mut WireReader<'a>) -> Result<T, MessageParseError>,
+    ) -> Result<Option<T>, MessageParseError> {
+        match self.read_bool(value)? {
+            true => read(self).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_reader_rejects_oversized_length() {
+        let mut bytes = usize::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(b"abc");
+
+        assert_eq!(
+            WireReader::new(&bytes).read_str("Value"),
+            Err(MessageParseError::UnexcpetedEndOfMessage)
+        );
+    }
+
+    #[test]
+    fn wire_reader_reads_written_values() {
+        let mut bytes = Vec::new();
+        write_str(&mut bytes, "Kitt3120");
+        write_option(&mut bytes, &Some(42), |bytes, value| {
+            write_u64(bytes, *value)
+        });
+        write_option::<u64>(&mut bytes, &None, |bytes, value| write_u64(bytes, *value));
+        bytes.extend_from_slice("⚡".as_bytes());
+
+        let mut reader = WireReader::new(&bytes);
+
+        assert_eq!(reader.read_str("Username"), Ok("Kitt3120"));
+        assert_eq!(
+            reader.read_option("Number", |reader| reader.read_u64("Number")),
+            Ok(Some(42))
+        );
+        assert_eq!(
+            reader.read_option("Number", |reader| reader.read_u64("Number")),
+            Ok(None)
+        );
+        assert_eq!(reader.read_remaining_str("Message"), Ok("⚡"));
+        assert!(reader.is_empty());
+    }
+}
@@ -0,0 +1,504 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::common::protocol::{
+    message::Message,
+    packet::{server::End, server::Warning, EndReason, Packet},
+};
+
+// Address buckets that have filled up again are forgotten once this many addresses are tracked
+const ADDRESS_PRUNE_THRESHOLD: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u64,
+    pub per_second: u64,
+}
+
+impl RateLimit {
+    pub fn new(burst: u64, per_second: u64) -> RateLimit {
+        RateLimit { burst, per_second }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    pub fn try_consume(&mut self, amount: u64, now: Instant) -> bool {
+        if !self.has(amount, now) {
+            return false;
+        }
+
+        self.tokens -= amount as f64;
+        true
+    }
+
+    pub fn has(&mut self, amount: u64, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= amount as f64
+    }
+
+    pub fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.limit.burst as f64
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+
+        self.tokens =
+            (self.tokens + elapsed * self.limit.per_second as f64).min(self.limit.burst as f64);
+        self.last_refill = now;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Penalty {
+    Drop,
+    Warn,
+    Mute(Duration),
+    Disconnect,
+}
+
+impl Penalty {
+    // The message the offending session should be sent, if any
+    pub fn response(&self) -> Option<Message> {
+        match self {
+            Penalty::Drop => None,
+            Penalty::Warn => Some(
+                Warning::new(String::from("You are sending messages too quickly")).to_message(),
+            ),
+            Penalty::Mute(duration) => Some(
+                Warning::new(format!(
+                    "You are sending messages too quickly and were muted for {} seconds",
+                    duration.as_secs()
+                ))
+                .to_message(),
+            ),
+            Penalty::Disconnect => Some(
+                End::new(
+                    EndReason::RateLimited,
+                    Some(String::from("You are sending messages too quickly")),
+                )
+                .to_message(),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    pub session_messages: RateLimit,
+    pub session_bytes: RateLimit,
+    pub address_messages: RateLimit,
+    pub address_bytes: RateLimit,
+    pub address_connections: RateLimit,
+    pub penalty: Penalty,
+}
+
+impl RateLimitConfig {
+    pub fn new() -> RateLimitConfig {
+        RateLimitConfig {
+            session_messages: RateLimit::new(10, 5),
+            session_bytes: RateLimit::new(64 * 1024, 16 * 1024),
+            address_messages: RateLimit::new(40, 20),
+            address_bytes: RateLimit::new(256 * 1024, 64 * 1024),
+            address_connections: RateLimit::new(5, 1),
+            penalty: Penalty::Warn,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimitMetrics {
+    pub messages_limited: u64,
+    pub bytes_limited: u64,
+    pub connections_limited: u64,
+    pub dropped: u64,
+    pub warned: u64,
+    pub muted: u64,
+    pub disconnected: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    messages_limited: AtomicU64,
+    bytes_limited: AtomicU64,
+    connections_limited: AtomicU64,
+    dropped: AtomicU64,
+    warned: AtomicU64,
+    muted: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionRateLimit {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    muted_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct AddressBuckets {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    connections: TokenBucket,
+}
+
+impl AddressBuckets {
+    fn new(config: &RateLimitConfig, now: Instant) -> AddressBuckets {
+        AddressBuckets {
+            messages: TokenBucket::new(config.address_messages, now),
+            bytes: TokenBucket::new(config.address_bytes, now),
+            connections: TokenBucket::new(config.address_connections, now),
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.messages.is_full(now) && self.bytes.is_full(now) && self.connections.is_full(now)
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    addresses: Mutex<HashMap<IpAddr, AddressBuckets>>,
+    counters: Counters,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            addresses: Mutex::new(HashMap::new()),
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn new_session(&self) -> SessionRateLimit {
+        let now = Instant::now();
+
+        SessionRateLimit {
+            messages: TokenBucket::new(self.config.session_messages, now),
+            bytes: TokenBucket::new(self.config.session_bytes, now),
+            muted_until: None,
+        }
+    }
+
+    // A connection over the limit has not been accepted yet, so it is always disconnected
+    pub fn admit_connection(&self, address: IpAddr) -> Result<(), Penalty> {
+        self.admit_connection_at(address, Instant::now())
+    }
+
+    // Sessions without an address, like local ones, are only limited per session
+    pub fn check_message(
+        &self,
+        session: &mut SessionRateLimit,
+        address: Option<IpAddr>,
+        size: usize,
+    ) -> Result<(), Penalty> {
+        self.check_message_at(session, address, size, Instant::now())
+    }
+
+    pub fn metrics(&self) -> RateLimitMetrics {
+        RateLimitMetrics {
+            messages_limited: self.counters.messages_limited.load(Ordering::Relaxed),
+            bytes_limited: self.counters.bytes_limited.load(Ordering::Relaxed),
+            connections_limited: self.counters.connections_limited.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            warned: self.counters.warned.load(Ordering::Relaxed),
+            muted: self.counters.muted.load(Ordering::Relaxed),
+            disconnected: self.counters.disconnected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn admit_connection_at(&self, address: IpAddr, now: Instant) -> Result<(), Penalty> {
+        let admitted = self.with_address(address, now, |buckets| {
+            buckets.connections.try_consume(1, now)
+        });
+
+        if admitted {
+            return Ok(());
+        }
+
+        self.counters
+            .connections_limited
+            .fetch_add(1, Ordering::Relaxed);
+        Err(self.penalize(Penalty::Disconnect))
+    }
+
+    fn check_message_at(
+        &self,
+        session: &mut SessionRateLimit,
+        address: Option<IpAddr>,
+        size: usize,
+        now: Instant,
+    ) -> Result<(), Penalty> {
+        if let Some(muted_until) = session.muted_until {
+            if now < muted_until {
+                return Err(self.penalize(Penalty::Drop));
+            }
+
+            session.muted_until = None;
+        }
+
+        let size = size as u64;
+        let (messages_allowed, bytes_allowed) = match address {
+            Some(address) => self.with_address(address, now, |buckets| {
+                consume_message(session, Some(buckets), size, now)
+            }),
+            None => consume_message(session, None, size, now),
+        };
+
+        if messages_allowed && bytes_allowed {
+            return Ok(());
+        }
+
+        if !messages_allowed {
+            self.counters
+                .messages_limited
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        if !bytes_allowed {
+            self.counters.bytes_limited.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Penalty::Mute(duration) = self.config.penalty {
+            session.muted_until = Some(now + duration);
+        }
+
+        Err(self.penalize(self.config.penalty))
+    }
+
+    fn with_address<T>(
+        &self,
+        address: IpAddr,
+        now: Instant,
+        action: impl FnOnce(&mut AddressBuckets) -> T,
+    ) -> T {
+        // The buckets stay consistent even if another thread panicked while holding the lock
+        let mut addresses = self
+            .addresses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if addresses.len() >= ADDRESS_PRUNE_THRESHOLD {
+            addresses.retain(|_, buckets| !buckets.is_full(now));
+        }
+
+        let buckets = addresses
+            .entry(address)
+            .or_insert_with(|| AddressBuckets::new(&self.config, now));
+
+        action(buckets)
+    }
+
+    fn penalize(&self, penalty: Penalty) -> Penalty {
+        let counter = match penalty {
+            Penalty::Drop => &self.counters.dropped,
+            Penalty::Warn => &self.counters.warned,
+            Penalty::Mute(_) => &self.counters.muted,
+            Penalty::Disconnect => &self.counters.disconnected,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+        penalty
+    }
+}
+
+// Tokens are only spent once every bucket has room, so a rejected message costs nothing.
+// Returns whether the message and byte buckets had room.
+fn consume_message(
+    session: &mut SessionRateLimit,
+    mut address: Option<&mut AddressBuckets>,
+    size: u64,
+    now: Instant,
+) -> (bool, bool) {
+    let messages_allowed = session.messages.has(1, now)
+        && address
+            .as_mut()
+            .is_none_or(|buckets| buckets.messages.has(1, now));
+    let bytes_allowed = session.bytes.has(size, now)
+        && address
+            .as_mut()
+            .is_none_or(|buckets| buckets.bytes.has(size, now));
+
+    if messages_allowed && bytes_allowed {
+        session.messages.try_consume(1, now);
+        session.bytes.try_consume(size, now);
+
+        if let Some(buckets) = address {
+            buckets.messages.try_consume(1, now);
+            buckets.bytes.try_consume(size, now);
+        }
+    }
+
+    (messages_allowed, bytes_allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> IpAddr {
+        IpAddr::from([192, 0, 2, 1])
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit::new(2, 1), now);
+
+        assert!(bucket.try_consume(1, now));
+        assert!(bucket.try_consume(1, now));
+        assert!(!bucket.try_consume(1, now));
+        assert!(bucket.try_consume(1, now + Duration::from_secs(1)));
+        assert!(bucket.is_full(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn rate_limiter_penalizes_message_floods() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            session_messages: RateLimit::new(2, 1),
+            ..RateLimitConfig::new()
+        });
+        let mut session = rate_limiter.new_session();
+        let now = Instant::now();
+
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 1, now),
+            Err(Penalty::Warn)
+        );
+
+        let metrics = rate_limiter.metrics();
+        assert_eq!(metrics.messages_limited, 1);
+        assert_eq!(metrics.warned, 1);
+    }
+
+    #[test]
+    fn rate_limiter_rejection_spends_no_tokens() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            session_messages: RateLimit::new(2, 1),
+            session_bytes: RateLimit::new(4, 1),
+            address_messages: RateLimit::new(2, 1),
+            address_bytes: RateLimit::new(4, 1),
+            ..RateLimitConfig::new()
+        });
+        let mut session = rate_limiter.new_session();
+        let now = Instant::now();
+
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 8, now),
+            Err(Penalty::Warn)
+        );
+
+        let expected = rate_limiter.new_session();
+        assert_eq!(session.messages.tokens, expected.messages.tokens);
+        assert_eq!(session.bytes.tokens, expected.bytes.tokens);
+        rate_limiter.with_address(address(), now, |buckets| {
+            assert!(buckets.messages.is_full(now));
+            assert!(buckets.bytes.is_full(now));
+        });
+
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 4, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rate_limiter_drops_messages_while_muted() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            session_bytes: RateLimit::new(4, 4),
+            penalty: Penalty::Mute(Duration::from_secs(30)),
+            ..RateLimitConfig::new()
+        });
+        let mut session = rate_limiter.new_session();
+        let now = Instant::now();
+
+        assert_eq!(
+            rate_limiter.check_message_at(&mut session, Some(address()), 8, now),
+            Err(Penalty::Mute(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            rate_limiter.check_message_at(
+                &mut session,
+                Some(address()),
+                1,
+                now + Duration::from_secs(10)
+            ),
+            Err(Penalty::Drop)
+        );
+        assert_eq!(
+            rate_limiter.check_message_at(
+                &mut session,
+                Some(address()),
+                1,
+                now + Duration::from_secs(31)
+            ),
+            Ok(())
+        );
+
+        let metrics = rate_limiter.metrics();
+        assert_eq!(metrics.bytes_limited, 1);
+        assert_eq!(metrics.muted, 1);
+        assert_eq!(metrics.dropped, 1);
+    }
+
+    #[test]
+    fn rate_limiter_limits_connections_per_address() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            address_connections: RateLimit::new(1, 1),
+            ..RateLimitConfig::new()
+        });
+        let now = Instant::now();
+
+        assert_eq!(rate_limiter.admit_connection_at(address(), now), Ok(()));
+        assert_eq!(
+            rate_limiter.admit_connection_at(address(), now),
+            Err(Penalty::Disconnect)
+        );
+        assert_eq!(
+            rate_limiter.admit_connection_at(IpAddr::from([192, 0, 2, 2]), now),
+            Ok(())
+        );
+        assert_eq!(rate_limiter.metrics().connections_limited, 1);
+    }
+}
@@ -0,0 +1,1338 @@
+pub mod error;
+pub mod session;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    sync::{mpsc::Sender, Mutex, MutexGuard, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use self::error::ServerStateError;
+
+use crate::common::{
+    authorized_keys::AuthorizedKeys,
+    command::ServerCommandContext,
+    federation::Federation,
+    file_transfer::FileStore,
+    message_stream::MessageTransport,
+    moderation::{error::ModerationError, Moderation},
+    offline_queue::OfflineQueue,
+    peer_credentials::PeerCredentials,
+    permissions::Permissions,
+    plugin::{Outgoing, PluginHost, Recipient},
+    protocol::{
+        error::{HandshakeError, UsernameError},
+        handshake::server::{Handshake, HandshakeArguments},
+        message::{client as client_message, server as server_message, Message},
+        packet::{
+            client::{self, Ban, Kick, OfferFile, PublishKey, RequestChunk, RequestKey},
+            server::{
+                self, Ack, Chat, DeliveryStatus, End, PrivateMessage, PublicKey, UploadReady,
+                UserJoined, UserLeft,
+            },
+            BanTarget, Delivery, EndReason, Packet, Rejection,
+        },
+        username_policy::UsernamePolicy,
+    },
+    rate_limit::{Penalty, RateLimitConfig, RateLimiter, SessionRateLimit},
+};
+
+#[cfg(feature = "webhooks")]
+use crate::common::webhook::{WebhookDispatcher, WebhookEvent};
+
+// rusty_chat has one conversation, listings call it by this name
+pub const MAIN_ROOM: &str = "main";
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub username: String,
+    pub device: Option<String>,
+    pub address: Option<IpAddr>,
+    pub connected_at: Instant,
+    sender: Sender<Message>,
+    rate_limit: SessionRateLimit,
+    // The end-to-end encryption key this session published, shared by all of the user's sessions
+    public_key: Option<[u8; 32]>,
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, connected for {}s",
+            self.device.as_deref().unwrap_or("Unnamed device"),
+            self.connected_at.elapsed().as_secs()
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ServerStats {
+    pub connected_users: usize,
+    pub messages_relayed: u64,
+    pub active_bans: usize,
+    pub uptime_seconds: u64,
+}
+
+// Everything a listener needs to admit and serve a session, shared by every listener behind a Mutex.
+// Each session's connection thread drains its own channel, so sending never blocks on a slow socket.
+#[derive(Debug)]
+pub struct ServerState {
+    username_policy: UsernamePolicy,
+    moderation: Moderation,
+    permissions: Permissions,
+    authorized_keys: AuthorizedKeys,
+    peer_credentials: PeerCredentials,
+    offline_queue: OfflineQueue,
+    rate_limiter: RateLimiter,
+    plugins: PluginHost,
+    // Without one, file packets are answered with FileSharingDisabled
+    file_store: Option<FileStore>,
+    federation: Option<Federation>,
+    #[cfg(feature = "webhooks")]
+    webhooks: Option<WebhookDispatcher>,
+    sessions: HashMap<u64, Session>,
+    next_session_id: u64,
+    messages_relayed: u64,
+    started_at: Instant,
+}
+
+impl ServerState {
+    pub fn new(
+        username_policy: UsernamePolicy,
+        moderation: Moderation,
+        permissions: Permissions,
+        authorized_keys: AuthorizedKeys,
+        peer_credentials: PeerCredentials,
+    ) -> ServerState {
+        ServerState {
+            username_policy,
+            moderation,
+            permissions,
+            authorized_keys,
+            peer_credentials,
+            offline_queue: OfflineQueue::default(),
+            rate_limiter: RateLimiter::new(RateLimitConfig::default()),
+            plugins: PluginHost::new(),
+            file_store: None,
+            federation: None,
+            #[cfg(feature = "webhooks")]
+            webhooks: None,
+            sessions: HashMap::new(),
+            next_session_id: 0,
+            messages_relayed: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    // Runs the handshake against a snapshot, so the lock isn't held while the client takes its time.
+    // A username taken in the meantime is caught by join.
+    pub fn handshake<T: MessageTransport>(
+        state: &Mutex<ServerState>,
+        transport: &mut T,
+    ) -> Result<Handshake, HandshakeError> {
+        let (
+            usernames,
+            username_policy,
+            moderation,
+            permissions,
+            authorized_keys,
+            peer_credentials,
+        ) = {
+            let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                state.usernames(),
+                state.username_policy.clone(),
+                state.moderation.clone(),
+                state.permissions.clone(),
+                state.authorized_keys.clone(),
+                state.peer_credentials.clone(),
+            )
+        };
+
+        let arguments = HandshakeArguments::new(
+            &usernames,
+            &username_policy,
+            &moderation,
+            &permissions,
+            &authorized_keys,
+            &peer_credentials,
+        );
+        Handshake::perform(transport, arguments)
+    }
+
+    pub fn username_policy(&self) -> &UsernamePolicy {
+        &self.username_policy
+    }
+
+    pub fn moderation(&self) -> &Moderation {
+        &self.moderation
+    }
+
+    pub fn moderation_mut(&mut self) -> &mut Moderation {
+        &mut self.moderation
+    }
+
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    pub fn permissions_mut(&mut self) -> &mut Permissions {
+        &mut self.permissions
+    }
+
+    pub fn authorized_keys(&self) -> &AuthorizedKeys {
+        &self.authorized_keys
+    }
+
+    pub fn authorized_keys_mut(&mut self) -> &mut AuthorizedKeys {
+        &mut self.authorized_keys
+    }
+
+    pub fn peer_credentials(&self) -> &PeerCredentials {
+        &self.peer_credentials
+    }
+
+    pub fn peer_credentials_mut(&mut self) -> &mut PeerCredentials {
+        &mut self.peer_credentials
+    }
+
+    pub fn offline_queue(&self) -> &OfflineQueue {
+        &self.offline_queue
+    }
+
+    pub fn offline_queue_mut(&mut self) -> &mut OfflineQueue {
+        &mut self.offline_queue
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    // Replaces the limiter and with it every address bucket, sessions keep their own buckets
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn plugins(&self) -> &PluginHost {
+        &self.plugins
+    }
+
+    pub fn plugins_mut(&mut self) -> &mut PluginHost {
+        &mut self.plugins
+    }
+
+    pub fn file_store(&self) -> Option<&FileStore> {
+        self.file_store.as_ref()
+    }
+
+    pub fn set_file_store(&mut self, file_store: FileStore) {
+        self.file_store = Some(file_store);
+    }
+
+    pub fn federation(&self) -> Option<&Federation> {
+        self.federation.as_ref()
+    }
+
+    pub fn federation_mut(&mut self) -> Option<&mut Federation> {
+        self.federation.as_mut()
+    }
+
+    // Linked servers learn about the local users right away
+    pub fn set_federation(&mut self, mut federation: Federation) {
+        federation.set_local_users(self.usernames());
+        self.federation = Some(federation);
+    }
+
+    #[cfg(feature = "webhooks")]
+    pub fn webhooks(&self) -> Option<&WebhookDispatcher> {
+        self.webhooks.as_ref()
+    }
+
+    #[cfg(feature = "webhooks")]
+    pub fn set_webhooks(&mut self, webhooks: WebhookDispatcher) {
+        self.webhooks = Some(webhooks);
+    }
+
+    pub fn sessions(&self) -> impl Iterator<Item = (u64, &Session)> {
+        self.sessions.iter().map(|(id, session)| (*id, session))
+    }
+
+    // Each user once, however many devices they are logged in from
+    pub fn usernames(&self) -> Vec<String> {
+        let mut session_ids: Vec<u64> = self.sessions.keys().copied().collect();
+        session_ids.sort();
+
+        // The oldest session's spelling wins
+        let mut usernames_by_canonical: HashMap<String, String> = HashMap::new();
+        for session_id in session_ids {
+            let username = &self.sessions[&session_id].username;
+            usernames_by_canonical
+                .entry(self.username_policy.canonicalize(username))
+                .or_insert_with(|| username.clone());
+        }
+
+        let mut usernames: Vec<String> = usernames_by_canonical.into_values().collect();
+        usernames.sort();
+
+        usernames
+    }
+
+    // Sorted by session id, so the oldest session comes first
+    pub fn user_sessions(&self, username: &str) -> Vec<(u64, &Session)> {
+        let mut session_ids = self.sessions_of(username);
+        session_ids.sort();
+
+        session_ids
+            .into_iter()
+            .map(|session_id| (session_id, &self.sessions[&session_id]))
+            .collect()
+    }
+
+    // Registered users may join from several devices, everyone else gets one session per username.
+    // The others only hear about the first session joining.
+    pub fn join(
+        &mut self,
+        username: String,
+        device: Option<String>,
+        address: Option<IpAddr>,
+        sender: Sender<Message>,
+    ) -> Result<u64, ServerStateError> {
+        let signed_in = !self.sessions_of(&username).is_empty();
+        if signed_in && !self.is_registered(&username) {
+            return Err(ServerStateError::UsernameError(UsernameError::Taken(
+                username,
+            )));
+        }
+
+        // Plugins hear about users, not devices, just like everyone else
+        let mut outgoing = Vec::new();
+        if !signed_in {
+            self.broadcast(&UserJoined::new(username.clone()).to_message());
+            #[cfg(feature = "webhooks")]
+            self.dispatch_webhook(WebhookEvent::UserJoined(username.clone()));
+            outgoing = self.plugins.handshake_completed(&username);
+        }
+
+        // Messages that came in while the user was offline go out right after the handshake
+        for queued in self.offline_queue.take(&self.username_policy, &username) {
+            let _ = sender.send(queued.to_message());
+        }
+
+        let session_id = self.next_session_id;
+        self.next_session_id += 1;
+        self.sessions.insert(
+            session_id,
+            Session {
+                username,
+                device,
+                address,
+                connected_at: Instant::now(),
+                sender,
+                rate_limit: self.rate_limiter.new_session(),
+                public_key: None,
+            },
+        );
+        // Sent once the session is in, so plugins can greet the user who just joined
+        self.deliver(outgoing);
+        if !signed_in {
+            self.update_federation();
+        }
+
+        Ok(session_id)
+    }
+
+    pub fn leave(&mut self, session_id: u64) -> Option<Session> {
+        let session = self.sessions.remove(&session_id)?;
+        if self.sessions_of(&session.username).is_empty() {
+            self.broadcast(&UserLeft::new(session.username.clone()).to_message());
+            #[cfg(feature = "webhooks")]
+            self.dispatch_webhook(WebhookEvent::UserLeft(session.username.clone()));
+
+            let outgoing = self.plugins.user_left(&session.username);
+            self.deliver(outgoing);
+            self.update_federation();
+        }
+
+        Some(session)
+    }
+
+    // Skips the mute check, for announcements from trusted callers like the admin API
+    pub fn relay(&mut self, chat: Chat) {
+        self.messages_relayed += 1;
+        #[cfg(feature = "webhooks")]
+        self.dispatch_webhook(WebhookEvent::Chat(chat.clone()));
+        self.broadcast(&chat.to_message());
+    }
+
+    // Muted and rate limited sessions are turned away before anyone sees the chat,
+    // after that the plugins may change or drop it.
+    // Returns the Ack for the sender when the chat asked for one.
+    pub fn relay_chat(&mut self, session_id: u64, chat: client::Chat) -> Option<Ack> {
+        let username = self.sessions.get(&session_id)?.username.clone();
+
+        let rejection = match self.moderation.is_muted(&self.username_policy, &username) {
+            true => Some(Rejection::Muted),
+            false => self.rate_limit(session_id, chat.message.len()).err(),
+        };
+
+        let rejection =
+            rejection.or_else(|| match self.filter(Chat::new(username, chat.message)) {
+                Some(chat) => {
+                    if let Some(federation) = &mut self.federation {
+                        federation.relay_local(&chat);
+                    }
+                    self.relay(chat);
+                    None
+                }
+                None => Some(Rejection::Filtered),
+            });
+
+        chat.nonce.map(|nonce| Ack::new(nonce, rejection))
+    }
+
+    // Takes what Federation::receive and remove_link have for local users.
+    // Remote chats pass the same mute and plugin checks as local ones, their own server rate
+    // limited them already.
+    pub fn relay_federated(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            let chat = match message {
+                Message::Server(server_message::Message::Chat(chat)) => chat,
+                message => {
+                    self.broadcast(&message);
+                    continue;
+                }
+            };
+
+            if self
+                .moderation
+                .is_muted(&self.username_policy, &chat.username)
+            {
+                continue;
+            }
+            if let Some(chat) = self.filter(chat) {
+                self.relay(chat);
+            }
+        }
+    }
+
+    // Listeners call this periodically, so plugins get to run on their own schedule
+    pub fn tick(&mut self, now: Instant) {
+        let outgoing = self.plugins.tick(now);
+        self.deliver(outgoing);
+    }
+
+    pub fn broadcast(&mut self, message: &Message) {
+        // A dropped receiver means the connection is gone, its thread calls leave on the way out
+        for session in self.sessions.values() {
+            let _ = session.sender.send(message.clone());
+        }
+    }
+
+    pub fn send_to(&self, username: &str, message: &Message) -> bool {
+        let session_ids = self.sessions_of(username);
+        for session_id in &session_ids {
+            let _ = self.sessions[session_id].sender.send(message.clone());
+        }
+
+        !session_ids.is_empty()
+    }
+
+    // Encrypted messages reach every device of the recipient, so they all share one identity.
+    // A key that differs from the one the user's other sessions published is refused.
+    // Returns whether the key was accepted.
+    pub fn publish_key(&mut self, session_id: u64, publish_key: &PublishKey) -> bool {
+        let username = self.username_of(session_id);
+        let conflicting = self
+            .user_sessions(&username)
+            .into_iter()
+            .filter(|(other_id, _)| *other_id != session_id)
+            .filter_map(|(_, session)| session.public_key)
+            .any(|public_key| public_key != publish_key.public_key);
+
+        match self.sessions.get_mut(&session_id) {
+            Some(session) if !conflicting => {
+                session.public_key = Some(publish_key.public_key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // The key stays available as long as any session that published it is connected
+    pub fn request_key(&self, request_key: &RequestKey) -> PublicKey {
+        let public_key = self
+            .user_sessions(&request_key.username)
+            .into_iter()
+            .find_map(|(_, session)| session.public_key);
+
+        PublicKey::new(request_key.username.clone(), public_key)
+    }
+
+    // Registered usernames can't be claimed by someone else while their owner is offline
+    pub fn is_registered(&self, username: &str) -> bool {
+        self.authorized_keys
+            .requires_key(&self.username_policy, username)
+            || self
+                .peer_credentials
+                .requires_uid(&self.username_policy, username)
+    }
+
+    // The payload is opaque here, it's passed on with the sender filled in. Registered users
+    // get it queued while offline, muted senders get it dropped.
+    // Returns the status to send back to the sender.
+    pub fn relay_encrypted(
+        &mut self,
+        sender: &str,
+        encrypted_message: client::EncryptedMessage,
+    ) -> DeliveryStatus {
+        let recipient = encrypted_message.recipient;
+        if self.moderation.is_muted(&self.username_policy, sender) {
+            return DeliveryStatus::new(recipient, Delivery::Dropped);
+        }
+
+        let mut relayed = server::EncryptedMessage::new(
+            sender.to_owned(),
+            None,
+            encrypted_message.receipt_id,
+            encrypted_message.payload,
+        );
+
+        if self.send_to(&recipient, &relayed.clone().to_message()) {
+            return DeliveryStatus::new(recipient, Delivery::Live);
+        }
+        if !self.is_registered(&recipient) {
+            return DeliveryStatus::new(recipient, Delivery::Dropped);
+        }
+
+        relayed.queued_at = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+        );
+        match self
+            .offline_queue
+            .push(&self.username_policy, &recipient, relayed)
+        {
+            true => DeliveryStatus::new(recipient, Delivery::Queued),
+            false => DeliveryStatus::new(recipient, Delivery::Dropped),
+        }
+    }
+
+    // Receipts only reach a sender who is online, there's nothing to queue them for
+    pub fn relay_read_receipt(&self, reader: &str, read_receipt: client::ReadReceipt) -> bool {
+        let relayed = server::ReadReceipt::new(reader.to_owned(), read_receipt.receipt_id);
+
+        self.send_to(&read_receipt.sender, &relayed.to_message())
+    }
+
+    // The plain text counterpart of relay_encrypted for /msg, nothing is queued for offline users.
+    // Returns whether the recipient was online.
+    pub fn send_private_message(
+        &self,
+        sender: &str,
+        recipient: &str,
+        message: String,
+    ) -> Result<bool, Rejection> {
+        if self.moderation.is_muted(&self.username_policy, sender) {
+            return Err(Rejection::Muted);
+        }
+
+        let private_message = PrivateMessage::new(sender.to_owned(), message);
+        Ok(self.send_to(recipient, &private_message.to_message()))
+    }
+
+    // Muted users can't share files, just like they can't chat
+    pub fn offer_file(
+        &mut self,
+        username: &str,
+        offer_file: &OfferFile,
+    ) -> Result<UploadReady, ServerStateError> {
+        if self.moderation.is_muted(&self.username_policy, username) {
+            return Err(ServerStateError::Rejected(Rejection::Muted));
+        }
+
+        self.file_store
+            .as_mut()
+            .ok_or(ServerStateError::FileSharingDisabled)?
+            .offer(&self.username_policy, username, offer_file)
+            .map_err(ServerStateError::FileTransferError)
+    }
+
+    // Everyone is sent the Attachment once the last chunk is in. Returns whether the upload is done.
+    pub fn write_file_chunk(
+        &mut self,
+        username: &str,
+        file_chunk: &client::FileChunk,
+    ) -> Result<bool, ServerStateError> {
+        let attachment = self
+            .file_store
+            .as_mut()
+            .ok_or(ServerStateError::FileSharingDisabled)?
+            .write_chunk(&self.username_policy, username, file_chunk)
+            .map_err(ServerStateError::FileTransferError)?;
+
+        let attachment = match attachment {
+            Some(attachment) => attachment,
+            None => return Ok(false),
+        };
+
+        self.messages_relayed += 1;
+        self.broadcast(&attachment.to_message());
+        Ok(true)
+    }
+
+    pub fn read_file_chunk(
+        &mut self,
+        request_chunk: &RequestChunk,
+    ) -> Result<server::FileChunk, ServerStateError> {
+        self.file_store
+            .as_mut()
+            .ok_or(ServerStateError::FileSharingDisabled)?
+            .read_chunk(request_chunk)
+            .map_err(ServerStateError::FileTransferError)
+    }
+
+    // Users can only revoke their own sessions. Returns whether the session was ended.
+    pub fn revoke_session(&mut self, username: &str, session_id: u64) -> bool {
+        if !self.sessions_of(username).contains(&session_id) {
+            return false;
+        }
+
+        self.end_sessions(&[session_id], End::new(EndReason::SessionRevoked, None));
+        true
+    }
+
+    // Every moderation packet from a client goes through here, so nothing takes effect before the
+    // sender's permission is checked. Other messages aren't moderation and are left alone.
+    pub fn moderate(
+        &mut self,
+        username: &str,
+        message: client_message::Message,
+    ) -> Result<(), ServerStateError> {
+        if self
+            .permissions
+            .authorize(&self.username_policy, username, &message)
+            .is_err()
+        {
+            return Err(ServerStateError::Rejected(Rejection::NotPermitted));
+        }
+
+        match message {
+            client_message::Message::Kick(kick) => {
+                self.kick(&kick);
+            }
+            client_message::Message::Ban(ban) => {
+                self.ban(ban).map_err(ServerStateError::ModerationError)?;
+            }
+            client_message::Message::Unban(unban) => {
+                self.moderation
+                    .unban(&self.username_policy, &unban)
+                    .map_err(ServerStateError::ModerationError)?;
+            }
+            client_message::Message::Mute(mute) => {
+                self.moderation.mute(&self.username_policy, mute)
+            }
+            client_message::Message::AssignRole(assign_role) => self
+                .permissions
+                .assign_role(&self.username_policy, &assign_role)
+                .map_err(ServerStateError::PermissionError)?,
+            client_message::Message::RevokeRole(revoke_role) => {
+                self.permissions
+                    .revoke_role(&self.username_policy, &revoke_role)
+                    .map_err(ServerStateError::PermissionError)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Skips the permission check, for callers that are trusted already like the admin API.
+    // Returns whether anyone was kicked.
+    pub fn kick(&mut self, kick: &Kick) -> bool {
+        let end = self.moderation.kick(kick);
+        let session_ids = self.sessions_of(&kick.username);
+
+        self.end_sessions(&session_ids, end);
+        !session_ids.is_empty()
+    }
+
+    // Skips the permission check like kick. Returns the number of sessions the ban closed.
+    pub fn ban(&mut self, ban: Ban) -> Result<usize, ModerationError> {
+        let session_ids = match &ban.target {
+            BanTarget::Username(username) => self.sessions_of(username),
+            BanTarget::Address(address) => self
+                .sessions
+                .iter()
+                .filter(|(_, session)| session.address == Some(*address))
+                .map(|(session_id, _)| *session_id)
+                .collect(),
+        };
+
+        let end = self.moderation.ban(&self.username_policy, ban)?;
+        self.end_sessions(&session_ids, end);
+
+        Ok(session_ids.len())
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        ServerStats {
+            connected_users: self.usernames().len(),
+            messages_relayed: self.messages_relayed,
+            active_bans: self.moderation.bans().len(),
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+        }
+    }
+
+    // Empty for sessions that are gone, which match no user
+    fn username_of(&self, session_id: u64) -> String {
+        self.sessions
+            .get(&session_id)
+            .map(|session| session.username.clone())
+            .unwrap_or_default()
+    }
+
+    fn sessions_of(&self, username: &str) -> Vec<u64> {
+        let canonical_username = self.username_policy.canonicalize(username);
+
+        self.sessions
+            .iter()
+            .filter(|(_, session)| {
+                self.username_policy.canonicalize(&session.username) == canonical_username
+            })
+            .map(|(session_id, _)| *session_id)
+            .collect()
+    }
+
+    // Runs the plugins, None when one of them dropped the chat
+    fn filter(&mut self, chat: Chat) -> Option<Chat> {
+        let outcome = self.plugins.chat(chat);
+        self.deliver(outcome.outgoing);
+
+        outcome.chat
+    }
+
+    // Only ever called with what was actually relayed, after the plugins had their say
+    #[cfg(feature = "webhooks")]
+    fn dispatch_webhook(&self, event: WebhookEvent) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.dispatch(&event);
+        }
+    }
+
+    fn update_federation(&mut self) {
+        let usernames = self.usernames();
+        if let Some(federation) = &mut self.federation {
+            federation.set_local_users(usernames);
+        }
+    }
+
+    fn deliver(&mut self, outgoing: Vec<Outgoing>) {
+        for Outgoing { recipient, message } in outgoing {
+            match recipient {
+                Recipient::Everyone => self.broadcast(&message),
+                Recipient::User(username) => {
+                    self.send_to(&username, &message);
+                }
+            }
+        }
+    }
+
+    // The penalty's warning or End goes to the session right away, a disconnected session is gone
+    fn rate_limit(&mut self, session_id: u64, size: usize) -> Result<(), Rejection> {
+        let session = match self.sessions.get_mut(&session_id) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let penalty =
+            match self
+                .rate_limiter
+                .check_message(&mut session.rate_limit, session.address, size)
+            {
+                Ok(()) => return Ok(()),
+                Err(penalty) => penalty,
+            };
+
+        if let Some(response) = penalty.response() {
+            let _ = session.sender.send(response);
+        }
+        if penalty == Penalty::Disconnect {
+            self.leave(session_id);
+        }
+
+        Err(Rejection::RateLimited)
+    }
+
+    fn end_sessions(&mut self, session_ids: &[u64], end: End) {
+        for session_id in session_ids {
+            if let Some(session) = self.sessions.get(session_id) {
+                let _ = session.sender.send(end.clone().to_message());
+            }
+            self.leave(*session_id);
+        }
+    }
+}
+
+// Runs the server-side built-in commands on behalf of one session.
+// The state is locked for each call only, so the registry can outlive any one lock.
+#[derive(Debug)]
+pub struct CommandCaller<'a> {
+    state: &'a Mutex<ServerState>,
+    session_id: u64,
+}
+
+impl<'a> CommandCaller<'a> {
+    pub fn new(state: &'a Mutex<ServerState>, session_id: u64) -> CommandCaller<'a> {
+        CommandCaller { state, session_id }
+    }
+
+    fn state(&self) -> MutexGuard<'a, ServerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ServerCommandContext for CommandCaller<'_> {
+    fn sessions(&self) -> Vec<String> {
+        let state = self.state();
+
+        state
+            .user_sessions(&state.username_of(self.session_id))
+            .into_iter()
+            .map(
+                |(session_id, session)| match session_id == self.session_id {
+                    true => format!("{}: {} (this device)", session_id, session),
+                    false => format!("{}: {}", session_id, session),
+                },
+            )
+            .collect()
+    }
+
+    fn revoke_session(&mut self, session_id: u64) -> Result<(), String> {
+        let mut state = self.state();
+        let username = state.username_of(self.session_id);
+
+        match state.revoke_session(&username, session_id) {
+            true => Ok(()),
+            false => Err(format!("You have no session {}", session_id)),
+        }
+    }
+
+    fn send_private_message(&mut self, recipient: &str, message: String) -> Result<(), String> {
+        let state = self.state();
+        let sender = state.username_of(self.session_id);
+
+        match state.send_private_message(&sender, recipient, message) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("No such user: {}", recipient)),
+            Err(rejection) => Err(rejection.to_string()),
+        }
+    }
+
+    fn usernames(&self) -> Vec<String> {
+        self.state().usernames()
+    }
+
+    fn whois(&self, username: &str) -> Vec<String> {
+        self.state()
+            .user_sessions(username)
+            .into_iter()
+            .map(|(session_id, session)| match session.address {
+                Some(address) => format!("{}: {} from {}", session_id, session, address),
+                None => format!("{}: {} from an unknown address", session_id, session),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::{self, Receiver};
+
+    use crate::common::{
+        authorized_keys::AuthorizedKey,
+        federation::message::{LinkMessage, RelayedChat},
+        peer_credentials::LocalUser,
+        plugin::{ChatVerdict, Plugin, PluginContext},
+        protocol::packet::{
+            client::{AssignRole, Mute},
+            Permission, PermissionSet, Role,
+        },
+        rate_limit::RateLimit,
+    };
+
+    fn state() -> ServerState {
+        ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )
+    }
+
+    fn join(state: &mut ServerState, username: &str) -> (u64, Receiver<Message>) {
+        join_from(state, username, None)
+    }
+
+    fn join_from(
+        state: &mut ServerState,
+        username: &str,
+        device: Option<&str>,
+    ) -> (u64, Receiver<Message>) {
+        let (sender, receiver) = mpsc::channel();
+        let session_id = state
+            .join(username.to_owned(), device.map(str::to_owned), None, sender)
+            .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+
+        (session_id, receiver)
+    }
+
+    #[test]
+    fn server_state_announces_joins_and_rejects_taken_usernames() {
+        let mut state = state();
+        let (_, alice) = join(&mut state, "Alice");
+        let (bob_id, _bob) = join(&mut state, "Bob");
+
+        assert_eq!(
+            alice.try_recv().ok(),
+            Some(UserJoined::new(String::from("Bob")).to_message())
+        );
+
+        let (sender, _) = mpsc::channel();
+        match state.join(String::from("ALICE"), None, None, sender) {
+            Err(ServerStateError::UsernameError(UsernameError::Taken(username))) => {
+                assert_eq!(username, "ALICE")
+            }
+            other => panic!("Taken username was not rejected: {:?}", other),
+        }
+
+        state.leave(bob_id);
+        assert_eq!(
+            alice.try_recv().ok(),
+            Some(UserLeft::new(String::from("Bob")).to_message())
+        );
+        assert_eq!(state.usernames(), vec![String::from("Alice")]);
+    }
+
+    #[test]
+    fn server_state_fans_out_to_every_device_of_registered_users() {
+        let mut state = state();
+        state
+            .peer_credentials_mut()
+            .allow(LocalUser::new(1000, String::from("bob")));
+        let (_, alice) = join(&mut state, "Alice");
+        let (laptop_id, laptop) = join_from(&mut state, "Bob", Some("Laptop"));
+        let (phone_id, phone) = join_from(&mut state, "bob", Some("Phone"));
+
+        assert_eq!(
+            alice.try_iter().collect::<Vec<_>>(),
+            vec![UserJoined::new(String::from("Bob")).to_message()]
+        );
+        assert_eq!(
+            state.usernames(),
+            vec![String::from("Alice"), String::from("Bob")]
+        );
+        assert_eq!(state.stats().connected_users, 2);
+
+        let devices: Vec<(u64, Option<&str>)> = state
+            .user_sessions("BOB")
+            .into_iter()
+            .map(|(session_id, session)| (session_id, session.device.as_deref()))
+            .collect();
+        assert_eq!(
+            devices,
+            vec![(laptop_id, Some("Laptop")), (phone_id, Some("Phone"))]
+        );
+
+        let chat = Chat::new(String::from("Alice"), String::from("Hi")).to_message();
+        assert!(state.send_to("Bob", &chat));
+        assert_eq!(laptop.try_iter().last(), Some(chat.clone()));
+        assert_eq!(phone.try_iter().last(), Some(chat));
+
+        assert!(!state.revoke_session("Alice", phone_id));
+        assert!(state.revoke_session("Bob", phone_id));
+        assert_eq!(
+            phone.try_recv().ok(),
+            Some(End::new(EndReason::SessionRevoked, None).to_message())
+        );
+        assert_eq!(alice.try_recv().ok(), None);
+
+        state.leave(laptop_id);
+        assert_eq!(
+            alice.try_recv().ok(),
+            Some(UserLeft::new(String::from("Bob")).to_message())
+        );
+    }
+
+    #[test]
+    fn server_state_serves_keys_and_relays_encrypted_messages() {
+        let mut state = state();
+        let (alice_id, alice) = join(&mut state, "Alice");
+        let (_, bob) = join(&mut state, "Bob");
+
+        assert!(state.publish_key(alice_id, &PublishKey::new([7; 32])));
+        assert_eq!(
+            state.request_key(&RequestKey::new(String::from("alice"))),
+            PublicKey::new(String::from("alice"), Some([7; 32]))
+        );
+
+        assert_eq!(
+            state.relay_encrypted(
+                "Alice",
+                client::EncryptedMessage::new(String::from("Bob"), Some(9), vec![1, 2, 3])
+            ),
+            DeliveryStatus::new(String::from("Bob"), Delivery::Live)
+        );
+        assert_eq!(
+            bob.try_iter().last(),
+            Some(
+                server::EncryptedMessage::new(String::from("Alice"), None, Some(9), vec![1, 2, 3])
+                    .to_message()
+            )
+        );
+
+        let read_receipt = client::ReadReceipt::new(String::from("alice"), 9);
+        assert!(state.relay_read_receipt("Bob", read_receipt));
+        assert_eq!(
+            alice.try_iter().last(),
+            Some(server::ReadReceipt::new(String::from("Bob"), 9).to_message())
+        );
+
+        state.leave(alice_id);
+        assert_eq!(
+            state.request_key(&RequestKey::new(String::from("Alice"))),
+            PublicKey::new(String::from("Alice"), None)
+        );
+    }
+
+    #[test]
+    fn server_state_keeps_one_key_across_devices() {
+        let mut state = state();
+        state
+            .peer_credentials_mut()
+            .allow(LocalUser::new(1000, String::from("alice")));
+        let (laptop_id, _laptop) = join_from(&mut state, "Alice", Some("Laptop"));
+        let (phone_id, _phone) = join_from(&mut state, "Alice", Some("Phone"));
+        let request_key = || RequestKey::new(String::from("Alice"));
+
+        assert!(state.publish_key(laptop_id, &PublishKey::new([7; 32])));
+        assert!(!state.publish_key(phone_id, &PublishKey::new([8; 32])));
+        assert!(state.publish_key(phone_id, &PublishKey::new([7; 32])));
+
+        state.leave(laptop_id);
+        assert_eq!(
+            state.request_key(&request_key()),
+            PublicKey::new(String::from("Alice"), Some([7; 32]))
+        );
+
+        // The last device may rotate the key, there's no one left to disagree
+        assert!(state.publish_key(phone_id, &PublishKey::new([8; 32])));
+        state.leave(phone_id);
+        assert_eq!(
+            state.request_key(&request_key()),
+            PublicKey::new(String::from("Alice"), None)
+        );
+    }
+
+    #[test]
+    fn server_state_queues_encrypted_messages_for_offline_users() {
+        let mut state = state();
+        state
+            .authorized_keys_mut()
+            .add(AuthorizedKey::new(
+                String::from("bob"),
+                ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+                    .verifying_key()
+                    .to_bytes(),
+                None,
+            ))
+            .unwrap_or_else(|err| panic!("Failed to add key: {}", err));
+        state.offline_queue_mut().set_max_per_user(1);
+        let (_alice_id, _alice) = join(&mut state, "Alice");
+
+        let relay = |state: &mut ServerState, recipient: &str| {
+            state
+                .relay_encrypted(
+                    "Alice",
+                    client::EncryptedMessage::new(recipient.to_owned(), None, vec![1, 2, 3]),
+                )
+                .delivery
+        };
+        assert_eq!(relay(&mut state, "Bob"), Delivery::Queued);
+        assert_eq!(relay(&mut state, "Bob"), Delivery::Dropped);
+        assert_eq!(relay(&mut state, "Carol"), Delivery::Dropped);
+
+        let (_, bob) = join(&mut state, "Bob");
+        let queued = match bob.try_recv() {
+            Ok(Message::Server(server_message::Message::EncryptedMessage(queued))) => queued,
+            message => panic!("Expected the queued message, got {:?}", message),
+        };
+        assert_eq!(queued.sender, "Alice");
+        assert!(queued.queued_at.is_some());
+        assert_eq!(
+            state.offline_queue().len_of(state.username_policy(), "bob"),
+            0
+        );
+        assert_eq!(relay(&mut state, "Bob"), Delivery::Live);
+    }
+
+    #[test]
+    fn server_state_acks_chats_with_nonces() {
+        let mut state = state();
+        let (alice_id, alice) = join(&mut state, "Alice");
+        let (troll_id, _troll) = join(&mut state, "Troll");
+        let _ = alice.try_iter().count();
+
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(None, String::from("Hi"))),
+            None
+        );
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(Some(1), String::from("Hi"))),
+            Some(Ack::new(1, None))
+        );
+        assert_eq!(alice.try_iter().count(), 2);
+
+        let policy = state.username_policy().clone();
+        state
+            .moderation_mut()
+            .mute(&policy, client::Mute::new(String::from("troll"), None));
+        assert_eq!(
+            state.relay_chat(troll_id, client::Chat::new(Some(2), String::from("spam"))),
+            Some(Ack::new(2, Some(Rejection::Muted)))
+        );
+        assert_eq!(alice.try_recv().ok(), None);
+        assert_eq!(state.stats().messages_relayed, 2);
+    }
+
+    #[test]
+    fn server_state_rate_limits_chats() {
+        let mut state = state();
+        state.set_rate_limiter(RateLimiter::new(RateLimitConfig {
+            session_messages: RateLimit::new(1, 1),
+            ..RateLimitConfig::new()
+        }));
+        let address = IpAddr::from([192, 0, 2, 1]);
+
+        let (sender, alice) = mpsc::channel();
+        let alice_id = state
+            .join(String::from("Alice"), None, Some(address), sender)
+            .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(Some(1), String::from("Hi"))),
+            Some(Ack::new(1, None))
+        );
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(Some(2), String::from("Hi"))),
+            Some(Ack::new(2, Some(Rejection::RateLimited)))
+        );
+        assert!(matches!(
+            alice.try_iter().last(),
+            Some(Message::Server(server_message::Message::Warning(_)))
+        ));
+        assert_eq!(state.stats().messages_relayed, 1);
+        assert_eq!(state.rate_limiter().metrics().warned, 1);
+    }
+
+    struct Bouncer;
+
+    impl Plugin for Bouncer {
+        fn name(&self) -> &str {
+            "bouncer"
+        }
+
+        fn on_handshake_completed(&mut self, context: &mut PluginContext, username: &str) {
+            context.say(Recipient::User(username.to_owned()), "bouncer", "No spam");
+        }
+
+        fn on_chat(&mut self, _context: &mut PluginContext, chat: &mut Chat) -> ChatVerdict {
+            match chat.message.contains("spam") {
+                true => ChatVerdict::Drop,
+                false => ChatVerdict::Relay,
+            }
+        }
+    }
+
+    #[test]
+    fn server_state_runs_plugins() {
+        let mut state = state();
+        state.plugins_mut().register(Box::new(Bouncer));
+        let (alice_id, alice) = join(&mut state, "Alice");
+
+        let greeting = Chat::new(String::from("bouncer"), String::from("No spam")).to_message();
+        assert_eq!(alice.try_iter().last(), Some(greeting));
+
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(Some(1), String::from("Hi"))),
+            Some(Ack::new(1, None))
+        );
+        assert_eq!(
+            state.relay_chat(alice_id, client::Chat::new(Some(2), String::from("spam"))),
+            Some(Ack::new(2, Some(Rejection::Filtered)))
+        );
+        assert_eq!(
+            alice.try_iter().collect::<Vec<_>>(),
+            vec![Chat::new(String::from("Alice"), String::from("Hi")).to_message()]
+        );
+    }
+
+    #[test]
+    fn server_state_enforces_mutes_and_kicks() {
+        let mut state = state();
+        let (_, alice) = join(&mut state, "Alice");
+        let (troll_id, troll) = join(&mut state, "Troll");
+        let _ = alice.try_iter().count();
+
+        let policy = state.username_policy().clone();
+        state
+            .moderation_mut()
+            .mute(&policy, client::Mute::new(String::from("troll"), None));
+        assert_eq!(
+            state.relay_chat(troll_id, client::Chat::new(None, String::from("spam"))),
+            None
+        );
+        assert_eq!(
+            state
+                .relay_encrypted(
+                    "Troll",
+                    client::EncryptedMessage::new(String::from("Alice"), None, vec![1, 2, 3])
+                )
+                .delivery,
+            Delivery::Dropped
+        );
+        assert_eq!(alice.try_recv().ok(), None);
+
+        assert!(state.kick(&Kick::new(
+            String::from("TROLL"),
+            Some(String::from("Spam"))
+        )));
+        assert_eq!(
+            troll.try_iter().last(),
+            Some(End::new(EndReason::Kicked, Some(String::from("Spam"))).to_message())
+        );
+        assert_eq!(state.usernames(), vec![String::from("Alice")]);
+    }
+
+    #[test]
+    fn server_state_refuses_moderation_without_permission() {
+        let mut state = state();
+        *state.permissions_mut() = Permissions::new(vec![Role::new(
+            String::from("moderator"),
+            PermissionSet::new(&[Permission::Kick]),
+        )]);
+        let policy = state.username_policy().clone();
+        state
+            .permissions_mut()
+            .assign_role(
+                &policy,
+                &AssignRole::new(String::from("Kitt3120"), String::from("moderator")),
+            )
+            .unwrap_or_else(|err| panic!("Failed to assign role: {}", err));
+        let (_, _alice) = join(&mut state, "Alice");
+        let (_, troll) = join(&mut state, "Troll");
+        let _ = troll.try_iter().count();
+
+        let kick = || client_message::Message::Kick(Kick::new(String::from("alice"), None));
+        assert!(matches!(
+            state.moderate("Troll", kick()),
+            Err(ServerStateError::Rejected(Rejection::NotPermitted))
+        ));
+        assert_eq!(state.stats().connected_users, 2);
+        assert_eq!(troll.try_recv().ok(), None);
+
+        state
+            .moderate("kitt3120", kick())
+            .unwrap_or_else(|err| panic!("Failed to kick: {}", err));
+        assert_eq!(state.usernames(), vec![String::from("Troll")]);
+    }
+
+    #[test]
+    fn server_state_ends_banned_sessions() {
+        let mut state = state();
+        let (_, troll) = join(&mut state, "Troll");
+
+        let closed = state
+            .ban(Ban::new(
+                BanTarget::Username(String::from("troll")),
+                None,
+                Some(String::from("Spam")),
+            ))
+            .unwrap_or_else(|err| panic!("Failed to ban: {}", err));
+
+        assert_eq!(closed, 1);
+        assert_eq!(
+            troll.try_recv().ok(),
+            Some(End::new(EndReason::Banned, Some(String::from("Spam"))).to_message())
+        );
+        assert_eq!(state.stats().connected_users, 0);
+        assert_eq!(state.stats().active_bans, 1);
+    }
+
+    #[test]
+    fn server_state_relays_through_federation() {
+        let mut state = state();
+        state.set_federation(Federation::new(String::from("berlin")));
+        let (sender, paris) = mpsc::channel();
+        let link_id = state
+            .federation_mut()
+            .map(|federation| federation.add_link(String::from("paris"), sender))
+            .unwrap_or_else(|| panic!("Federation is not set"))
+            .unwrap_or_else(|err| panic!("Failed to link: {}", err));
+
+        let (alice_id, alice) = join(&mut state, "Alice");
+        assert!(matches!(
+            paris.try_iter().last(),
+            Some(LinkMessage::Presence(presence)) if presence.users == vec![String::from("Alice")]
+        ));
+
+        state.relay_chat(alice_id, client::Chat::new(None, String::from("Hi Paris")));
+        assert!(matches!(
+            paris.try_iter().last(),
+            Some(LinkMessage::Chat(chat)) if chat.username == "Alice" && chat.message == "Hi Paris"
+        ));
+
+        let receive = |state: &mut ServerState, sequence, message: &str| {
+            let relayed_chat = LinkMessage::Chat(RelayedChat::new(
+                String::from("paris"),
+                1,
+                sequence,
+                vec![String::from("paris")],
+                String::from("bob"),
+                message.to_owned(),
+            ));
+            let messages = state
+                .federation_mut()
+                .map(|federation| federation.receive(link_id, relayed_chat))
+                .unwrap_or_else(|| panic!("Federation is not set"))
+                .unwrap_or_else(|err| panic!("Failed to receive: {}", err));
+            state.relay_federated(messages);
+        };
+
+        receive(&mut state, 0, "Hi Berlin");
+        assert_eq!(
+            alice.try_iter().last(),
+            Some(Chat::new(String::from("bob@paris"), String::from("Hi Berlin")).to_message())
+        );
+        assert_eq!(state.stats().messages_relayed, 2);
+
+        state.moderation_mut().mute(
+            &UsernamePolicy::new(),
+            Mute::new(String::from("bob@paris"), None),
+        );
+        receive(&mut state, 1, "Still here");
+        assert_eq!(alice.try_iter().last(), None);
+
+        state.leave(alice_id);
+        assert!(matches!(
+            paris.try_iter().last(),
+            Some(LinkMessage::Presence(presence)) if presence.users.is_empty()
+        ));
+    }
+}
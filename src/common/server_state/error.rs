@@ -0,0 +1,43 @@
+use std::{fmt::Display, io::Error};
+
+use crate::common::{
+    file_transfer::FileTransferError,
+    message_stream::error::MessageStreamError,
+    moderation::error::ModerationError,
+    permissions::error::PermissionError,
+    protocol::{
+        error::{HandshakeError, UsernameError},
+        packet::Rejection,
+    },
+};
+
+#[derive(Debug)]
+pub enum ServerStateError {
+    IoError(Error),
+    Rejected(Rejection),
+    UsernameError(UsernameError),
+    ModerationError(ModerationError),
+    PermissionError(PermissionError),
+    HandshakeError(HandshakeError),
+    MessageStreamError(MessageStreamError),
+    FileTransferError(FileTransferError),
+    FileSharingDisabled,
+}
+
+impl Display for ServerStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ServerStateError::IoError(e) => write!(f, "IoError while serving sessions: {}", e),
+            ServerStateError::Rejected(rejection) => write!(f, "Rejected: {}", rejection),
+            ServerStateError::UsernameError(e) => write!(f, "UsernameError: {}", e),
+            ServerStateError::ModerationError(e) => write!(f, "ModerationError: {}", e),
+            ServerStateError::PermissionError(e) => write!(f, "PermissionError: {}", e),
+            ServerStateError::HandshakeError(e) => write!(f, "HandshakeError: {}", e),
+            ServerStateError::MessageStreamError(e) => write!(f, "MessageStreamError: {}", e),
+            ServerStateError::FileTransferError(e) => write!(f, "FileTransferError: {}", e),
+            ServerStateError::FileSharingDisabled => {
+                write!(f, "File sharing is not enabled on this server")
+            }
+        }
+    }
+}
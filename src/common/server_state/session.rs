@@ -0,0 +1,521 @@
+use std::{
+    net::TcpListener,
+    ops::ControlFlow,
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+};
+
+use super::{error::ServerStateError, CommandCaller, ServerState};
+
+use crate::common::{
+    command::CommandRegistry,
+    message_stream::{MessageStream, SplitTransport},
+    protocol::{
+        message::{client as client_message, Message},
+        packet::{
+            server::{End, Warning},
+            EndReason, Packet, Rejection,
+        },
+        serializable::Serializable,
+    },
+};
+
+impl ServerState {
+    // Serves every connection on its own thread until the listener fails
+    pub fn serve(
+        state: &Arc<Mutex<ServerState>>,
+        listener: TcpListener,
+    ) -> Result<(), ServerStateError> {
+        for tcp_stream in listener.incoming() {
+            let tcp_stream = tcp_stream.map_err(ServerStateError::IoError)?;
+            let state = state.clone();
+
+            thread::spawn(move || {
+                ServerState::serve_session(&state, MessageStream::new(tcp_stream))
+            });
+        }
+
+        Ok(())
+    }
+
+    // Handshake, join, then every packet the client sends until it quits or is ended from elsewhere.
+    // The session's messages go out on a writer thread, so the reader never waits for a slow socket.
+    pub fn serve_session<T: SplitTransport + Send + 'static>(
+        state: &Mutex<ServerState>,
+        mut transport: T,
+    ) -> Result<(), ServerStateError> {
+        // An address that connects too often is turned away before the handshake costs anything
+        if let Some(address) = transport.peer_address() {
+            let admitted = state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .rate_limiter
+                .admit_connection(address);
+
+            if let Err(penalty) = admitted {
+                if let Some(response) = penalty.response() {
+                    let _ = transport.send_message(&response);
+                }
+                return Err(ServerStateError::Rejected(Rejection::RateLimited));
+            }
+        }
+
+        let handshake = ServerState::handshake(state, &mut transport)
+            .map_err(ServerStateError::HandshakeError)?;
+        let mut writer = transport
+            .try_clone()
+            .map_err(ServerStateError::MessageStreamError)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let joined = state.lock().unwrap_or_else(PoisonError::into_inner).join(
+            handshake.username().to_owned(),
+            handshake.device().map(str::to_owned),
+            transport.peer_address(),
+            sender,
+        );
+        let session_id = match joined {
+            Ok(session_id) => session_id,
+            // Someone else took the username while the handshake ran
+            Err(ServerStateError::UsernameError(err)) => {
+                let end = End::new(err.end_reason(), Some(err.to_string()));
+                let _ = transport.send_message(&end.to_message());
+                return Err(ServerStateError::UsernameError(err));
+            }
+            Err(err) => return Err(err),
+        };
+
+        // The channel closes when the session leaves, however it was ended
+        thread::spawn(move || {
+            for message in receiver {
+                if writer.send_message(&message).is_err() {
+                    break;
+                }
+            }
+            writer.shutdown();
+        });
+
+        let registry = CommandRegistry::with_server_builtins();
+        let result = loop {
+            let message = match transport.read_message() {
+                Ok(message) => message,
+                Err(err) => break Err(ServerStateError::MessageStreamError(err)),
+            };
+
+            let flow = dispatch(state, &registry, session_id, handshake.username(), message);
+            if flow.is_break() {
+                break Ok(());
+            }
+        };
+
+        state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .leave(session_id);
+
+        result
+    }
+
+    // Gone sessions are skipped, their connection is closing already
+    fn reply(&self, session_id: u64, message: Message) {
+        if let Some(session) = self.sessions.get(&session_id) {
+            let _ = session.sender.send(message);
+        }
+    }
+}
+
+// Commands lock the state per call, everything else is handled under one lock
+fn dispatch<'a>(
+    state: &'a Mutex<ServerState>,
+    registry: &CommandRegistry<CommandCaller<'a>>,
+    session_id: u64,
+    username: &str,
+    message: Message,
+) -> ControlFlow<()> {
+    let message = match message {
+        Message::Client(
+            client_message::Message::Authenticate(_)
+            | client_message::Message::ChallengeResponse(_),
+        )
+        | Message::Server(_) => {
+            let end = End::new(
+                EndReason::ProtocolMismatch,
+                Some(String::from("The handshake is over")),
+            );
+            state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reply(session_id, end.to_message());
+
+            return ControlFlow::Break(());
+        }
+        Message::Client(message) => message,
+    };
+
+    // Chats are limited by relay_chat, so a rejected one still gets its Ack
+    if !matches!(
+        message,
+        client_message::Message::Chat(_) | client_message::Message::End(_)
+    ) {
+        let limited = state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rate_limit(session_id, message.as_bytes().len())
+            .is_err();
+        if limited {
+            return ControlFlow::Continue(());
+        }
+    }
+
+    if let client_message::Message::Command(command) = &message {
+        let permissions = {
+            let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            state
+                .permissions
+                .permissions_of(&state.username_policy, username)
+        };
+
+        let mut caller = CommandCaller::new(state, session_id);
+        let command_result = registry.respond(&mut caller, permissions, command);
+        state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reply(session_id, command_result.to_message());
+
+        return ControlFlow::Continue(());
+    }
+
+    // Replies go out under the same lock, so an Ack directly follows the sender's own echo.
+    // The IRC gateway relies on that to hide the echo.
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+    let reply = match message {
+        client_message::Message::Chat(chat) => state
+            .relay_chat(session_id, chat)
+            .map(|ack| ack.to_message()),
+        client_message::Message::End(_) => return ControlFlow::Break(()),
+        client_message::Message::PublishKey(publish_key) => {
+            match state.publish_key(session_id, &publish_key) {
+                true => None,
+                false => Some(warning("Your other devices published a different key")),
+            }
+        }
+        client_message::Message::RequestKey(request_key) => {
+            Some(state.request_key(&request_key).to_message())
+        }
+        client_message::Message::EncryptedMessage(encrypted_message) => Some(
+            state
+                .relay_encrypted(username, encrypted_message)
+                .to_message(),
+        ),
+        client_message::Message::ReadReceipt(read_receipt) => {
+            state.relay_read_receipt(username, read_receipt);
+            None
+        }
+        client_message::Message::OfferFile(offer_file) => {
+            match state.offer_file(username, &offer_file) {
+                Ok(upload_ready) => Some(upload_ready.to_message()),
+                Err(err) => Some(warning(&err.to_string())),
+            }
+        }
+        client_message::Message::FileChunk(file_chunk) => state
+            .write_file_chunk(username, &file_chunk)
+            .err()
+            .map(|err| warning(&err.to_string())),
+        client_message::Message::RequestChunk(request_chunk) => {
+            match state.read_file_chunk(&request_chunk) {
+                Ok(file_chunk) => Some(file_chunk.to_message()),
+                Err(err) => Some(warning(&err.to_string())),
+            }
+        }
+        // Handled above
+        client_message::Message::Command(_)
+        | client_message::Message::Authenticate(_)
+        | client_message::Message::ChallengeResponse(_) => None,
+        moderation => state
+            .moderate(username, moderation)
+            .err()
+            .map(|err| warning(&err.to_string())),
+    };
+
+    if let Some(reply) = reply {
+        state.reply(session_id, reply);
+    }
+
+    ControlFlow::Continue(())
+}
+
+fn warning(text: &str) -> Message {
+    Warning::new(text.to_owned()).to_message()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        fs,
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    use crate::common::{
+        authorized_keys::AuthorizedKeys,
+        file_transfer::{FileDownload, FileStore, FileStoreConfig, FileUpload},
+        moderation::Moderation,
+        peer_credentials::PeerCredentials,
+        permissions::Permissions,
+        protocol::{
+            error::HandshakeError,
+            handshake::client::{Handshake, HandshakeArguments},
+            message::server as server_message,
+            packet::{
+                client::{self, Kick, RequestChunk, RequestKey},
+                server::{self, Ack, Attachment, CommandResult, PublicKey, UserJoined, UserLeft},
+                Compression,
+            },
+            username_policy::UsernamePolicy,
+        },
+        rate_limit::{RateLimit, RateLimitConfig, RateLimiter},
+    };
+
+    fn listen(state: ServerState) -> (Arc<Mutex<ServerState>>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err))
+            .to_string();
+
+        let state = Arc::new(Mutex::new(state));
+        let server_state = state.clone();
+        thread::spawn(move || ServerState::serve(&server_state, listener));
+
+        (state, address)
+    }
+
+    fn state() -> ServerState {
+        ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )
+    }
+
+    fn connect(address: &str, username: &str) -> MessageStream {
+        let mut message_stream = open(address);
+        let arguments =
+            HandshakeArguments::new(username.to_owned(), None, Compression::Deflate, None);
+        Handshake::perform(&mut message_stream, arguments)
+            .unwrap_or_else(|err| panic!("Failed to perform handshake: {}", err));
+
+        message_stream
+    }
+
+    fn open(address: &str) -> MessageStream {
+        let tcp_stream =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        tcp_stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap_or_else(|err| panic!("Failed to set timeout: {}", err));
+
+        MessageStream::new(tcp_stream)
+    }
+
+    fn send(message_stream: &mut MessageStream, packet: impl Packet) {
+        message_stream
+            .send_message(&packet.to_message())
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+    }
+
+    // Skips whatever else the session is told in the meantime
+    fn expect(message_stream: &mut MessageStream, packet: impl Packet) {
+        let expected = packet.to_message();
+
+        loop {
+            let message = message_stream
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read {}: {}", expected, err));
+            if message == expected {
+                return;
+            }
+        }
+    }
+
+    // Sessions join once their handshake is done on the server's side too
+    fn wait_for(state: &Mutex<ServerState>, username: &str) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .usernames()
+            .iter()
+            .any(|joined| joined == username)
+        {
+            assert!(Instant::now() < deadline, "{} never joined", username);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn serve_session_dispatches_client_packets() {
+        let (state, address) = listen(state());
+
+        let mut alice = connect(&address, "Alice");
+        wait_for(&state, "Alice");
+        let mut bob = connect(&address, "Bob");
+        expect(&mut alice, UserJoined::new(String::from("Bob")));
+
+        send(
+            &mut alice,
+            client::Chat::new(Some(7), String::from("Hi Bob")),
+        );
+        expect(&mut alice, Ack::new(7, None));
+        expect(
+            &mut bob,
+            server::Chat::new(String::from("Alice"), String::from("Hi Bob")),
+        );
+
+        send(
+            &mut bob,
+            client::Command::new(String::from("msg"), vec![String::from("Carol")]),
+        );
+        expect(
+            &mut bob,
+            CommandResult::new(false, String::from("Usage: /msg <user> <message>")),
+        );
+
+        send(&mut bob, RequestKey::new(String::from("Alice")));
+        expect(&mut bob, PublicKey::new(String::from("Alice"), None));
+
+        send(&mut bob, RequestChunk::new(1, 0, 1024));
+        expect(
+            &mut bob,
+            Warning::new(ServerStateError::FileSharingDisabled.to_string()),
+        );
+
+        send(&mut bob, Kick::new(String::from("Alice"), None));
+        expect(
+            &mut bob,
+            Warning::new(ServerStateError::Rejected(Rejection::NotPermitted).to_string()),
+        );
+
+        send(&mut alice, client::End::new(EndReason::Quit, None));
+        expect(&mut bob, UserLeft::new(String::from("Alice")));
+        assert_eq!(
+            state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .usernames(),
+            vec![String::from("Bob")]
+        );
+    }
+
+    #[test]
+    fn serve_session_rate_limits_connections_and_packets() {
+        let mut state = state();
+        state.set_rate_limiter(RateLimiter::new(RateLimitConfig {
+            session_messages: RateLimit::new(1, 1),
+            address_connections: RateLimit::new(1, 1),
+            ..RateLimitConfig::new()
+        }));
+        let (_, address) = listen(state);
+
+        let mut alice = connect(&address, "Alice");
+
+        // Turned away before the server reads the Authenticate
+        let mut bob = open(&address);
+        let rejected = Handshake::perform(
+            &mut bob,
+            HandshakeArguments::new(String::from("Bob"), None, Compression::None, None),
+        );
+        assert!(matches!(
+            rejected,
+            Err(HandshakeError::AuthenticationFailed(
+                EndReason::RateLimited,
+                _
+            ))
+        ));
+
+        send(&mut alice, RequestKey::new(String::from("Bob")));
+        expect(&mut alice, PublicKey::new(String::from("Bob"), None));
+        send(&mut alice, RequestKey::new(String::from("Bob")));
+        expect(
+            &mut alice,
+            Warning::new(String::from("You are sending messages too quickly")),
+        );
+    }
+
+    #[test]
+    fn serve_session_shares_files() {
+        let directory =
+            std::env::temp_dir().join(format!("rusty_chat_serve_files_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let mut state = state();
+        state.set_file_store(
+            FileStore::new(FileStoreConfig::new(directory.join("store")))
+                .unwrap_or_else(|err| panic!("Failed to create file store: {}", err)),
+        );
+        let (state, address) = listen(state);
+
+        let source = directory.join("notes.txt");
+        let content = "Standup moved to 10:30\n".repeat(20);
+        fs::write(&source, &content).unwrap_or_else(|err| panic!("Failed to write file: {}", err));
+        let upload = FileUpload::open(&source, "text/plain")
+            .unwrap_or_else(|err| panic!("Failed to open upload: {}", err));
+
+        let mut alice = connect(&address, "Alice");
+        wait_for(&state, "Alice");
+        let mut bob = connect(&address, "Bob");
+        wait_for(&state, "Bob");
+
+        send(&mut alice, upload.offer());
+        let upload_ready = loop {
+            match alice.read_message() {
+                Ok(Message::Server(server_message::Message::UploadReady(upload_ready))) => {
+                    break upload_ready
+                }
+                Ok(_) => continue,
+                Err(err) => panic!("Failed to read UploadReady: {}", err),
+            }
+        };
+        for chunk in upload
+            .chunks(&upload_ready)
+            .unwrap_or_else(|err| panic!("Failed to read chunks: {}", err))
+        {
+            send(
+                &mut alice,
+                chunk.unwrap_or_else(|err| panic!("Failed to read chunk: {}", err)),
+            );
+        }
+
+        let attachment = Attachment::new(
+            String::from("Alice"),
+            upload_ready.file_id,
+            upload.info().clone(),
+        );
+        expect(&mut bob, attachment.clone());
+
+        let target = directory.join("download.txt");
+        let mut download = FileDownload::create(attachment, &target)
+            .unwrap_or_else(|err| panic!("Failed to create download: {}", err));
+        while let Some(request_chunk) = download.next_request() {
+            send(&mut bob, request_chunk);
+            let file_chunk = loop {
+                match bob.read_message() {
+                    Ok(Message::Server(server_message::Message::FileChunk(file_chunk))) => {
+                        break file_chunk
+                    }
+                    Ok(_) => continue,
+                    Err(err) => panic!("Failed to read FileChunk: {}", err),
+                }
+            };
+            download
+                .write_chunk(&file_chunk)
+                .unwrap_or_else(|err| panic!("Failed to write chunk: {}", err));
+        }
+        assert_eq!(fs::read_to_string(&target).ok(), Some(content));
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
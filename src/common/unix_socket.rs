@@ -0,0 +1,110 @@
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::common::{
+    message_stream::{error::MessageStreamError, MessageStream},
+    server_state::ServerState,
+};
+
+// Accepts local clients on a socket path. Whoever may write to the socket file may connect,
+// so the mode decides which local users get in, e.g. 0o660 for the server's group.
+#[derive(Debug)]
+pub struct UnixSocketListener {
+    unix_listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocketListener {
+    pub fn bind(path: &Path, mode: u32) -> io::Result<UnixSocketListener> {
+        remove_stale_socket(path)?;
+
+        // Bind inside a private directory and move the socket into place once its mode is set,
+        // so it is never reachable with the umask's permissions
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Socket path has no file name")
+        })?;
+        let staging_directory = path.with_file_name(format!(
+            ".{}.{}",
+            file_name.to_string_lossy(),
+            process::id()
+        ));
+        DirBuilder::new().mode(0o700).create(&staging_directory)?;
+
+        let staged_path = staging_directory.join(file_name);
+        let bound = UnixListener::bind(&staged_path).and_then(|unix_listener| {
+            fs::set_permissions(&staged_path, Permissions::from_mode(mode))?;
+            fs::rename(&staged_path, path)?;
+            Ok(unix_listener)
+        });
+        let _ = fs::remove_file(&staged_path);
+        let _ = fs::remove_dir(&staging_directory);
+
+        Ok(UnixSocketListener {
+            unix_listener: bound?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn accept(&self) -> Result<MessageStream, MessageStreamError> {
+        let (unix_stream, _) = self
+            .unix_listener
+            .accept()
+            .map_err(MessageStreamError::IoError)?;
+
+        Ok(MessageStream::new(unix_stream))
+    }
+
+    // Serves every local client on its own thread until accepting fails
+    pub fn serve(&self, state: &Arc<Mutex<ServerState>>) -> Result<(), MessageStreamError> {
+        loop {
+            let message_stream = self.accept()?;
+            let state = state.clone();
+
+            thread::spawn(move || ServerState::serve_session(&state, message_stream));
+        }
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// A socket file nobody listens on is left over from a crash, a live one means another server
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Socket path exists and is not a socket",
+        ));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "Another server is listening on the socket path",
+        ));
+    }
+
+    fs::remove_file(path)
+}
@@ -0,0 +1,607 @@
+pub mod error;
+
+use std::{
+    io::BufReader,
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde_json::json;
+use sha2::Sha256;
+
+use self::error::WebhookError;
+
+use crate::common::{
+    http::{HttpRequest, HttpResponse},
+    moderation::unix_time,
+    protocol::packet::server::Chat,
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Rusty-Chat-Signature";
+pub const EVENT_HEADER: &str = "X-Rusty-Chat-Event";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WebhookEvent {
+    Chat(Chat),
+    UserJoined(String),
+    UserLeft(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum WebhookTrigger {
+    MessageMatches(Regex),
+    // Matches the keyword as a whole word, with or without a leading @, ignoring case
+    Mention(String),
+    UserJoined,
+    UserLeft,
+}
+
+impl WebhookTrigger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookTrigger::MessageMatches(_) => "message",
+            WebhookTrigger::Mention(_) => "mention",
+            WebhookTrigger::UserJoined => "user_joined",
+            WebhookTrigger::UserLeft => "user_left",
+        }
+    }
+
+    pub fn matches(&self, event: &WebhookEvent) -> bool {
+        match (self, event) {
+            (WebhookTrigger::MessageMatches(pattern), WebhookEvent::Chat(chat)) => {
+                pattern.is_match(&chat.message)
+            }
+            (WebhookTrigger::Mention(keyword), WebhookEvent::Chat(chat)) => {
+                let keyword = keyword.trim_start_matches('@').to_lowercase();
+                chat.message
+                    .split(|character: char| {
+                        !(character.is_alphanumeric() || "_-.".contains(character))
+                    })
+                    .any(|word| word.trim_end_matches('.').to_lowercase() == keyword)
+            }
+            (WebhookTrigger::UserJoined, WebhookEvent::UserJoined(_))
+            | (WebhookTrigger::UserLeft, WebhookEvent::UserLeft(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Receivers check the HMAC-SHA256 of the body in the signature header against this
+    pub secret: String,
+    pub triggers: Vec<WebhookTrigger>,
+}
+
+impl WebhookConfig {
+    pub fn new(url: String, secret: String, triggers: Vec<WebhookTrigger>) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            secret,
+            triggers,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WebhookDeliveryConfig {
+    pub queue_capacity: usize,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl WebhookDeliveryConfig {
+    pub fn new() -> WebhookDeliveryConfig {
+        WebhookDeliveryConfig {
+            queue_capacity: 256,
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for WebhookDeliveryConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WebhookMetrics {
+    pub queued: u64,
+    pub delivered: u64,
+    pub retried: u64,
+    pub failed: u64,
+    pub dropped: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicU64,
+    delivered: AtomicU64,
+    retried: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+#[derive(Clone, Debug)]
+struct Webhook {
+    url: WebhookUrl,
+    secret: String,
+    triggers: Vec<WebhookTrigger>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct WebhookUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl WebhookUrl {
+    fn parse(url: &str) -> Result<WebhookUrl, WebhookError> {
+        let invalid = || WebhookError::InvalidUrl(url.to_owned());
+
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(WebhookUrl {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Delivery {
+    url: WebhookUrl,
+    event: &'static str,
+    body: Vec<u8>,
+    signature: String,
+}
+
+// Queues deliveries for a single worker thread. The queue is bounded and never waited on,
+// so a slow receiver costs dropped deliveries instead of delaying the relay.
+// ServerState dispatches once the plugins are done, so only relayed chats are sent.
+#[derive(Debug)]
+pub struct WebhookDispatcher {
+    webhooks: Vec<Webhook>,
+    sender: SyncSender<Delivery>,
+    counters: Arc<Counters>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        webhooks: Vec<WebhookConfig>,
+        delivery_config: WebhookDeliveryConfig,
+    ) -> Result<WebhookDispatcher, WebhookError> {
+        let webhooks = webhooks
+            .into_iter()
+            .map(|webhook| {
+                Ok(Webhook {
+                    url: WebhookUrl::parse(&webhook.url)?,
+                    secret: webhook.secret,
+                    triggers: webhook.triggers,
+                })
+            })
+            .collect::<Result<Vec<_>, WebhookError>>()?;
+
+        let (sender, receiver) = mpsc::sync_channel(delivery_config.queue_capacity);
+        let counters = Arc::new(Counters::default());
+
+        let worker_counters = Arc::clone(&counters);
+        thread::spawn(move || deliver_all(receiver, delivery_config, &worker_counters));
+
+        Ok(WebhookDispatcher {
+            webhooks,
+            sender,
+            counters,
+        })
+    }
+
+    // Returns the number of deliveries queued for the event
+    pub fn dispatch(&self, event: &WebhookEvent) -> usize {
+        let mut queued = 0;
+
+        for webhook in &self.webhooks {
+            let trigger = match webhook
+                .triggers
+                .iter()
+                .find(|trigger| trigger.matches(event))
+            {
+                Some(trigger) => trigger,
+                None => continue,
+            };
+
+            let body = payload(trigger.name(), event);
+            let delivery = Delivery {
+                url: webhook.url.clone(),
+                event: trigger.name(),
+                signature: sign(&webhook.secret, &body),
+                body,
+            };
+
+            match self.sender.try_send(delivery) {
+                Ok(()) => {
+                    queued += 1;
+                    self.counters.queued.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        queued
+    }
+
+    pub fn metrics(&self) -> WebhookMetrics {
+        WebhookMetrics {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            delivered: self.counters.delivered.load(Ordering::Relaxed),
+            retried: self.counters.retried.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn payload(event_name: &str, event: &WebhookEvent) -> Vec<u8> {
+    let payload = match event {
+        WebhookEvent::Chat(chat) => json!({
+            "event": event_name,
+            "username": chat.username,
+            "message": chat.message,
+            "timestamp": unix_time(),
+        }),
+        WebhookEvent::UserJoined(username) | WebhookEvent::UserLeft(username) => json!({
+            "event": event_name,
+            "username": username,
+            "timestamp": unix_time(),
+        }),
+    };
+
+    payload.to_string().into_bytes()
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(body);
+
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("sha256={}", signature)
+}
+
+fn deliver_all(
+    receiver: Receiver<Delivery>,
+    delivery_config: WebhookDeliveryConfig,
+    counters: &Counters,
+) {
+    // Ends once the dispatcher and with it the sender is dropped
+    for delivery in receiver {
+        for attempt in 0..delivery_config.max_attempts {
+            if attempt > 0 {
+                counters.retried.fetch_add(1, Ordering::Relaxed);
+                thread::sleep(delivery_config.backoff(attempt - 1));
+            }
+
+            if post(&delivery, delivery_config.timeout).is_ok() {
+                counters.delivered.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
+
+            if attempt + 1 == delivery_config.max_attempts {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+fn post(delivery: &Delivery, timeout: Duration) -> Result<(), WebhookError> {
+    let url = &delivery.url;
+    let address = (url.host.as_str(), url.port)
+        .to_socket_addrs()
+        .map_err(WebhookError::IoError)?
+        .next()
+        .ok_or_else(|| WebhookError::InvalidUrl(url.host.clone()))?;
+
+    let mut tcp_stream =
+        TcpStream::connect_timeout(&address, timeout).map_err(WebhookError::IoError)?;
+    tcp_stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| tcp_stream.set_write_timeout(Some(timeout)))
+        .map_err(WebhookError::IoError)?;
+
+    let request = HttpRequest::new(
+        "POST",
+        &url.path,
+        vec![
+            (
+                String::from("Content-Type"),
+                String::from("application/json"),
+            ),
+            (String::from(EVENT_HEADER), String::from(delivery.event)),
+            (String::from(SIGNATURE_HEADER), delivery.signature.clone()),
+        ],
+        delivery.body.clone(),
+    );
+    request
+        .write_to(&mut tcp_stream, &format!("{}:{}", url.host, url.port))
+        .map_err(WebhookError::HttpError)?;
+
+    let response = HttpResponse::read_from(&mut BufReader::new(tcp_stream))
+        .map_err(WebhookError::HttpError)?;
+    if !response.is_success() {
+        return Err(WebhookError::UnexpectedStatus(response.status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{net::TcpListener, time::Instant};
+
+    use crate::common::{
+        authorized_keys::AuthorizedKeys,
+        moderation::Moderation,
+        peer_credentials::PeerCredentials,
+        permissions::Permissions,
+        plugin::{ChatVerdict, Plugin, PluginContext},
+        protocol::{
+            packet::{client, server::Ack, Packet, Rejection},
+            username_policy::UsernamePolicy,
+        },
+        server_state::ServerState,
+    };
+
+    struct SpamFilter;
+
+    impl Plugin for SpamFilter {
+        fn name(&self) -> &str {
+            "spam_filter"
+        }
+
+        fn on_chat(&mut self, _context: &mut PluginContext, chat: &mut Chat) -> ChatVerdict {
+            match chat.message.contains("spam") {
+                true => ChatVerdict::Drop,
+                false => ChatVerdict::Relay,
+            }
+        }
+    }
+
+    fn chat(message: &str) -> WebhookEvent {
+        WebhookEvent::Chat(Chat::new(String::from("Alice"), String::from(message)))
+    }
+
+    #[test]
+    fn webhook_triggers_match_events() {
+        let pattern = WebhookTrigger::MessageMatches(
+            Regex::new(r"^deploy \w+$").unwrap_or_else(|err| panic!("Invalid regex: {}", err)),
+        );
+        let mention = WebhookTrigger::Mention(String::from("@oncall"));
+
+        assert!(pattern.matches(&chat("deploy staging")));
+        assert!(!pattern.matches(&chat("please deploy staging")));
+        assert!(mention.matches(&chat("ping @OnCall, the build broke.")));
+        assert!(!mention.matches(&chat("oncallers are asleep")));
+        assert!(WebhookTrigger::UserJoined.matches(&WebhookEvent::UserJoined(String::from("Bob"))));
+        assert!(!WebhookTrigger::UserJoined.matches(&WebhookEvent::UserLeft(String::from("Bob"))));
+    }
+
+    #[test]
+    fn webhook_signature_is_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn webhook_dispatcher_retries_until_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        // Fails the first delivery attempt, accepts the second
+        let receiver = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in [503, 204] {
+                let (mut tcp_stream, _) = listener
+                    .accept()
+                    .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+                let request = HttpRequest::read_from(&mut BufReader::new(
+                    tcp_stream
+                        .try_clone()
+                        .unwrap_or_else(|err| panic!("Failed to clone stream: {}", err)),
+                ))
+                .unwrap_or_else(|err| panic!("Failed to read request: {}", err));
+                HttpResponse::new(status, Vec::new(), Vec::new())
+                    .write_to(&mut tcp_stream)
+                    .unwrap_or_else(|err| panic!("Failed to write response: {}", err));
+                requests.push(request);
+            }
+            requests
+        });
+
+        let mut delivery_config = WebhookDeliveryConfig::new();
+        delivery_config.initial_backoff = Duration::from_millis(10);
+        let dispatcher = WebhookDispatcher::new(
+            vec![WebhookConfig::new(
+                format!("http://{}/hooks/chat", address),
+                String::from("secret"),
+                vec![WebhookTrigger::Mention(String::from("oncall"))],
+            )],
+            delivery_config,
+        )
+        .unwrap_or_else(|err| panic!("Failed to create dispatcher: {}", err));
+
+        assert_eq!(dispatcher.dispatch(&chat("Hello")), 0);
+        assert_eq!(dispatcher.dispatch(&chat("@oncall help")), 1);
+
+        let requests = receiver
+            .join()
+            .unwrap_or_else(|_| panic!("Receiver thread panicked"));
+        let request = &requests[1];
+        assert_eq!(request.path, "/hooks/chat");
+        assert_eq!(request.header(EVENT_HEADER), Some("mention"));
+        assert_eq!(
+            request.header(SIGNATURE_HEADER),
+            Some(sign("secret", &request.body).as_str())
+        );
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body)
+            .unwrap_or_else(|err| panic!("Failed to parse payload: {}", err));
+        assert_eq!(body["message"], "@oncall help");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while dispatcher.metrics().delivered == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let metrics = dispatcher.metrics();
+        assert_eq!(
+            (metrics.queued, metrics.delivered, metrics.retried),
+            (1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn webhook_fires_from_relayed_chat_without_stalling_it() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        // Holds on to the delivery without answering until the test is done
+        let (requests, received) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let (mut tcp_stream, _) = listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            let request = HttpRequest::read_from(&mut BufReader::new(
+                tcp_stream
+                    .try_clone()
+                    .unwrap_or_else(|err| panic!("Failed to clone stream: {}", err)),
+            ))
+            .unwrap_or_else(|err| panic!("Failed to read request: {}", err));
+            let _ = requests.send(request);
+            let _ = released.recv();
+            let _ = HttpResponse::new(204, Vec::new(), Vec::new()).write_to(&mut tcp_stream);
+        });
+
+        let dispatcher = WebhookDispatcher::new(
+            vec![WebhookConfig::new(
+                format!("http://{}/hooks/chat", address),
+                String::from("secret"),
+                vec![WebhookTrigger::Mention(String::from("oncall"))],
+            )],
+            WebhookDeliveryConfig::new(),
+        )
+        .unwrap_or_else(|err| panic!("Failed to create dispatcher: {}", err));
+        let mut state = ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        );
+        state.set_webhooks(dispatcher);
+        state.plugins_mut().register(Box::new(SpamFilter));
+
+        let (sender, alice) = mpsc::channel();
+        let alice_id = state
+            .join(String::from("Alice"), None, None, sender)
+            .unwrap_or_else(|err| panic!("Failed to join: {}", err));
+
+        // Dropped by the plugin, so the receiver never hears of it
+        assert_eq!(
+            state.relay_chat(
+                alice_id,
+                client::Chat::new(Some(1), String::from("@oncall buy spam"))
+            ),
+            Some(Ack::new(1, Some(Rejection::Filtered)))
+        );
+
+        for message in ["@oncall the build broke", "anyone?"] {
+            assert_eq!(
+                state.relay_chat(alice_id, client::Chat::new(Some(1), String::from(message))),
+                Some(Ack::new(1, None))
+            );
+            assert_eq!(
+                alice.try_recv().ok(),
+                Some(Chat::new(String::from("Alice"), String::from(message)).to_message())
+            );
+        }
+
+        let request = received
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap_or_else(|err| panic!("Webhook was not delivered: {}", err));
+        let body: serde_json::Value = serde_json::from_slice(&request.body)
+            .unwrap_or_else(|err| panic!("Failed to parse payload: {}", err));
+        assert_eq!(request.header(EVENT_HEADER), Some("mention"));
+        assert_eq!(body["username"], "Alice");
+        assert_eq!(body["message"], "@oncall the build broke");
+
+        let _ = release.send(());
+    }
+
+    #[test]
+    fn webhook_url_must_be_http() {
+        assert!(WebhookUrl::parse("https://example.com/hook").is_err());
+        assert_eq!(
+            WebhookUrl::parse("http://example.com")
+                .unwrap_or_else(|err| panic!("Failed to parse URL: {}", err)),
+            WebhookUrl {
+                host: String::from("example.com"),
+                port: 80,
+                path: String::from("/"),
+            }
+        );
+    }
+}
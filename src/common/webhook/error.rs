@@ -0,0 +1,28 @@
+use std::fmt::Display;
+
+use crate::common::http::error::HttpError;
+
+#[derive(Debug)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    IoError(std::io::Error),
+    HttpError(HttpError),
+    UnexpectedStatus(u16),
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::InvalidUrl(url) => {
+                write!(f, "Invalid webhook URL {}, only http:// is supported", url)
+            }
+            WebhookError::IoError(err) => write!(f, "IoError while delivering webhook: {}", err),
+            WebhookError::HttpError(err) => {
+                write!(f, "HTTP error while delivering webhook: {}", err)
+            }
+            WebhookError::UnexpectedStatus(status) => {
+                write!(f, "Webhook receiver answered with status {}", status)
+            }
+        }
+    }
+}
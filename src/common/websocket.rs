@@ -0,0 +1,447 @@
+use std::{
+    io,
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue, Uri},
+    protocol::{Role, WebSocketConfig},
+    ClientRequestBuilder, WebSocket,
+};
+
+#[cfg(feature = "json")]
+use crate::common::protocol::codec::JsonCodec;
+use crate::common::{
+    message_stream::{
+        error::MessageStreamError, MessageTransport, SplitTransport, MAX_MESSAGE_SIZE,
+    },
+    protocol::{
+        codec::{BinaryCodec, Codec},
+        message::Message,
+    },
+    server_state::{error::ServerStateError, ServerState},
+};
+
+// A client that stalls the upgrade is dropped after this long
+pub const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Browsers pick the encoding through the WebSocket subprotocol, without one they get Binary
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WebSocketEncoding {
+    Binary,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl WebSocketEncoding {
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            WebSocketEncoding::Binary => "rusty-chat",
+            #[cfg(feature = "json")]
+            WebSocketEncoding::Json => "rusty-chat.json",
+        }
+    }
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<WebSocketEncoding> {
+        match subprotocol.trim() {
+            "rusty-chat" => Some(WebSocketEncoding::Binary),
+            #[cfg(feature = "json")]
+            "rusty-chat.json" => Some(WebSocketEncoding::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WebSocketListener {
+    tcp_listener: TcpListener,
+}
+
+impl WebSocketListener {
+    pub fn new(tcp_listener: TcpListener) -> WebSocketListener {
+        WebSocketListener { tcp_listener }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp_listener.local_addr()
+    }
+
+    // Only accepts the connection, so a slow upgrade never holds up the next client.
+    // WebSocketStream::accept upgrades it on the connection's own thread.
+    pub fn accept(&self) -> Result<TcpStream, MessageStreamError> {
+        let (tcp_stream, _) = self
+            .tcp_listener
+            .accept()
+            .map_err(MessageStreamError::IoError)?;
+
+        Ok(tcp_stream)
+    }
+
+    // Serves every browser on its own thread until accepting fails
+    pub fn serve(&self, state: &Arc<Mutex<ServerState>>) -> Result<(), MessageStreamError> {
+        loop {
+            let tcp_stream = self.accept()?;
+            let state = state.clone();
+
+            thread::spawn(move || {
+                let websocket_stream = WebSocketStream::accept(tcp_stream)
+                    .map_err(ServerStateError::MessageStreamError)?;
+                ServerState::serve_session(&state, websocket_stream)
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WebSocketStream {
+    websocket: WebSocket<TcpStream>,
+    encoding: WebSocketEncoding,
+    role: Role,
+}
+
+impl WebSocketStream {
+    pub fn accept(tcp_stream: TcpStream) -> Result<WebSocketStream, MessageStreamError> {
+        let mut encoding = WebSocketEncoding::Binary;
+
+        // The callback signature is dictated by tungstenite
+        #[allow(clippy::result_large_err)]
+        let negotiate = |request: &Request, mut response: Response| {
+            let requested = request
+                .headers()
+                .get_all(SEC_WEBSOCKET_PROTOCOL)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .find_map(WebSocketEncoding::from_subprotocol);
+
+            if let Some(requested) = requested {
+                encoding = requested;
+                response.headers_mut().insert(
+                    SEC_WEBSOCKET_PROTOCOL,
+                    HeaderValue::from_static(requested.subprotocol()),
+                );
+            }
+
+            Ok::<Response, ErrorResponse>(response)
+        };
+
+        tcp_stream
+            .set_read_timeout(Some(UPGRADE_TIMEOUT))
+            .map_err(MessageStreamError::IoError)?;
+        let websocket = tungstenite::accept_hdr_with_config(tcp_stream, negotiate, Some(config()))
+            .map_err(|err| MessageStreamError::WebSocketError(err.to_string()))?;
+        // Sessions wait for their clients as long as it takes
+        websocket
+            .get_ref()
+            .set_read_timeout(None)
+            .map_err(MessageStreamError::IoError)?;
+
+        Ok(WebSocketStream {
+            websocket,
+            encoding,
+            role: Role::Server,
+        })
+    }
+
+    // For Rust clients and tests, browsers bring their own WebSocket implementation
+    pub fn connect(
+        tcp_stream: TcpStream,
+        url: &str,
+        encoding: WebSocketEncoding,
+    ) -> Result<WebSocketStream, MessageStreamError> {
+        let uri = url
+            .parse::<Uri>()
+            .map_err(|err| MessageStreamError::WebSocketError(err.to_string()))?;
+        let request = ClientRequestBuilder::new(uri).with_sub_protocol(encoding.subprotocol());
+
+        let (websocket, _) =
+            tungstenite::client::client_with_config(request, tcp_stream, Some(config()))
+                .map_err(|err| MessageStreamError::WebSocketError(err.to_string()))?;
+
+        Ok(WebSocketStream {
+            websocket,
+            encoding,
+            role: Role::Client,
+        })
+    }
+
+    pub fn encoding(&self) -> WebSocketEncoding {
+        self.encoding
+    }
+}
+
+impl MessageTransport for WebSocketStream {
+    fn read_message(&mut self) -> Result<Message, MessageStreamError> {
+        loop {
+            let frame = self.websocket.read().map_err(websocket_error)?;
+
+            return match frame {
+                tungstenite::Message::Binary(bytes) => BinaryCodec
+                    .decode(&bytes)
+                    .map_err(MessageStreamError::CodecError),
+                #[cfg(feature = "json")]
+                tungstenite::Message::Text(text) => JsonCodec
+                    .decode(text.as_bytes())
+                    .map_err(MessageStreamError::CodecError),
+                #[cfg(not(feature = "json"))]
+                tungstenite::Message::Text(_) => Err(MessageStreamError::WebSocketError(
+                    String::from("Text frames require the json feature"),
+                )),
+                tungstenite::Message::Close(_) => Err(MessageStreamError::IoError(
+                    io::Error::from(io::ErrorKind::ConnectionAborted),
+                )),
+                // Pings are answered by tungstenite itself
+                _ => continue,
+            };
+        }
+    }
+
+    fn send_message(&mut self, message: &Message) -> Result<(), MessageStreamError> {
+        let frame = match self.encoding {
+            WebSocketEncoding::Binary => {
+                let bytes = BinaryCodec
+                    .encode(message)
+                    .map_err(MessageStreamError::CodecError)?;
+                tungstenite::Message::binary(bytes)
+            }
+            #[cfg(feature = "json")]
+            WebSocketEncoding::Json => {
+                let bytes = JsonCodec
+                    .encode(message)
+                    .map_err(MessageStreamError::CodecError)?;
+                let text = String::from_utf8(bytes)
+                    .map_err(|err| MessageStreamError::WebSocketError(err.to_string()))?;
+                tungstenite::Message::text(text.trim_end())
+            }
+        };
+
+        self.websocket.send(frame).map_err(websocket_error)
+    }
+
+    fn peer_address(&self) -> Option<IpAddr> {
+        self.websocket
+            .get_ref()
+            .peer_addr()
+            .ok()
+            .map(|address| address.ip())
+    }
+}
+
+// The clone picks up after the upgrade and only ever sends. The original keeps reading and
+// answers pings and closes with whole frames of its own.
+impl SplitTransport for WebSocketStream {
+    fn try_clone(&self) -> Result<WebSocketStream, MessageStreamError> {
+        let tcp_stream = self
+            .websocket
+            .get_ref()
+            .try_clone()
+            .map_err(MessageStreamError::IoError)?;
+
+        Ok(WebSocketStream {
+            websocket: WebSocket::from_raw_socket(tcp_stream, self.role, Some(config())),
+            encoding: self.encoding,
+            role: self.role,
+        })
+    }
+
+    fn shutdown(&self) {
+        let _ = self.websocket.get_ref().shutdown(Shutdown::Both);
+    }
+}
+
+fn config() -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_SIZE))
+        .max_frame_size(Some(MAX_MESSAGE_SIZE))
+}
+
+// Lost connections surface as IoError, just like on a MessageStream
+fn websocket_error(err: tungstenite::Error) -> MessageStreamError {
+    match err {
+        tungstenite::Error::Io(err) => MessageStreamError::IoError(err),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            MessageStreamError::IoError(io::Error::from(io::ErrorKind::ConnectionAborted))
+        }
+        tungstenite::Error::Capacity(_) => MessageStreamError::MessageTooLarge(MAX_MESSAGE_SIZE),
+        err => MessageStreamError::WebSocketError(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::common::{
+        authorized_keys::AuthorizedKeys,
+        message_stream::MessageStream,
+        moderation::Moderation,
+        peer_credentials::PeerCredentials,
+        permissions::Permissions,
+        protocol::{
+            error::HandshakeError,
+            handshake::client as client_handshake,
+            packet::{client, server, Compression, EndReason, Packet},
+            username_policy::UsernamePolicy,
+        },
+    };
+
+    fn listen() -> (WebSocketListener, String) {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let listener = WebSocketListener::new(tcp_listener);
+        let address = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+
+        (listener, address.to_string())
+    }
+
+    fn connect(address: &str, encoding: WebSocketEncoding) -> WebSocketStream {
+        let tcp_stream =
+            TcpStream::connect(address).unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+
+        WebSocketStream::connect(tcp_stream, &format!("ws://{}/", address), encoding)
+            .unwrap_or_else(|err| panic!("Failed to open WebSocket: {}", err))
+    }
+
+    fn chat(transport: &mut impl MessageTransport, username: &str) -> Message {
+        client_handshake::Handshake::perform(
+            transport,
+            client_handshake::HandshakeArguments::new(
+                username.to_owned(),
+                None,
+                Compression::Deflate,
+                None,
+            ),
+        )
+        .unwrap_or_else(|err| panic!("Failed to perform handshake: {}", err));
+
+        transport
+            .send_message(&client::Chat::new(None, String::from("Hello")).to_message())
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+        transport
+            .read_message()
+            .unwrap_or_else(|err| panic!("Failed to read message: {}", err))
+    }
+
+    fn state() -> Arc<Mutex<ServerState>> {
+        Arc::new(Mutex::new(ServerState::new(
+            UsernamePolicy::new(),
+            Moderation::new(),
+            Permissions::new(Vec::new()),
+            AuthorizedKeys::new(),
+            PeerCredentials::new(),
+        )))
+    }
+
+    fn expect(transport: &mut impl MessageTransport, packet: impl Packet) {
+        let expected = packet.to_message();
+
+        loop {
+            let message = transport
+                .read_message()
+                .unwrap_or_else(|err| panic!("Failed to read {}: {}", expected, err));
+            if message == expected {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn websocket_and_tcp_clients_share_one_server_state() {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|err| panic!("Failed to bind listener: {}", err));
+        let tcp_address = tcp_listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("Failed to get address: {}", err));
+        let (websocket_listener, websocket_address) = listen();
+
+        let state = state();
+        let tcp_state = state.clone();
+        thread::spawn(move || ServerState::serve(&tcp_state, tcp_listener));
+        let websocket_state = state.clone();
+        thread::spawn(move || websocket_listener.serve(&websocket_state));
+
+        let tcp_stream = TcpStream::connect(tcp_address)
+            .unwrap_or_else(|err| panic!("Failed to connect: {}", err));
+        let mut alice = MessageStream::new(tcp_stream);
+        let reply = chat(&mut alice, "Alice");
+        assert_eq!(
+            reply,
+            server::Chat::new(String::from("Alice"), String::from("Hello")).to_message()
+        );
+
+        let mut websocket_stream = connect(&websocket_address, WebSocketEncoding::Binary);
+        let rejected = client_handshake::Handshake::perform(
+            &mut websocket_stream,
+            client_handshake::HandshakeArguments::new(
+                String::from("alice"),
+                None,
+                Compression::None,
+                None,
+            ),
+        );
+        assert!(matches!(
+            rejected,
+            Err(HandshakeError::AuthenticationFailed(
+                EndReason::UsernameTaken,
+                _
+            ))
+        ));
+
+        let mut bob = connect(&websocket_address, WebSocketEncoding::Binary);
+        let reply = chat(&mut bob, "Bob");
+        assert_eq!(
+            reply,
+            server::Chat::new(String::from("Bob"), String::from("Hello")).to_message()
+        );
+        expect(
+            &mut alice,
+            server::Chat::new(String::from("Bob"), String::from("Hello")),
+        );
+
+        alice
+            .send_message(&client::Chat::new(None, String::from("Hi Bob")).to_message())
+            .unwrap_or_else(|err| panic!("Failed to send message: {}", err));
+        expect(
+            &mut bob,
+            server::Chat::new(String::from("Alice"), String::from("Hi Bob")),
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn websocket_negotiates_json_text_frames() {
+        let (websocket_listener, websocket_address) = listen();
+
+        let state = state();
+        let server = thread::spawn(move || {
+            let tcp_stream = websocket_listener
+                .accept()
+                .unwrap_or_else(|err| panic!("Failed to accept: {}", err));
+            let websocket_stream = WebSocketStream::accept(tcp_stream)
+                .unwrap_or_else(|err| panic!("Failed to upgrade: {}", err));
+            assert_eq!(websocket_stream.encoding(), WebSocketEncoding::Json);
+
+            let _ = ServerState::serve_session(&state, websocket_stream);
+        });
+
+        let mut websocket_stream = connect(&websocket_address, WebSocketEncoding::Json);
+        let reply = chat(&mut websocket_stream, "Carol");
+        assert_eq!(
+            reply,
+            server::Chat::new(String::from("Carol"), String::from("Hello")).to_message()
+        );
+        drop(websocket_stream);
+
+        server
+            .join()
+            .unwrap_or_else(|_| panic!("Server thread panicked"));
+    }
+}